@@ -1,52 +1,284 @@
+use std::fs;
 use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use chess::book::{Book, BookBuilder};
+use chess::engine::transposition::{self, TranspositionTable};
+use chess::eval;
+use chess::game::chess960;
 use chess::game::state::State;
+use chess::game::variant::{self, Standard, Variant};
 use chess::game::Game;
 use chess::parsing;
-use clap::{App, Arg, SubCommand};
+use chess::parsing::pgn::Pgn;
+use chess::protocol::{uci, xboard};
+use chess::tablebase::Tablebase;
+use clap::{App, AppSettings, Arg, SubCommand};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 fn main() {
-    let matches = App::new("chess")
-        .version(VERSION)
-        .version_short("v")
-        .about("do some chess stuff on the command line")
-        .arg(
-            Arg::with_name("initial")
-                .short("i")
-                .long("initial")
-                .takes_value(true)
-                .help("Initial position in Forsyth-Edwards notation"),
-        )
-        .arg(
-            Arg::with_name("moves")
-                .short("m")
-                .long("moves")
-                .takes_value(true)
-                .help("Moves to play in algebraic chess notation"),
-        )
-        .subcommand(SubCommand::with_name("play").about("play moves interactively"))
-        .get_matches();
-
-    let mut game = setup_game(matches.value_of("initial"));
-    let new_state = play_moves(game.state, matches.value_of("moves"));
-    game = Game { state: new_state };
-
-    if matches.subcommand_matches("play").is_some() {
-        play(game);
+    let matches =
+        App::new("chess")
+            .version(VERSION)
+            .version_short("v")
+            .about("do some chess stuff on the command line")
+            .arg(
+                Arg::with_name("initial")
+                    .short("i")
+                    .long("initial")
+                    .takes_value(true)
+                    .help("Initial position in Forsyth-Edwards notation"),
+            )
+            .arg(
+                Arg::with_name("moves")
+                    .short("m")
+                    .long("moves")
+                    .takes_value(true)
+                    .help("Moves to play in algebraic chess notation"),
+            )
+            .arg(
+                Arg::with_name("pgn")
+                    .short("p")
+                    .long("pgn")
+                    .takes_value(true)
+                    .conflicts_with("initial")
+                    .help("Load the mainline of a game in Portable Game Notation from a file"),
+            )
+            .arg(
+                Arg::with_name("fen")
+                    .short("f")
+                    .long("fen")
+                    .help("Print the resulting position in Forsyth-Edwards notation"),
+            )
+            .arg(
+                Arg::with_name("variant")
+                    .long("variant")
+                    .takes_value(true)
+                    .possible_values(&[
+                        "standard",
+                        "chess960",
+                        "crazyhouse",
+                        "atomic",
+                        "antichess",
+                        "threecheck",
+                        "kingofthehill",
+                    ])
+                    .default_value("standard")
+                    .help("Rules to play by"),
+            )
+            .arg(
+                Arg::with_name("start-index")
+                    .long("start-index")
+                    .takes_value(true)
+                    .conflicts_with_all(&["initial", "pgn"])
+                    .help("Chess960 start position from 0 to 959, random if not given"),
+            )
+            .subcommand(
+                SubCommand::with_name("play")
+                    .about("play moves interactively")
+                    .arg(
+                        Arg::with_name("save-pgn")
+                            .long("save-pgn")
+                            .takes_value(true)
+                            .value_name("file")
+                            .help("Save the game in Portable Game Notation when it ends"),
+                    ),
+            )
+            .subcommand(SubCommand::with_name("eval").about("evaluate the position, term by term"))
+            .subcommand(
+                SubCommand::with_name("perft")
+                    .about("count the positions reachable in a number of moves")
+                    .arg(
+                        Arg::with_name("depth")
+                            .short("d")
+                            .long("depth")
+                            .takes_value(true)
+                            .required(true)
+                            .help("Number of plies to search"),
+                    )
+                    .arg(
+                        Arg::with_name("fen").long("fen").takes_value(true).help(
+                            "Position in Forsyth-Edwards notation, instead of the current one",
+                        ),
+                    )
+                    .arg(
+                        Arg::with_name("hash")
+                            .long("hash")
+                            .takes_value(true)
+                            .value_name("megabytes")
+                            .help("Cache counts of positions already seen in a table of this size"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("book")
+                    .about("use opening books in Polyglot format")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        SubCommand::with_name("probe")
+                            .about("list the book moves for a position")
+                            .arg(
+                                Arg::with_name("book")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .help("Book file"),
+                            )
+                            .arg(Arg::with_name("fen").long("fen").takes_value(true).help(
+                                "Position in Forsyth-Edwards notation, instead of the current one",
+                            )),
+                    )
+                    .subcommand(
+                        SubCommand::with_name("build")
+                            .about("build a book from games in Portable Game Notation")
+                            .arg(
+                                Arg::with_name("output")
+                                    .short("o")
+                                    .long("output")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .value_name("file")
+                                    .help("Book file to write"),
+                            )
+                            .arg(
+                                Arg::with_name("max-ply")
+                                    .long("max-ply")
+                                    .takes_value(true)
+                                    .default_value("30")
+                                    .help("Only take moves from this many plies into each game"),
+                            )
+                            .arg(
+                                Arg::with_name("games")
+                                    .takes_value(true)
+                                    .multiple(true)
+                                    .required(true)
+                                    .help("Files of games"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("tb")
+                    .about("probe Syzygy endgame tablebases")
+                    .arg(
+                        Arg::with_name("path")
+                            .long("path")
+                            .takes_value(true)
+                            .required(true)
+                            .env("SYZYGY_PATH")
+                            .value_name("dir")
+                            .help("Directory of .rtbw and .rtbz tables"),
+                    )
+                    .arg(
+                        Arg::with_name("fen").long("fen").takes_value(true).help(
+                            "Position in Forsyth-Edwards notation, instead of the current one",
+                        ),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("uci")
+                    .about("speak the Universal Chess Interface on stdin and stdout"),
+            )
+            .subcommand(
+                SubCommand::with_name("xboard")
+                    .about("speak the XBoard/WinBoard engine protocol on stdin and stdout"),
+            )
+            .get_matches();
+
+    if matches.subcommand_matches("uci").is_some() {
+        let stdin = io::stdin();
+        uci::run(stdin.lock(), io::stdout());
+        return;
+    }
+    if matches.subcommand_matches("xboard").is_some() {
+        let stdin = io::stdin();
+        xboard::run(stdin.lock(), io::stdout());
+        return;
+    }
+
+    let variant_name = matches.value_of("variant").unwrap();
+    let variant = || variant::from_name(variant_name).expect("clap checks the variant");
+    let mut game = match (matches.value_of("pgn"), variant_name) {
+        (Some(path), _) => load_pgn(path),
+        (None, "chess960") if !matches.is_present("initial") => {
+            chess960_game(matches.value_of("start-index"))
+        }
+        _ if matches.is_present("start-index") => {
+            panic!("--start-index needs --variant chess960")
+        }
+        _ => setup_game(variant(), matches.value_of("initial")),
+    };
+    play_moves(&mut game, matches.value_of("moves"));
+
+    if let Some(play_matches) = matches.subcommand_matches("play") {
+        let game = play(game);
+        if let Some(path) = play_matches.value_of("save-pgn") {
+            save_pgn(&game, path);
+        }
+    } else if matches.subcommand_matches("eval").is_some() {
+        println!("{}", eval::breakdown(&game.state));
+    } else if let Some(perft_matches) = matches.subcommand_matches("perft") {
+        let depth = match perft_matches.value_of("depth").unwrap().parse() {
+            Ok(depth) if depth > 0 => depth,
+            _ => panic!("Depth must be a positive number"),
+        };
+        let game = match perft_matches.value_of("fen") {
+            Some(_) => setup_game(variant(), perft_matches.value_of("fen")),
+            None => game,
+        };
+        let mut table =
+            perft_matches
+                .value_of("hash")
+                .map(|megabytes| match megabytes.parse::<usize>() {
+                    Ok(megabytes) => {
+                        TranspositionTable::new(megabytes.clamp(1, transposition::MAX_MEGABYTES))
+                    }
+                    Err(_) => panic!("Hash size must be a number of megabytes"),
+                });
+        if table.is_some() && game.variant.name() != Standard.name() {
+            panic!("--hash only counts standard chess");
+        }
+        perft(&game.variant, &game.state, depth, table.as_mut());
+    } else if let Some(book_matches) = matches.subcommand_matches("book") {
+        if let Some(probe_matches) = book_matches.subcommand_matches("probe") {
+            let game = match probe_matches.value_of("fen") {
+                Some(_) => setup_game(variant(), probe_matches.value_of("fen")),
+                None => game,
+            };
+            probe_book(probe_matches.value_of("book").unwrap(), &game);
+        } else if let Some(build_matches) = book_matches.subcommand_matches("build") {
+            let max_ply = match build_matches.value_of("max-ply").unwrap().parse() {
+                Ok(max_ply) => max_ply,
+                Err(_) => panic!("Max ply must be a number"),
+            };
+            build_book(
+                build_matches.values_of("games").unwrap(),
+                build_matches.value_of("output").unwrap(),
+                max_ply,
+            );
+        }
+    } else if let Some(tb_matches) = matches.subcommand_matches("tb") {
+        let game = match tb_matches.value_of("fen") {
+            Some(_) => setup_game(variant(), tb_matches.value_of("fen")),
+            None => game,
+        };
+        probe_tablebase(tb_matches.value_of("path").unwrap(), &game);
+    } else if matches.is_present("fen") {
+        println!("{}", game.state.to_fen());
     } else {
         println!("{}", game.state.board.str());
+        if let Some(outcome) = game.outcome() {
+            println!("{} ({})", outcome, outcome.result_token());
+        }
     }
 }
 
-fn setup_game(initial_fen: Option<&str>) -> Game {
+fn setup_game(variant: Box<dyn Variant>, initial_fen: Option<&str>) -> Game<Box<dyn Variant>> {
     match initial_fen {
-        None => chess::new_game(),
+        None => Game::new(variant),
         Some(fen_str) => {
             let result = parsing::parse_fen(fen_str);
             if let Ok(state) = result {
-                Game::with_state(state)
+                let state = variant.set_up(state);
+                Game::with_variant_state(variant, state)
             } else {
                 panic!("Couldn't parse fen: {:?}", result);
             }
@@ -54,7 +286,162 @@ fn setup_game(initial_fen: Option<&str>) -> Game {
     }
 }
 
-fn play_moves(mut state: State, moves: Option<&str>) -> State {
+/// A Chess960 game from the start position `index`, or a random one.
+fn chess960_game(index: Option<&str>) -> Game<Box<dyn Variant>> {
+    let index = match index {
+        Some(index) => index
+            .parse()
+            .unwrap_or_else(|_| panic!("Start index must be a number")),
+        None => {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.subsec_nanos());
+            nanos % chess960::POSITIONS
+        }
+    };
+    match chess960::start_position(index) {
+        Ok(state) => Game::with_variant_state(Box::new(Standard), state),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn load_pgn(path: &str) -> Game<Box<dyn Variant>> {
+    let input = fs::read_to_string(path).expect("Couldn't read pgn file");
+    let pgn = match parsing::parse_pgn(&input) {
+        Ok(pgn) => pgn,
+        Err(e) => panic!("Couldn't parse pgn: {}", e),
+    };
+    let variant = pgn.variant().unwrap_or_else(|e| panic!("{}", e));
+    let mut game = match pgn.initial_state() {
+        Ok(state) => Game::with_variant_state(variant, state),
+        Err(e) => panic!("{}", e),
+    };
+    match pgn.replay() {
+        Ok(moves) => moves.into_iter().for_each(|m0ve| game.play(m0ve)),
+        Err(e) => panic!("{}", e),
+    }
+    game
+}
+
+fn save_pgn<V: Variant>(game: &Game<V>, path: &str) {
+    let date = today();
+    let pgn = Pgn::from_game(game, &[("Event", "chess play"), ("Date", &date)]);
+    fs::write(path, pgn.to_string()).expect("Couldn't write pgn file");
+    println!("Saved game to {}.", path);
+}
+
+/// Today's date in PGN's `YYYY.MM.DD` format.
+fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    // Convert days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = (secs / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+/// Print the number of leaf nodes below each move, then the total. The
+/// table only caches counts of standard chess.
+fn perft(
+    variant: &dyn Variant,
+    state: &State,
+    depth: u32,
+    mut table: Option<&mut TranspositionTable<u64>>,
+) {
+    let mut total = 0;
+    for m0ve in variant.legal_moves(state) {
+        let nodes = match table.as_deref_mut() {
+            Some(table) => transposition::perft(&m0ve.next, depth - 1, table),
+            None => variant.perft(&m0ve.next, depth - 1),
+        };
+        println!("{}: {}", m0ve.to_coordinate(state), nodes);
+        total += nodes;
+    }
+    println!("\nNodes searched: {}", total);
+}
+
+/// Print the book moves from the position of `game`, heaviest first.
+fn probe_book<V: Variant>(path: &str, game: &Game<V>) {
+    let state = &game.state;
+    let book = Book::open(path).unwrap_or_else(|e| panic!("Couldn't open book: {}", e));
+    let moves = book.moves(state);
+    if moves.is_empty() {
+        println!("No book moves for key {:016x}.", state.zobrist_key());
+        return;
+    }
+    let total: u64 = moves.iter().map(|(_, weight)| *weight as u64).sum();
+    for (m0ve, weight) in &moves {
+        println!(
+            "{:<8} {:<6} {:>6} {:>5.1}%",
+            game.variant.san(state, m0ve),
+            m0ve.to_coordinate(state),
+            weight,
+            100.0 * *weight as f64 / total.max(1) as f64
+        );
+    }
+}
+
+/// Build a book from every game in the PGN files at `paths`.
+fn build_book<'a>(paths: impl Iterator<Item = &'a str>, output: &str, max_ply: usize) {
+    let mut builder = BookBuilder::new(max_ply);
+    let mut count = 0;
+    for path in paths {
+        let input = fs::read_to_string(path).expect("Couldn't read pgn file");
+        let pgns = match parsing::parse_pgn_multiple(&input) {
+            Ok(pgns) => pgns,
+            Err(e) => panic!("Couldn't parse pgn: {}", e),
+        };
+        for pgn in &pgns {
+            match builder.add_pgn(pgn) {
+                Ok(()) => count += 1,
+                Err(e) => eprintln!("Skipping game in {}: {}", path, e),
+            }
+        }
+    }
+    let book = builder.build();
+    book.save(output)
+        .unwrap_or_else(|e| panic!("Couldn't save book: {}", e));
+    println!(
+        "Wrote {} entries from {} games to {}.",
+        book.len(),
+        count,
+        output
+    );
+}
+
+/// Print the result and distance to zeroing of the position of `game`, then
+/// the moves that keep the result best.
+fn probe_tablebase<V: Variant>(path: &str, game: &Game<V>) {
+    let state = &game.state;
+    let tablebase =
+        Tablebase::open(path).unwrap_or_else(|e| panic!("Couldn't open tablebase: {}", e));
+    let probe = tablebase
+        .probe_wdl(state)
+        .and_then(|wdl| Ok((wdl, tablebase.probe_dtz(state)?)))
+        .and_then(|(wdl, dtz)| Ok((wdl, dtz, tablebase.best_moves(state)?)));
+    let (wdl, dtz, moves) = probe.unwrap_or_else(|e| panic!("Couldn't probe tablebase: {}", e));
+    println!("WDL: {}", wdl);
+    println!("DTZ: {}", dtz);
+    for (m0ve, dtz) in &moves {
+        println!(
+            "{:<8} {:<6} DTZ {}",
+            game.variant.san(state, m0ve),
+            m0ve.to_coordinate(state),
+            dtz
+        );
+    }
+}
+
+fn play_moves<V: Variant>(game: &mut Game<V>, moves: Option<&str>) {
     match moves {
         None => (),
         Some(moves_str) => {
@@ -62,11 +449,9 @@ fn play_moves(mut state: State, moves: Option<&str>) -> State {
             match res {
                 Ok(move_descriptions) => {
                     for move_description in move_descriptions {
-                        let game_moves = state.gen_moves();
-                        match move_description.match_moves(game_moves) {
-                            Some(m0ve) => {
-                                state = m0ve.next;
-                            }
+                        let game_moves = game.moves();
+                        match move_description.match_moves(&game.state, game_moves) {
+                            Some(m0ve) => game.play(m0ve),
                             None => panic!("Error making move {:?}", move_description),
                         }
                     }
@@ -77,41 +462,65 @@ fn play_moves(mut state: State, moves: Option<&str>) -> State {
             }
         }
     }
-    state
 }
 
-fn play(mut game: Game) {
+fn play<V: Variant>(mut game: Game<V>) -> Game<V> {
     let ended: &mut bool = &mut false;
     let mut buf = String::new();
 
     while !*ended {
         println!("\n{}", game.state.board.str());
 
-        let moves = game.state.gen_moves();
-
-        if moves.is_empty() {
-            println!("Game over! RET quits.");
+        if let Some(outcome) = game.outcome() {
+            println!(
+                "Game over! {} ({}). RET quits.",
+                outcome,
+                outcome.result_token()
+            );
             io::stdin().read_line(&mut buf).unwrap();
             *ended = true;
             break;
         }
 
+        let moves = game.moves();
+
+        if let Some(checks) = game.state.checks {
+            println!(
+                "Checks given: White {}, Black {}.",
+                checks.white, checks.black
+            );
+        }
         println!("{}'s move.", game.state.player);
 
-        println!("Please enter a move, or 'q' quits.");
+        println!("Please enter a move, 'undo', 'redo', or 'q' quits.");
 
         io::stdin().read_line(&mut buf).unwrap();
 
-        if buf.trim() == "q" {
-            *ended = true;
-            break;
+        match buf.trim() {
+            "q" => {
+                *ended = true;
+                break;
+            }
+            "undo" => {
+                if !game.undo() {
+                    println!("Nothing to undo!");
+                }
+                buf = String::new();
+                continue;
+            }
+            "redo" => {
+                if !game.redo() {
+                    println!("Nothing to redo!");
+                }
+                buf = String::new();
+                continue;
+            }
+            _ => (),
         }
 
         match parsing::parse_algebraic_notation(buf.trim()) {
-            Ok(move_description) => match move_description.match_moves(moves) {
-                Some(m0ve) => {
-                    game = Game { state: m0ve.next };
-                }
+            Ok(move_description) => match move_description.match_moves(&game.state, moves) {
+                Some(m0ve) => game.play(m0ve),
                 None => println!("Can't make that move!"),
             },
             Err(_) => {
@@ -121,4 +530,6 @@ fn play(mut game: Game) {
 
         buf = String::new();
     }
+
+    game
 }
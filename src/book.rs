@@ -0,0 +1,396 @@
+//! Opening books in the Polyglot `.bin` format, see
+//! http://hgm.nubati.net/book_format.html
+//!
+//! A book is a list of 16 byte entries sorted by the Zobrist key of the
+//! position they're for, each giving one move and how good it is.
+
+use crate::game::{
+    castles::Castleside, m0ve::Action, m0ve::Move, piece::Piece, player::Player, pos::Pos,
+    state::State,
+};
+use crate::parsing::pgn::{Pgn, ReplayError};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs;
+
+const ENTRY_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    /// The move, in Polyglot's encoding, see `decode_move`.
+    pub raw_move: u16,
+    /// Higher is better. Moves are picked in proportion to their weight.
+    pub weight: u16,
+    /// Unused here, but kept so books are written back unchanged.
+    pub learn: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    /// A book of `entries`, in any order.
+    pub fn new(mut entries: Vec<BookEntry>) -> Book {
+        entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)));
+        Book { entries }
+    }
+
+    /// Read a book from the contents of a `.bin` file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Book, String> {
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(format!(
+                "book size {} is not a multiple of {}",
+                bytes.len(),
+                ENTRY_SIZE
+            ));
+        }
+        let entries = bytes
+            .chunks(ENTRY_SIZE)
+            .map(|chunk| BookEntry {
+                key: u64::from_be_bytes(chunk[0..8].try_into().unwrap()),
+                raw_move: u16::from_be_bytes(chunk[8..10].try_into().unwrap()),
+                weight: u16::from_be_bytes(chunk[10..12].try_into().unwrap()),
+                learn: u32::from_be_bytes(chunk[12..16].try_into().unwrap()),
+            })
+            .collect();
+        Ok(Book::new(entries))
+    }
+
+    pub fn open(path: &str) -> Result<Book, String> {
+        let bytes = fs::read(path).map_err(|e| format!("couldn't read {}: {}", path, e))?;
+        Book::from_bytes(&bytes)
+    }
+
+    /// The contents of a `.bin` file for this book.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.entries.len() * ENTRY_SIZE);
+        for entry in &self.entries {
+            bytes.extend_from_slice(&entry.key.to_be_bytes());
+            bytes.extend_from_slice(&entry.raw_move.to_be_bytes());
+            bytes.extend_from_slice(&entry.weight.to_be_bytes());
+            bytes.extend_from_slice(&entry.learn.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_bytes()).map_err(|e| format!("couldn't write {}: {}", path, e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entries for the position with `key`, heaviest first.
+    pub fn entries(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = self.entries.partition_point(|entry| entry.key <= key);
+        &self.entries[start..end]
+    }
+
+    /// The legal book moves from `state` with their weights, heaviest first.
+    /// Entries that don't decode to a legal move, from a hash collision for
+    /// example, are left out.
+    pub fn moves(&self, state: &State) -> Vec<(Move, u16)> {
+        self.entries(state.zobrist_key())
+            .iter()
+            .filter_map(|entry| Some((decode_move(entry.raw_move, state)?, entry.weight)))
+            .collect()
+    }
+
+    /// The book move from `state` with the highest weight.
+    pub fn best_move(&self, state: &State) -> Option<Move> {
+        self.moves(state)
+            .into_iter()
+            .find(|(_, weight)| *weight > 0)
+            .map(|(m0ve, _)| m0ve)
+    }
+
+    /// A book move from `state`, picked at random in proportion to the
+    /// weights. `random` is any random number.
+    pub fn weighted_move(&self, state: &State, random: u64) -> Option<Move> {
+        let moves = self.moves(state);
+        let total: u64 = moves.iter().map(|(_, weight)| *weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = random % total;
+        for (m0ve, weight) in moves {
+            if pick < weight as u64 {
+                return Some(m0ve);
+            }
+            pick -= weight as u64;
+        }
+        None
+    }
+}
+
+fn promotion_code(piece: Piece) -> u16 {
+    match piece {
+        Piece::Knight => 1,
+        Piece::Bishop => 2,
+        Piece::Rook => 3,
+        Piece::Queen => 4,
+        _ => 0,
+    }
+}
+
+fn square_code(pos: Pos) -> u16 {
+    (pos.rank as u16) * 8 + pos.file as u16
+}
+
+fn code_square(code: u16) -> Pos {
+    Pos {
+        rank: ((code >> 3) & 7) as u8,
+        file: (code & 7) as u8,
+    }
+}
+
+/// Encode `action`, played from `state`, the way Polyglot does: the
+/// destination in bits 0-5, the origin in bits 6-11 and any promotion in
+/// bits 12-14. Castling is written as the king capturing its own rook.
+/// Polyglot has no code for crazyhouse drops, which all come out as 0.
+pub fn encode_move(action: &Action, state: &State) -> u16 {
+    let (from, to, promotion) = match *action {
+        Action::Simple { from, to } => (from, to, 0),
+        Action::Promotion { from, to, piece } => (from, to, promotion_code(piece)),
+        Action::Castle { castleside } => {
+            let (rook, _) = state.castling.rook_squares(state.player, castleside);
+            (state.board.get_king_pos(state.player), rook, 0)
+        }
+        Action::Drop { .. } => return 0,
+    };
+    promotion << 12 | square_code(from) << 6 | square_code(to)
+}
+
+/// The legal move from `state` that Polyglot encodes as `raw_move`, see
+/// `encode_move`.
+pub fn decode_move(raw_move: u16, state: &State) -> Option<Move> {
+    let from = code_square(raw_move >> 6);
+    let to = code_square(raw_move);
+    let action = match (raw_move >> 12) & 7 {
+        0 => match (state.board.piece_at(from), state.board.piece_at(to)) {
+            (Some((player, Piece::King)), Some((owner, Piece::Rook))) if player == owner => {
+                let castleside = if to.file > from.file {
+                    Castleside::Kingside
+                } else {
+                    Castleside::Queenside
+                };
+                Action::Castle { castleside }
+            }
+            _ => Action::Simple { from, to },
+        },
+        1 => Action::Promotion {
+            from,
+            to,
+            piece: Piece::Knight,
+        },
+        2 => Action::Promotion {
+            from,
+            to,
+            piece: Piece::Bishop,
+        },
+        3 => Action::Promotion {
+            from,
+            to,
+            piece: Piece::Rook,
+        },
+        4 => Action::Promotion {
+            from,
+            to,
+            piece: Piece::Queen,
+        },
+        _ => return None,
+    };
+    state
+        .gen_moves()
+        .into_iter()
+        .find(|m0ve| m0ve.action == action)
+}
+
+/// Collects moves from games into a book. Each move scores 2 for every game
+/// the side playing it won, 1 for every draw or unfinished game and nothing
+/// for a loss, so moves only ever played by the losing side are left out.
+#[derive(Debug, Clone, Default)]
+pub struct BookBuilder {
+    max_ply: usize,
+    scores: HashMap<(u64, u16), u64>,
+}
+
+impl BookBuilder {
+    /// A builder only taking moves from the first `max_ply` plies of each
+    /// game.
+    pub fn new(max_ply: usize) -> BookBuilder {
+        BookBuilder {
+            max_ply,
+            scores: HashMap::new(),
+        }
+    }
+
+    /// Add the moves of a game played from `start`, which ended with `result`,
+    /// a PGN result token like `1-0`.
+    pub fn add_game(&mut self, start: &State, moves: &[Move], result: &str) {
+        let mut state = start;
+        for m0ve in moves.iter().take(self.max_ply) {
+            let points = match (result, state.player) {
+                ("1-0", Player::White) | ("0-1", Player::Black) => 2,
+                ("1-0", Player::Black) | ("0-1", Player::White) => 0,
+                _ => 1,
+            };
+            let raw_move = encode_move(&m0ve.action, state);
+            *self
+                .scores
+                .entry((state.zobrist_key(), raw_move))
+                .or_insert(0) += points;
+            state = &m0ve.next;
+        }
+    }
+
+    /// Add the mainline of a game parsed from PGN.
+    pub fn add_pgn(&mut self, pgn: &Pgn) -> Result<(), ReplayError> {
+        let start = pgn.initial_state()?;
+        let moves = pgn.replay()?;
+        self.add_game(&start, &moves, pgn.result.as_deref().unwrap_or("*"));
+        Ok(())
+    }
+
+    /// The book of every move with a score, with the scores scaled down to
+    /// fit in a weight if needed.
+    pub fn build(self) -> Book {
+        let max = self.scores.values().copied().max().unwrap_or(0);
+        let scale = max.div_ceil(u16::MAX as u64).max(1);
+        let entries = self
+            .scores
+            .into_iter()
+            .filter(|(_, score)| *score > 0)
+            .map(|((key, raw_move), score)| BookEntry {
+                key,
+                raw_move,
+                weight: (score / scale).max(1) as u16,
+                learn: 0,
+            })
+            .collect();
+        Book::new(entries)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::pos::*;
+    use crate::parsing::{parse_fen, parse_pgn};
+
+    fn play(state: &State, coordinates: &[&str]) -> Vec<Move> {
+        let mut current = state.clone();
+        let mut moves = vec![];
+        for text in coordinates {
+            let m0ve = crate::protocol::find_move(&current, text).unwrap();
+            current = m0ve.next.clone();
+            moves.push(m0ve);
+        }
+        moves
+    }
+
+    #[test]
+    fn test_encoding() {
+        let state = crate::new_game().state;
+        let push = Action::Simple { from: e2, to: e4 };
+        // e2 is square 12 and e4 square 28.
+        assert_eq!(encode_move(&push, &state), 12 << 6 | 28);
+        assert_eq!(decode_move(12 << 6 | 28, &state).unwrap().action, push);
+        // Not legal here.
+        assert!(decode_move(12 << 6 | 36, &state).is_none());
+
+        let state = parse_fen("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let castle = Action::Castle {
+            castleside: Castleside::Queenside,
+        };
+        // e1 takes a1.
+        assert_eq!(encode_move(&castle, &state), 4 << 6);
+        assert_eq!(decode_move(4 << 6, &state).unwrap().action, castle);
+        let kingside = Action::Castle {
+            castleside: Castleside::Kingside,
+        };
+        assert_eq!(decode_move(4 << 6 | 7, &state).unwrap().action, kingside);
+
+        let promotion = Action::Promotion {
+            from: b7,
+            to: a8,
+            piece: Piece::Knight,
+        };
+        let raw = encode_move(&promotion, &state);
+        assert_eq!(raw, 1 << 12 | 49 << 6 | 56);
+        assert_eq!(decode_move(raw, &state).unwrap().action, promotion);
+    }
+
+    #[test]
+    fn test_build_and_read() {
+        let start = crate::new_game().state;
+        let mut builder = BookBuilder::new(2);
+        builder.add_game(&start, &play(&start, &["e2e4", "e7e5", "g1f3"]), "1-0");
+        builder.add_game(&start, &play(&start, &["e2e4", "c7c5"]), "0-1");
+        builder.add_game(&start, &play(&start, &["d2d4", "d7d5"]), "1/2-1/2");
+        let book = builder.build();
+        // e4, d4, d5 and c5. Not e5, which lost, or Nf3 at ply 3.
+        assert_eq!(book.len(), 4);
+
+        let book = Book::from_bytes(&book.to_bytes()).unwrap();
+        let moves: Vec<(String, u16)> = book
+            .moves(&start)
+            .iter()
+            .map(|(m0ve, weight)| (m0ve.to_coordinate(&start), *weight))
+            .collect();
+        assert_eq!(
+            moves,
+            vec![("e2e4".to_string(), 2), ("d2d4".to_string(), 1)]
+        );
+        assert_eq!(
+            book.best_move(&start).unwrap().action,
+            Action::Simple { from: e2, to: e4 }
+        );
+        let picks: Vec<Action> = (0..3)
+            .map(|random| book.weighted_move(&start, random).unwrap().action)
+            .collect();
+        assert_eq!(
+            picks,
+            vec![
+                Action::Simple { from: e2, to: e4 },
+                Action::Simple { from: e2, to: e4 },
+                Action::Simple { from: d2, to: d4 },
+            ]
+        );
+
+        // Only c5 won after e4.
+        let after_e4 = play(&start, &["e2e4"]).remove(0).next;
+        let moves = book.moves(&after_e4);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].0.to_coordinate(&after_e4), "c7c5");
+
+        let empty = parse_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(book.best_move(&empty).is_none());
+        assert!(book.weighted_move(&empty, 7).is_none());
+    }
+
+    #[test]
+    fn test_add_pgn() {
+        let pgn = parse_pgn("[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 1-0").unwrap();
+        let mut builder = BookBuilder::new(10);
+        builder.add_pgn(&pgn).unwrap();
+        let book = builder.build();
+        // The losing side's e5 is left out.
+        assert_eq!(book.len(), 2);
+    }
+
+    #[test]
+    fn test_bad_size() {
+        assert!(Book::from_bytes(&[0; 17]).is_err());
+        assert_eq!(Book::from_bytes(&[]).unwrap(), Book::default());
+    }
+}
@@ -0,0 +1,451 @@
+//! Choosing a move: negamax alpha-beta search with iterative deepening.
+
+pub mod transposition;
+
+use crate::eval::{evaluate, piece_value};
+use crate::game::{m0ve::Action, m0ve::Move, state::State};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use transposition::{score_from_table, score_to_table, Bound, SearchEntry, TranspositionTable};
+
+/// The score of delivering checkmate right now. Mates further away score
+/// one less per ply, so shorter mates are preferred.
+pub const MATE: i32 = 30_000;
+
+/// The deepest the search will go when no other limit stops it.
+pub const MAX_DEPTH: u32 = 64;
+
+/// How often, in nodes, the clock is checked.
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+/// When to stop searching. The search stops at whichever limit is reached
+/// first, or at `MAX_DEPTH` if none is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+}
+
+impl Limits {
+    pub fn depth(depth: u32) -> Limits {
+        Limits {
+            depth: Some(depth),
+            ..Limits::default()
+        }
+    }
+
+    pub fn nodes(nodes: u64) -> Limits {
+        Limits {
+            nodes: Some(nodes),
+            ..Limits::default()
+        }
+    }
+
+    pub fn movetime(movetime: Duration) -> Limits {
+        Limits {
+            movetime: Some(movetime),
+            ..Limits::default()
+        }
+    }
+}
+
+/// The outcome of the deepest completed iteration of a search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    /// `None` only if there are no legal moves.
+    pub best_move: Option<Move>,
+    /// Centipawns from the point of view of the side to move, or a mate
+    /// score, see `mate_in`.
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    pub elapsed: Duration,
+    /// The principal variation: the line both sides are expected to play,
+    /// starting with `best_move`.
+    pub pv: Vec<Move>,
+}
+
+impl SearchResult {
+    /// If the score is a forced mate, the number of moves (not plies) until
+    /// it happens: positive when the side to move mates, negative when it is
+    /// mated.
+    pub fn mate_in(&self) -> Option<i32> {
+        let plies = MATE - self.score.abs();
+        if plies > MAX_DEPTH as i32 {
+            None
+        } else if self.score > 0 {
+            Some((plies + 1) / 2)
+        } else {
+            Some(-plies / 2)
+        }
+    }
+}
+
+/// Search `state` for the best move within `limits`.
+pub fn best_move(state: &State, limits: Limits) -> SearchResult {
+    let mut table = TranspositionTable::default();
+    search(state, limits, &mut table, &AtomicBool::new(false), |_| ())
+}
+
+/// Like `best_move`, but also stops as soon as `stop` is set, from another
+/// thread for example, and calls `report` with the result of every completed
+/// iteration so callers can show the search's progress. `table` can be kept
+/// between searches, so later ones reuse what earlier ones found.
+pub fn search<F>(
+    state: &State,
+    limits: Limits,
+    table: &mut TranspositionTable,
+    stop: &AtomicBool,
+    mut report: F,
+) -> SearchResult
+where
+    F: FnMut(&SearchResult),
+{
+    table.new_search();
+    let mut searcher = Searcher {
+        limits,
+        table,
+        stop,
+        start: Instant::now(),
+        nodes: 0,
+        aborted: false,
+    };
+    let mut result = SearchResult {
+        best_move: None,
+        score: 0,
+        depth: 0,
+        nodes: 0,
+        elapsed: Duration::from_secs(0),
+        pv: vec![],
+    };
+
+    let mut moves = state.gen_moves();
+    if moves.is_empty() {
+        result.score = if state.in_check() { -MATE } else { 0 };
+        return result;
+    }
+
+    let max_depth = limits.depth.unwrap_or(MAX_DEPTH).clamp(1, MAX_DEPTH);
+    for depth in 1..=max_depth {
+        let mut pv = vec![];
+        let score = searcher.negamax(state, depth, 0, -MATE, MATE, &result.pv, &mut pv);
+        if searcher.aborted && !result.pv.is_empty() {
+            break;
+        }
+        if pv.is_empty() {
+            // Stopped before the first iteration found anything, so fall
+            // back to any legal move.
+            pv.push(moves.swap_remove(0));
+        }
+
+        result = SearchResult {
+            best_move: pv.first().cloned(),
+            score,
+            depth,
+            nodes: searcher.nodes,
+            elapsed: searcher.start.elapsed(),
+            pv,
+        };
+        report(&result);
+
+        if searcher.aborted || result.mate_in().is_some() {
+            break;
+        }
+    }
+    result.nodes = searcher.nodes;
+    result.elapsed = searcher.start.elapsed();
+    result
+}
+
+struct Searcher<'a> {
+    limits: Limits,
+    table: &'a mut TranspositionTable,
+    stop: &'a AtomicBool,
+    start: Instant,
+    nodes: u64,
+    aborted: bool,
+}
+
+impl<'a> Searcher<'a> {
+    /// Count a node and check whether the search should stop.
+    fn visit(&mut self) -> bool {
+        self.nodes += 1;
+        if self.stop.load(Ordering::Relaxed) {
+            self.aborted = true;
+        }
+        if let Some(nodes) = self.limits.nodes {
+            if self.nodes > nodes {
+                self.aborted = true;
+            }
+        }
+        if let Some(movetime) = self.limits.movetime {
+            if self.nodes % TIME_CHECK_INTERVAL == 0 && self.start.elapsed() >= movetime {
+                self.aborted = true;
+            }
+        }
+        self.aborted
+    }
+
+    /// The score of `state` from the side to move, searching `depth` plies
+    /// and then only captures. `pv` is filled with the best line found.
+    /// `hint` is the previous iteration's line, tried first, otherwise the
+    /// best move stored in the transposition table.
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(
+        &mut self,
+        state: &State,
+        depth: u32,
+        ply: u32,
+        mut alpha: i32,
+        beta: i32,
+        hint: &[Move],
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        if self.visit() {
+            return 0;
+        }
+
+        let key = state.zobrist_key();
+        let mut table_move = None;
+        if let Some(entry) = self.table.probe(key) {
+            let score = score_from_table(entry.data.score, ply);
+            // Exact scores are only used for cutoffs too, so that the
+            // principal variation is always searched out in full.
+            let cutoff = match entry.data.bound {
+                Bound::Exact => score >= beta || score <= alpha,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if ply > 0 && entry.depth >= depth && cutoff {
+                return score;
+            }
+            table_move = entry.data.best_move;
+        }
+
+        let moves = state.gen_moves();
+        if moves.is_empty() {
+            return if state.in_check() {
+                -MATE + ply as i32
+            } else {
+                0
+            };
+        }
+        if state.halfmove_clock >= 100 {
+            return 0;
+        }
+        if depth == 0 {
+            return self.quiesce(state, alpha, beta);
+        }
+
+        let original_alpha = alpha;
+        let hinted = hint.first().map(|m0ve| m0ve.action).or(table_move);
+        for m0ve in order_moves(moves, hinted) {
+            let child_hint = match hint.first() {
+                Some(hinted) if hinted.action == m0ve.action => &hint[1..],
+                _ => &[],
+            };
+            let mut child_pv = vec![];
+            let score = -self.negamax(
+                &m0ve.next,
+                depth - 1,
+                ply + 1,
+                -beta,
+                -alpha,
+                child_hint,
+                &mut child_pv,
+            );
+            if self.aborted {
+                return 0;
+            }
+            if score > alpha {
+                alpha = score;
+                pv.clear();
+                pv.push(m0ve);
+                pv.append(&mut child_pv);
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+
+        let bound = if alpha >= beta {
+            Bound::Lower
+        } else if alpha > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        let entry = SearchEntry {
+            best_move: pv.first().map(|m0ve| m0ve.action).or(hinted),
+            bound,
+            score: score_to_table(alpha, ply),
+        };
+        self.table.store(key, depth, entry);
+        alpha
+    }
+
+    /// Search captures and promotions only, until the position is quiet,
+    /// so the evaluation isn't taken in the middle of an exchange.
+    fn quiesce(&mut self, state: &State, mut alpha: i32, beta: i32) -> i32 {
+        if self.visit() {
+            return 0;
+        }
+
+        let stand_pat = evaluate(state);
+        if stand_pat >= beta {
+            return beta;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let moves = state
+            .gen_moves()
+            .into_iter()
+            .filter(|m0ve| {
+                m0ve.captured.is_some() || matches!(m0ve.action, Action::Promotion { .. })
+            })
+            .collect();
+        for m0ve in order_moves(moves, None) {
+            let score = -self.quiesce(&m0ve.next, -beta, -alpha);
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return beta;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+}
+
+/// Put the hinted move first, then captures of the most valuable pieces,
+/// then everything else in generation order.
+fn order_moves(mut moves: Vec<Move>, hint: Option<Action>) -> Vec<Move> {
+    moves.sort_by_key(|m0ve| {
+        if hint == Some(m0ve.action) {
+            i32::MIN
+        } else {
+            -m0ve.captured.map_or(0, piece_value)
+        }
+    });
+    moves
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::pos::*;
+    use crate::parsing::parse_fen;
+
+    #[test]
+    fn test_mate_in_one() {
+        let state = parse_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let result = best_move(&state, Limits::depth(3));
+        assert_eq!(
+            result.best_move.as_ref().unwrap().action,
+            Action::Simple { from: a1, to: a8 }
+        );
+        assert_eq!(result.score, MATE - 1);
+        assert_eq!(result.mate_in(), Some(1));
+        // Stops as soon as the mate is found.
+        assert_eq!(result.depth, 1);
+    }
+
+    #[test]
+    fn test_mated_in_one() {
+        // Kb8 is forced, then Rh8#.
+        let state = parse_fen("k7/8/1K6/8/8/8/8/7R b - - 0 1").unwrap();
+        let result = best_move(&state, Limits::depth(3));
+        assert_eq!(result.score, -MATE + 2);
+        assert_eq!(result.mate_in(), Some(-1));
+        assert_eq!(result.pv.len(), 2);
+    }
+
+    #[test]
+    fn test_takes_hanging_queen() {
+        let state = parse_fen("4k3/8/8/3q4/8/2N5/8/4K3 w - - 0 1").unwrap();
+        let result = best_move(&state, Limits::depth(2));
+        assert_eq!(
+            result.best_move.as_ref().unwrap().action,
+            Action::Simple { from: c3, to: d5 }
+        );
+        assert!(result.score > 0);
+    }
+
+    #[test]
+    fn test_no_legal_moves() {
+        // Fool's mate.
+        let state =
+            parse_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+        let result = best_move(&state, Limits::depth(2));
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, -MATE);
+        assert_eq!(result.mate_in(), Some(0));
+    }
+
+    #[test]
+    fn test_pv() {
+        let state = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let mut depths = vec![];
+        let stop = AtomicBool::new(false);
+        let mut table = TranspositionTable::default();
+        let result = search(&state, Limits::depth(3), &mut table, &stop, |info| {
+            depths.push(info.depth)
+        });
+        assert_eq!(depths, vec![1, 2, 3]);
+        assert_eq!(result.depth, 3);
+        assert_eq!(result.pv.len(), 3);
+        assert_eq!(result.best_move.as_ref(), result.pv.first());
+        // Each move in the line is legal in the position before it.
+        let mut current = state;
+        for m0ve in &result.pv {
+            assert!(current.gen_moves().contains(m0ve));
+            current = m0ve.next.clone();
+        }
+    }
+
+    #[test]
+    fn test_reuses_table() {
+        let state =
+            parse_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let stop = AtomicBool::new(false);
+        let mut table = TranspositionTable::default();
+        let first = search(&state, Limits::depth(4), &mut table, &stop, |_| ());
+        let second = search(&state, Limits::depth(4), &mut table, &stop, |_| ());
+        assert_eq!(first.score, second.score);
+        assert!(second.nodes < first.nodes);
+        assert!(table.probe(state.zobrist_key()).is_some());
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let state = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let result = best_move(&state, Limits::nodes(500));
+        assert!(result.best_move.is_some());
+        assert!(result.nodes <= 501);
+        assert!(result.depth < MAX_DEPTH);
+    }
+
+    #[test]
+    fn test_stop() {
+        let state = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let stop = AtomicBool::new(false);
+        let mut table = TranspositionTable::default();
+        let result = search(&state, Limits::default(), &mut table, &stop, |info| {
+            if info.depth == 2 {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+        assert_eq!(result.depth, 2);
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn test_movetime_limit() {
+        let state = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let result = best_move(&state, Limits::movetime(Duration::from_millis(50)));
+        assert!(result.best_move.is_some());
+        assert!(result.elapsed < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,270 @@
+//! A fixed-size cache of positions already searched, keyed by their Zobrist
+//! key, so positions reached by different move orders are only searched
+//! once.
+
+use crate::engine::{MATE, MAX_DEPTH};
+use crate::game::{m0ve::Action, state::State};
+use std::mem;
+
+/// The table size used when none is asked for.
+pub const DEFAULT_MEGABYTES: usize = 16;
+
+/// The largest table the protocols and command line will ask for.
+pub const MAX_MEGABYTES: usize = 4_096;
+
+/// How a stored score relates to the position's true score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is exact.
+    Exact,
+    /// The search failed high: the true score is at least this.
+    Lower,
+    /// The search failed low: the true score is at most this.
+    Upper,
+}
+
+/// What the search remembers about a position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchEntry {
+    pub best_move: Option<Action>,
+    pub bound: Bound,
+    /// From the side to move, with mates counted from this position, see
+    /// `score_to_table`.
+    pub score: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Entry<T> {
+    pub key: u64,
+    /// How many plies deep the walk below this position went.
+    pub depth: u32,
+    generation: u8,
+    pub data: T,
+}
+
+/// A hash table of at most a given number of megabytes, holding one entry
+/// per slot. A new entry replaces the old one in its slot if it is for the
+/// same position, was searched at least as deep, or the old one is left over
+/// from an earlier search (see `new_search`).
+///
+/// The data stored is `SearchEntry` for the engine, but any depth-limited
+/// walk over `State` can use the table, e.g. `perft` stores node counts.
+#[derive(Debug, Clone)]
+pub struct TranspositionTable<T = SearchEntry> {
+    entries: Vec<Option<Entry<T>>>,
+    generation: u8,
+}
+
+impl<T: Copy> TranspositionTable<T> {
+    /// An empty table using no more than `megabytes` of memory, but always
+    /// at least one slot. Callers should keep `megabytes` within
+    /// `MAX_MEGABYTES`, as the whole table is allocated up front.
+    pub fn new(megabytes: usize) -> Self {
+        let bytes = megabytes.saturating_mul(1024 * 1024);
+        let slots = bytes / mem::size_of::<Option<Entry<T>>>();
+        // A power of two, so the slot is just the low bits of the key.
+        let slots = match slots {
+            0 => 1,
+            _ => 1 << (usize::BITS - 1 - slots.leading_zeros()),
+        };
+        TranspositionTable {
+            entries: vec![None; slots],
+            generation: 0,
+        }
+    }
+
+    /// The number of entries the table can hold.
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn index(&self, key: u64) -> usize {
+        key as usize & (self.entries.len() - 1)
+    }
+
+    /// The entry for the position with `key`, if it's still in the table.
+    pub fn probe(&self, key: u64) -> Option<&Entry<T>> {
+        self.entries[self.index(key)]
+            .as_ref()
+            .filter(|entry| entry.key == key)
+    }
+
+    /// Remember `data` for the position with `key`, found by walking `depth`
+    /// plies below it.
+    pub fn store(&mut self, key: u64, depth: u32, data: T) {
+        let generation = self.generation;
+        let index = self.index(key);
+        let slot = &mut self.entries[index];
+        let replace = match slot {
+            None => true,
+            Some(old) => old.key == key || old.generation != generation || depth >= old.depth,
+        };
+        if replace {
+            *slot = Some(Entry {
+                key,
+                depth,
+                generation,
+                data,
+            });
+        }
+    }
+
+    /// Start a new search. Entries from earlier searches are kept, but are
+    /// replaced by anything new regardless of depth.
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Forget everything, e.g. for a new game.
+    pub fn clear(&mut self) {
+        for slot in self.entries.iter_mut() {
+            *slot = None;
+        }
+        self.generation = 0;
+    }
+
+    /// How full the table is with entries from the current search, in
+    /// permille, estimated from the first thousand slots.
+    pub fn hashfull(&self) -> u32 {
+        let sample = self.entries.len().min(1000);
+        let used = self.entries[..sample]
+            .iter()
+            .filter(|slot| slot.is_some_and(|entry| entry.generation == self.generation))
+            .count();
+        (used * 1000 / sample) as u32
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        TranspositionTable::new(DEFAULT_MEGABYTES)
+    }
+}
+
+fn is_mate(score: i32) -> bool {
+    score.abs() > MATE - MAX_DEPTH as i32
+}
+
+/// Convert a score found `ply` plies from the root to one for storing.
+/// Mate scores count plies from the root, but the same position can be
+/// reached at different plies, so they are stored counted from the position
+/// itself.
+pub fn score_to_table(score: i32, ply: u32) -> i32 {
+    match score {
+        _ if !is_mate(score) => score,
+        _ if score > 0 => score + ply as i32,
+        _ => score - ply as i32,
+    }
+}
+
+/// The inverse of `score_to_table`, for a position `ply` plies from the
+/// root.
+pub fn score_from_table(score: i32, ply: u32) -> i32 {
+    match score {
+        _ if !is_mate(score) => score,
+        _ if score > 0 => score - ply as i32,
+        _ => score + ply as i32,
+    }
+}
+
+/// Like `State::perft`, but remembering the counts of positions already
+/// seen in `table`.
+pub fn perft(state: &State, depth: u32, table: &mut TranspositionTable<u64>) -> u64 {
+    if depth <= 1 {
+        return state.perft(depth);
+    }
+    let key = state.zobrist_key();
+    if let Some(entry) = table.probe(key) {
+        if entry.depth == depth {
+            return entry.data;
+        }
+    }
+    let nodes = state
+        .gen_moves()
+        .iter()
+        .map(|m0ve| perft(&m0ve.next, depth - 1, table))
+        .sum();
+    table.store(key, depth, nodes);
+    nodes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::pos::*;
+    use crate::parsing::parse_fen;
+
+    #[test]
+    fn test_capacity() {
+        let table: TranspositionTable<u64> = TranspositionTable::new(1);
+        assert!(table.capacity().is_power_of_two());
+        assert!(table.capacity() * mem::size_of::<Option<Entry<u64>>>() <= 1024 * 1024);
+        let table: TranspositionTable<u64> = TranspositionTable::new(0);
+        assert_eq!(table.capacity(), 1);
+    }
+
+    #[test]
+    fn test_replacement() {
+        let mut table = TranspositionTable::new(0);
+        table.store(1, 5, 'a');
+        assert_eq!(table.probe(1).unwrap().data, 'a');
+        assert!(table.probe(2).is_none());
+
+        // A shallower entry for another position doesn't replace a deeper
+        // one from the same search...
+        table.store(2, 4, 'b');
+        assert_eq!(table.probe(1).unwrap().data, 'a');
+        table.store(2, 5, 'b');
+        assert_eq!(table.probe(2).unwrap().data, 'b');
+
+        // ...but does replace one from an earlier search.
+        table.new_search();
+        table.store(3, 1, 'c');
+        assert_eq!(table.probe(3).unwrap().data, 'c');
+        assert_eq!(table.hashfull(), 1000);
+
+        table.clear();
+        assert!(table.probe(3).is_none());
+        assert_eq!(table.hashfull(), 0);
+    }
+
+    #[test]
+    fn test_mate_scores() {
+        // Mate in 3 plies from a position 2 plies from the root is stored as
+        // mate in 3, and read back as mate in 4 from a position 1 ply deep.
+        let stored = score_to_table(MATE - 5, 2);
+        assert_eq!(stored, MATE - 3);
+        assert_eq!(score_from_table(stored, 1), MATE - 4);
+        assert_eq!(score_to_table(-MATE + 5, 2), -MATE + 3);
+        assert_eq!(score_to_table(150, 2), 150);
+        assert_eq!(score_from_table(-150, 7), -150);
+    }
+
+    #[test]
+    fn test_perft() {
+        let mut table = TranspositionTable::new(1);
+        let state = crate::new_game().state;
+        assert_eq!(perft(&state, 4, &mut table), 197_281);
+        assert!(table.probe(state.zobrist_key()).is_some());
+        // The second time is answered from the table.
+        assert_eq!(perft(&state, 4, &mut table), 197_281);
+
+        let kiwipete =
+            parse_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        assert_eq!(perft(&kiwipete, 3, &mut table), 97_862);
+    }
+
+    #[test]
+    fn test_search_entry() {
+        let mut table = TranspositionTable::default();
+        let entry = SearchEntry {
+            best_move: Some(Action::Simple { from: e2, to: e4 }),
+            bound: Bound::Lower,
+            score: 30,
+        };
+        table.store(42, 3, entry);
+        assert_eq!(table.probe(42).unwrap().data, entry);
+        assert_eq!(table.probe(42).unwrap().depth, 3);
+    }
+}
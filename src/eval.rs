@@ -0,0 +1,435 @@
+//! Static evaluation: material, tapered piece-square tables, mobility, pawn
+//! structure and king safety, in centipawns.
+
+use crate::game::bitboard::{self, bit, Bitboard};
+use crate::game::{board::Board, state::State};
+use crate::game::{piece::Piece, piece::Piece::*, player::Player, player::Player::*, pos::Pos};
+use std::fmt;
+
+/// A score for the middlegame and one for the endgame, blended by how much
+/// material is left.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Tapered {
+    mg: i32,
+    eg: i32,
+}
+
+impl Tapered {
+    fn new(mg: i32, eg: i32) -> Tapered {
+        Tapered { mg, eg }
+    }
+
+    fn add(self, other: Tapered) -> Tapered {
+        Tapered::new(self.mg + other.mg, self.eg + other.eg)
+    }
+
+    fn scale(self, n: i32) -> Tapered {
+        Tapered::new(self.mg * n, self.eg * n)
+    }
+
+    fn blend(self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+/// The phase with all minor and major pieces on the board.
+pub const MAX_PHASE: i32 = 24;
+
+const EVALUATED_PIECES: [Piece; 6] = [Pawn, Knight, Bishop, Rook, Queen, King];
+
+/// The middlegame value of `piece` in centipawns.
+pub fn piece_value(piece: Piece) -> i32 {
+    material(piece).mg
+}
+
+fn material(piece: Piece) -> Tapered {
+    match piece {
+        Pawn => Tapered::new(100, 120),
+        Knight => Tapered::new(320, 300),
+        Bishop => Tapered::new(330, 320),
+        Rook => Tapered::new(500, 530),
+        Queen => Tapered::new(950, 950),
+        King => Tapered::new(0, 0),
+    }
+}
+
+/// How much `piece` counts towards the game phase.
+fn phase_weight(piece: Piece) -> i32 {
+    match piece {
+        Knight | Bishop => 1,
+        Rook => 2,
+        Queen => 4,
+        Pawn | King => 0,
+    }
+}
+
+// Piece-square tables from White's point of view, laid out as the board is
+// printed: a8 first, h1 last. Black's are mirrored.
+
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING_MIDDLEGAME_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+fn piece_square(player: Player, piece: Piece, pos: Pos) -> Tapered {
+    let rank = match player {
+        White => 7 - pos.rank,
+        Black => pos.rank,
+    };
+    let index = (rank * 8 + pos.file) as usize;
+    match piece {
+        Pawn => Tapered::new(PAWN_TABLE[index], PAWN_TABLE[index]),
+        Knight => Tapered::new(KNIGHT_TABLE[index], KNIGHT_TABLE[index]),
+        Bishop => Tapered::new(BISHOP_TABLE[index], BISHOP_TABLE[index]),
+        Rook => Tapered::new(ROOK_TABLE[index], ROOK_TABLE[index]),
+        Queen => Tapered::new(QUEEN_TABLE[index], QUEEN_TABLE[index]),
+        King => Tapered::new(KING_MIDDLEGAME_TABLE[index], KING_ENDGAME_TABLE[index]),
+    }
+}
+
+/// Bonus per square a piece attacks that isn't held by its own side.
+fn mobility_weight(piece: Piece) -> Tapered {
+    match piece {
+        Knight => Tapered::new(4, 4),
+        Bishop => Tapered::new(5, 5),
+        Rook => Tapered::new(2, 4),
+        Queen => Tapered::new(1, 2),
+        Pawn | King => Tapered::new(0, 0),
+    }
+}
+
+const DOUBLED_PAWN: Tapered = Tapered { mg: -10, eg: -20 };
+const ISOLATED_PAWN: Tapered = Tapered { mg: -15, eg: -10 };
+
+/// Passed pawn bonus by how many ranks the pawn has advanced.
+const PASSED_PAWN_MG: [i32; 8] = [0, 5, 10, 15, 25, 40, 60, 0];
+const PASSED_PAWN_EG: [i32; 8] = [0, 10, 20, 35, 60, 90, 130, 0];
+
+/// Bonus per pawn sheltering the king, one and two ranks in front of it.
+const PAWN_SHIELD: [i32; 2] = [12, 6];
+/// Penalty per attack on the squares around the king.
+const KING_ZONE_ATTACK: i32 = -8;
+
+const FILE_A: Bitboard = 0x0101_0101_0101_0101;
+
+fn file_mask(file: i32) -> Bitboard {
+    if (0..8).contains(&file) {
+        FILE_A << file
+    } else {
+        0
+    }
+}
+
+/// The squares strictly in front of `pos` from `player`'s point of view, on
+/// its own file and the files either side.
+fn front_span(player: Player, pos: Pos) -> Bitboard {
+    let files = file_mask(pos.file as i32 - 1)
+        | file_mask(pos.file as i32)
+        | file_mask(pos.file as i32 + 1);
+    let ranks = match player {
+        White if pos.rank == 7 => 0,
+        White => !0 << ((pos.rank + 1) * 8),
+        Black => (1 << (pos.rank * 8)) - 1,
+    };
+    files & ranks
+}
+
+/// The squares `piece` of `player` standing on `pos` attacks.
+fn attacks(board: &Board, player: Player, piece: Piece, pos: Pos) -> Bitboard {
+    let occupied = board.occupied();
+    match piece {
+        Pawn => bitboard::pawn_attacks(player, pos),
+        Knight => bitboard::knight_attacks(pos),
+        Bishop => bitboard::bishop_attacks(pos, occupied),
+        Rook => bitboard::rook_attacks(pos, occupied),
+        Queen => bitboard::queen_attacks(pos, occupied),
+        King => bitboard::king_attacks(pos),
+    }
+}
+
+/// One side's score, term by term.
+#[derive(Default)]
+struct Terms {
+    material: Tapered,
+    piece_squares: Tapered,
+    mobility: Tapered,
+    pawn_structure: Tapered,
+    king_safety: Tapered,
+}
+
+fn terms(board: &Board, player: Player) -> Terms {
+    let mut terms = Terms::default();
+    let own = board.player_pieces(player);
+
+    for &piece in EVALUATED_PIECES.iter() {
+        for pos in bitboard::squares(board.pieces(player, piece)) {
+            terms.material = terms.material.add(material(piece));
+            terms.piece_squares = terms.piece_squares.add(piece_square(player, piece, pos));
+            let reach = (attacks(board, player, piece, pos) & !own).count_ones() as i32;
+            terms.mobility = terms.mobility.add(mobility_weight(piece).scale(reach));
+        }
+    }
+
+    let pawns = board.pieces(player, Pawn);
+    let their_pawns = board.pieces(player.other(), Pawn);
+    for pos in bitboard::squares(pawns) {
+        let file = pos.file as i32;
+        if pawns & file_mask(file) & !bit(pos) != 0 {
+            // Counted once for each pawn in the pair.
+            terms.pawn_structure = terms.pawn_structure.add(DOUBLED_PAWN);
+        }
+        if pawns & (file_mask(file - 1) | file_mask(file + 1)) == 0 {
+            terms.pawn_structure = terms.pawn_structure.add(ISOLATED_PAWN);
+        }
+        if their_pawns & front_span(player, pos) == 0 {
+            let advanced = match player {
+                White => pos.rank,
+                Black => 7 - pos.rank,
+            } as usize;
+            let bonus = Tapered::new(PASSED_PAWN_MG[advanced], PASSED_PAWN_EG[advanced]);
+            terms.pawn_structure = terms.pawn_structure.add(bonus);
+        }
+    }
+
+    let king = board.get_king_pos(player);
+    let zone = bitboard::king_attacks(king) | bit(king);
+    let shield = front_span(player, king) & pawns;
+    let shelter: i32 = bitboard::squares(shield)
+        .map(|pos| match (pos.rank as i32 - king.rank as i32).abs() {
+            1 => PAWN_SHIELD[0],
+            2 => PAWN_SHIELD[1],
+            _ => 0,
+        })
+        .sum();
+    let attacks_on_zone: i32 = EVALUATED_PIECES
+        .iter()
+        .flat_map(|&piece| {
+            bitboard::squares(board.pieces(player.other(), piece)).map(move |pos| {
+                (attacks(board, player.other(), piece, pos) & zone).count_ones() as i32
+            })
+        })
+        .sum();
+    // Only matters while there are pieces around to attack the king.
+    terms.king_safety = Tapered::new(shelter + attacks_on_zone * KING_ZONE_ATTACK, 0);
+
+    terms
+}
+
+/// An evaluation split into its terms, each in centipawns from the point of
+/// view of the side to move. The terms add up to the evaluation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakdown {
+    pub player: Player,
+    /// From 0 (bare kings and pawns) to `MAX_PHASE` (all pieces on the board).
+    pub phase: i32,
+    pub material: i32,
+    pub piece_squares: i32,
+    pub mobility: i32,
+    pub pawn_structure: i32,
+    pub king_safety: i32,
+}
+
+impl Breakdown {
+    pub fn total(&self) -> i32 {
+        self.material + self.piece_squares + self.mobility + self.pawn_structure + self.king_safety
+    }
+}
+
+impl fmt::Display for Breakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "material        {:>6}", self.material)?;
+        writeln!(f, "piece squares   {:>6}", self.piece_squares)?;
+        writeln!(f, "mobility        {:>6}", self.mobility)?;
+        writeln!(f, "pawn structure  {:>6}", self.pawn_structure)?;
+        writeln!(f, "king safety     {:>6}", self.king_safety)?;
+        writeln!(f, "total           {:>6}", self.total())?;
+        write!(
+            f,
+            "(centipawns for {}, phase {}/{})",
+            self.player, self.phase, MAX_PHASE
+        )
+    }
+}
+
+/// Evaluate `state` term by term.
+pub fn breakdown(state: &State) -> Breakdown {
+    let board = &state.board;
+    let phase = EVALUATED_PIECES
+        .iter()
+        .map(|&piece| {
+            let count = (board.pieces(White, piece) | board.pieces(Black, piece)).count_ones();
+            count as i32 * phase_weight(piece)
+        })
+        .sum::<i32>()
+        .min(MAX_PHASE);
+
+    let (own, theirs) = (
+        terms(board, state.player),
+        terms(board, state.player.other()),
+    );
+    let term = |own: Tapered, theirs: Tapered| own.blend(phase) - theirs.blend(phase);
+    Breakdown {
+        player: state.player,
+        phase,
+        material: term(own.material, theirs.material),
+        piece_squares: term(own.piece_squares, theirs.piece_squares),
+        mobility: term(own.mobility, theirs.mobility),
+        pawn_structure: term(own.pawn_structure, theirs.pawn_structure),
+        king_safety: term(own.king_safety, theirs.king_safety),
+    }
+}
+
+/// Evaluate `state` in centipawns from the point of view of the side to
+/// move: positive is good for the player whose turn it is.
+pub fn evaluate(state: &State) -> i32 {
+    breakdown(state).total()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::pos::*;
+    use crate::parsing::parse_fen;
+
+    fn eval_fen(fen: &str) -> Breakdown {
+        breakdown(&parse_fen(fen).unwrap())
+    }
+
+    #[test]
+    fn test_initial_position_is_level() {
+        let breakdown = eval_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(breakdown.phase, MAX_PHASE);
+        assert_eq!(breakdown.total(), 0);
+        assert_eq!(breakdown.material, 0);
+    }
+
+    #[test]
+    fn test_side_to_move() {
+        let white = parse_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        let black = parse_fen("4k3/8/8/8/8/8/8/3QK3 b - - 0 1").unwrap();
+        assert!(evaluate(&white) > 800);
+        assert_eq!(evaluate(&white), -evaluate(&black));
+    }
+
+    #[test]
+    fn test_mirrored_positions_score_the_same() {
+        let white = eval_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4");
+        let black = eval_fen("rnbqk2r/pppp1ppp/5n2/2b1p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq - 4 4");
+        assert_eq!(
+            white,
+            Breakdown {
+                player: White,
+                ..black
+            }
+        );
+    }
+
+    #[test]
+    fn test_pawn_structure() {
+        // Doubled and isolated c-pawns against a healthy pair.
+        let breakdown = eval_fen("4k3/6pp/8/8/8/2P5/2P5/4K3 w - - 0 1");
+        assert!(breakdown.pawn_structure < 0);
+
+        // A passed pawn is worth more the further it has gone.
+        let far = eval_fen("4k3/8/1P6/8/8/8/8/4K3 w - - 0 1");
+        let near = eval_fen("4k3/8/8/8/8/1P6/8/4K3 w - - 0 1");
+        assert!(far.pawn_structure > near.pawn_structure);
+        assert!(far.pawn_structure > 0);
+    }
+
+    #[test]
+    fn test_front_span() {
+        assert_eq!(
+            front_span(White, b6),
+            bit(a7) | bit(b7) | bit(c7) | bit(a8) | bit(b8) | bit(c8)
+        );
+        assert_eq!(front_span(Black, h2), bit(g1) | bit(h1));
+        assert_eq!(front_span(White, e8), 0);
+    }
+
+    #[test]
+    fn test_king_safety() {
+        let sheltered = eval_fen("r5k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1");
+        let exposed = eval_fen("r5k1/5ppp/8/8/8/8/PPP5/6K1 w - - 0 1");
+        assert!(sheltered.king_safety > exposed.king_safety);
+    }
+}
@@ -1,37 +1,313 @@
+pub mod antichess;
+pub mod atomic;
+pub mod bitboard;
 pub mod board;
 pub mod castles;
+pub mod chess960;
+pub mod king_of_the_hill;
 pub mod m0ve;
 pub mod move_description;
+pub mod outcome;
 pub mod piece;
 pub mod player;
+pub mod pocket;
 pub mod pos;
 pub mod state;
+pub mod three_check;
+pub mod variant;
+pub mod zobrist;
 
-use self::board::Board;
-use self::castles::Castles;
-use self::player::Player::*;
+use self::m0ve::Move;
+use self::outcome::Outcome;
 use self::state::State;
+use self::variant::{Standard, Variant};
 
-pub struct Game {
+/// A move played in a game.
+#[derive(Debug, PartialEq)]
+pub struct Ply {
+    pub m0ve: Move,
+    /// The move in standard algebraic notation.
+    pub san: String,
+    /// The position the move was played from.
+    pub prior: State,
+}
+
+/// A game played under the rules of `V`, standard chess unless otherwise
+/// given.
+pub struct Game<V: Variant = Standard> {
+    pub variant: V,
     pub state: State,
+    /// The position the game started from.
+    pub initial: State,
+    /// Every move played, including undone moves that can still be redone.
+    plies: Vec<Ply>,
+    /// The number of plies in `plies` leading to `state`.
+    current: usize,
 }
 
 impl Default for Game {
     fn default() -> Self {
-        let board = Board::initial();
-        let player = White;
-        let state = State {
-            board,
-            player,
-            en_passant: None,
-            castling: Castles::initial(),
-        };
-        Game { state }
+        Game::new(Standard)
     }
 }
 
 impl Game {
+    /// A game of standard chess from `state`.
     pub fn with_state(state: State) -> Game {
-        Game { state }
+        Game::with_variant_state(Standard, state)
+    }
+}
+
+impl<V: Variant> Game<V> {
+    /// A game of `variant` from its start position.
+    pub fn new(variant: V) -> Self {
+        let state = variant.start_position();
+        Game::with_variant_state(variant, state)
+    }
+
+    /// A game of `variant` from `state`.
+    pub fn with_variant_state(variant: V, state: State) -> Self {
+        Game {
+            variant,
+            initial: state.clone(),
+            state,
+            plies: vec![],
+            current: 0,
+        }
+    }
+
+    /// The legal moves from the current position.
+    pub fn moves(&self) -> Vec<Move> {
+        self.variant.legal_moves(&self.state)
+    }
+
+    /// Play `m0ve`, which must be one of `self.moves()`. Any undone moves
+    /// are discarded.
+    pub fn play(&mut self, m0ve: Move) {
+        let san = self.variant.san(&self.state, &m0ve);
+        let prior = std::mem::replace(&mut self.state, m0ve.next.clone());
+        self.plies.truncate(self.current);
+        self.plies.push(Ply { m0ve, san, prior });
+        self.current += 1;
+    }
+
+    /// The moves leading to the current position.
+    pub fn plies(&self) -> &[Ply] {
+        &self.plies[..self.current]
+    }
+
+    /// The number of moves leading to the current position.
+    pub fn ply(&self) -> usize {
+        self.current
+    }
+
+    /// Take back the last move. Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        if self.current == 0 {
+            return false;
+        }
+        self.goto_ply(self.current - 1)
+    }
+
+    /// Replay the last undone move. Returns false if there is nothing to
+    /// redo.
+    pub fn redo(&mut self) -> bool {
+        self.goto_ply(self.current + 1)
+    }
+
+    /// Move to the position after `ply` moves, keeping later moves
+    /// available to redo. Returns false if `ply` hasn't been played.
+    pub fn goto_ply(&mut self, ply: usize) -> bool {
+        if ply > self.plies.len() {
+            return false;
+        }
+        self.state = self.state_at(ply);
+        self.current = ply;
+        true
+    }
+
+    /// How the game has ended, if it has, under the rules of its variant.
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.variant.outcome(&self.state, self.repetitions())
+    }
+
+    /// How many times the current position has occurred in this game.
+    fn repetitions(&self) -> usize {
+        self.plies()
+            .iter()
+            .map(|ply| &ply.prior)
+            .chain(std::iter::once(&self.state))
+            .filter(|state| state.same_position(&self.state))
+            .count()
+    }
+
+    fn state_at(&self, ply: usize) -> State {
+        match ply {
+            0 => self.initial.clone(),
+            _ => self.plies[ply - 1].m0ve.next.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::outcome::Termination;
+    use crate::game::player::Player::*;
+    use crate::parsing::{parse_algebraic_notation, parse_fen};
+
+    fn play_sans(game: &mut Game, sans: &[&str]) {
+        for san in sans {
+            let description = parse_algebraic_notation(san).unwrap();
+            game.play(
+                description
+                    .match_moves(&game.state, game.state.gen_moves())
+                    .unwrap(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_play_records_plies() {
+        let mut game = Game::default();
+        play_sans(&mut game, &["e4", "e5", "Nf3"]);
+        let sans: Vec<&str> = game.plies().iter().map(|ply| ply.san.as_str()).collect();
+        assert_eq!(sans, vec!["e4", "e5", "Nf3"]);
+        assert_eq!(game.plies()[0].prior, game.initial);
+        assert_eq!(game.plies()[2].m0ve.next, game.state);
+        assert_eq!(game.ply(), 3);
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut game = Game::default();
+        assert!(!game.undo());
+        play_sans(&mut game, &["e4", "e5"]);
+        let after_e5 = game.state.clone();
+
+        assert!(game.undo());
+        assert_eq!(game.state, game.plies.last().unwrap().prior);
+        assert!(game.undo());
+        assert_eq!(game.state, game.initial);
+        assert!(!game.undo());
+
+        assert!(game.redo());
+        assert!(game.redo());
+        assert_eq!(game.state, after_e5);
+        assert!(!game.redo());
+    }
+
+    #[test]
+    fn test_play_after_undo_discards_redo() {
+        let mut game = Game::default();
+        play_sans(&mut game, &["e4", "e5"]);
+        game.undo();
+        play_sans(&mut game, &["c5"]);
+        assert!(!game.redo());
+        let sans: Vec<&str> = game.plies().iter().map(|ply| ply.san.as_str()).collect();
+        assert_eq!(sans, vec!["e4", "c5"]);
+    }
+
+    #[test]
+    fn test_goto_ply() {
+        let mut game = Game::default();
+        play_sans(&mut game, &["e4", "e5", "Nf3", "Nc6"]);
+        let after_nf3 = game.plies()[2].m0ve.next.clone();
+
+        assert!(game.goto_ply(0));
+        assert_eq!(game.state, game.initial);
+        assert!(game.goto_ply(3));
+        assert_eq!(game.state, after_nf3);
+        assert!(!game.goto_ply(5));
+        assert_eq!(game.ply(), 3);
+    }
+
+    #[test]
+    fn test_outcome_checkmate() {
+        let mut game = Game::default();
+        play_sans(&mut game, &["f3", "e5", "g4"]);
+        assert_eq!(game.outcome(), None);
+        play_sans(&mut game, &["Qh4#"]);
+        let outcome = game.outcome().unwrap();
+        assert_eq!(
+            outcome,
+            Outcome::Win {
+                winner: Black,
+                termination: Termination::Checkmate
+            }
+        );
+        assert_eq!(outcome.result_token(), "0-1");
+        assert_eq!(outcome.to_string(), "Black wins by checkmate");
+    }
+
+    #[test]
+    fn test_outcome_stalemate() {
+        let state = parse_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(
+            Game::with_state(state).outcome(),
+            Some(Outcome::Draw {
+                termination: Termination::Stalemate
+            })
+        );
+    }
+
+    #[test]
+    fn test_outcome_fifty_move_rule() {
+        let state = parse_fen("7k/8/8/8/8/8/R7/K7 w - - 99 80").unwrap();
+        let mut game = Game::with_state(state);
+        assert_eq!(game.outcome(), None);
+        play_sans(&mut game, &["Rb2"]);
+        assert_eq!(
+            game.outcome(),
+            Some(Outcome::Draw {
+                termination: Termination::FiftyMoveRule
+            })
+        );
+    }
+
+    #[test]
+    fn test_outcome_threefold_repetition() {
+        let mut game = Game::default();
+        play_sans(
+            &mut game,
+            &["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1"],
+        );
+        assert_eq!(game.outcome(), None);
+        play_sans(&mut game, &["Ng8"]);
+        assert_eq!(
+            game.outcome(),
+            Some(Outcome::Draw {
+                termination: Termination::ThreefoldRepetition
+            })
+        );
+    }
+
+    #[test]
+    fn test_outcome_threefold_repetition_after_double_push() {
+        // No pawn can take on e3, so the position after 1. e4 comes back.
+        let mut game = Game::default();
+        play_sans(
+            &mut game,
+            &["e4", "Nf6", "Nf3", "Ng8", "Ng1", "Nf6", "Nf3", "Ng8"],
+        );
+        assert_eq!(game.outcome(), None);
+        play_sans(&mut game, &["Ng1"]);
+        assert_eq!(
+            game.outcome(),
+            Some(Outcome::Draw {
+                termination: Termination::ThreefoldRepetition
+            })
+        );
+    }
+
+    #[test]
+    fn test_outcome_insufficient_material() {
+        let state = parse_fen("8/8/4k3/8/8/3K4/8/6n1 w - - 0 1").unwrap();
+        assert_eq!(
+            Game::with_state(state).outcome(),
+            Some(Outcome::Draw {
+                termination: Termination::InsufficientMaterial
+            })
+        );
     }
 }
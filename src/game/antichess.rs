@@ -0,0 +1,185 @@
+//! Antichess, also known as losing chess or giveaway: whoever has a capture
+//! must make one, the king is an ordinary piece that can be captured and
+//! promoted to, there is no castling, and a player wins by losing all their
+//! pieces or by having no legal move.
+
+use crate::game::board::Board;
+use crate::game::castles::Castles;
+use crate::game::m0ve::{Action, Move};
+use crate::game::outcome::{Outcome, Termination};
+use crate::game::piece::Piece::*;
+use crate::game::player::Player;
+use crate::game::state::State;
+use crate::game::variant::Variant;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Antichess;
+
+/// The same promotion as `m0ve`, to a king instead.
+fn promote_to_king(player: Player, m0ve: &Move) -> Option<Move> {
+    let (from, to) = match m0ve.action {
+        Action::Promotion {
+            from,
+            to,
+            piece: Queen,
+        } => (from, to),
+        _ => return None,
+    };
+    let next = &m0ve.next;
+    Some(Move {
+        action: Action::Promotion {
+            from,
+            to,
+            piece: King,
+        },
+        next: State::new(
+            next.board.place_piece(to, Some((player, King))),
+            next.player,
+            next.en_passant,
+            next.castling,
+            next.halfmove_clock,
+            next.fullmove_number,
+        ),
+        captured: m0ve.captured,
+    })
+}
+
+impl Variant for Antichess {
+    fn name(&self) -> &'static str {
+        "Antichess"
+    }
+
+    fn start_position(&self) -> State {
+        State::new(Board::initial(), Player::White, None, Castles::none(), 0, 1)
+    }
+
+    fn legal_moves(&self, state: &State) -> Vec<Move> {
+        let mut moves: Vec<Move> = state
+            .pseudo_legal_moves()
+            .into_iter()
+            .filter(|m0ve| !matches!(m0ve.action, Action::Castle { .. }))
+            .flat_map(|m0ve| {
+                let king = promote_to_king(state.player, &m0ve);
+                std::iter::once(m0ve).chain(king)
+            })
+            .collect();
+        if moves.iter().any(|m0ve| m0ve.captured.is_some()) {
+            moves.retain(|m0ve| m0ve.captured.is_some());
+        }
+        moves
+    }
+
+    fn outcome(&self, state: &State, repetitions: usize) -> Option<Outcome> {
+        if state.board.player_pieces(state.player) == 0 {
+            return Some(Outcome::Win {
+                winner: state.player,
+                termination: Termination::AllPiecesLost,
+            });
+        }
+        if self.legal_moves(state).is_empty() {
+            return Some(Outcome::Win {
+                winner: state.player,
+                termination: Termination::Stalemate,
+            });
+        }
+
+        let termination = if state.halfmove_clock >= 100 {
+            Termination::FiftyMoveRule
+        } else if repetitions >= 3 {
+            Termination::ThreefoldRepetition
+        } else {
+            return None;
+        };
+        Some(Outcome::Draw { termination })
+    }
+
+    /// There is no check: the king can be taken like any other piece.
+    fn in_check(&self, _state: &State) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Game;
+    use crate::parsing::{parse_algebraic_notation, parse_fen};
+
+    fn coordinates(state: &State) -> Vec<String> {
+        let mut moves: Vec<_> = Antichess
+            .legal_moves(state)
+            .iter()
+            .map(|m0ve| m0ve.to_coordinate(state))
+            .collect();
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn test_captures_are_compulsory() {
+        let state = parse_fen("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(coordinates(&state), vec!["e4d5"]);
+
+        // The king can be taken, and can take.
+        let state = parse_fen("8/8/8/8/8/8/3k4/4K3 w - - 0 1").unwrap();
+        assert_eq!(coordinates(&state), vec!["e1d2"]);
+    }
+
+    #[test]
+    fn test_promotions_and_castling() {
+        let state = parse_fen("8/P7/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        let moves = coordinates(&state);
+        for promotion in &["a7a8q", "a7a8r", "a7a8b", "a7a8n", "a7a8k"] {
+            assert!(moves.contains(&promotion.to_string()));
+        }
+        assert!(!moves.contains(&"e1g1".to_string()));
+
+        let mut game = Game::with_variant_state(Antichess, state);
+        let promotion = game
+            .moves()
+            .into_iter()
+            .find(|m0ve| m0ve.to_coordinate(&game.state) == "a7a8k")
+            .unwrap();
+        game.play(promotion);
+        assert_eq!(game.plies()[0].san, "a8=K");
+        assert_eq!(game.state.to_fen(), "K7/8/8/8/8/8/8/R3K2R b KQ - 0 1");
+    }
+
+    #[test]
+    fn test_san_after_a_king_is_taken() {
+        // Both rooks can take the king, and White has none of its own.
+        let state = parse_fen("8/8/8/8/8/3p4/8/R2k3R w - - 0 1").unwrap();
+        let mut game = Game::with_variant_state(Antichess, state);
+        for san in &["Raxd1", "d2", "Rxd2"] {
+            let desc = parse_algebraic_notation(san).unwrap();
+            game.play(desc.match_moves(&game.state, game.moves()).unwrap());
+        }
+        let sans: Vec<_> = game.plies().iter().map(|ply| ply.san.as_str()).collect();
+        assert_eq!(sans, vec!["Raxd1", "d2", "Rxd2"]);
+    }
+
+    #[test]
+    fn test_outcome() {
+        // Black has lost all their pieces.
+        let state = parse_fen("8/8/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(
+            Antichess.outcome(&state, 1),
+            Some(Outcome::Win {
+                winner: Player::Black,
+                termination: Termination::AllPiecesLost
+            })
+        );
+
+        // White's pawn is blocked.
+        let state = parse_fen("8/8/8/8/8/4p3/4P3/8 w - - 0 1").unwrap();
+        assert_eq!(
+            Antichess.outcome(&state, 1),
+            Some(Outcome::Win {
+                winner: Player::White,
+                termination: Termination::Stalemate
+            })
+        );
+
+        assert_eq!(Game::new(Antichess).outcome(), None);
+    }
+}
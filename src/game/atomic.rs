@@ -0,0 +1,257 @@
+//! Atomic chess: a capture sets off an explosion that removes the capturing
+//! piece, the captured one and every piece but pawns on the eight squares
+//! around. Kings can't capture, since they would blow themselves up, and a
+//! side wins by exploding the enemy king, whether or not it is in check.
+
+use crate::game::bitboard::{self, Bitboard};
+use crate::game::board::Board;
+use crate::game::m0ve::{Action, Move};
+use crate::game::outcome::{Outcome, Termination};
+use crate::game::piece::Piece::*;
+use crate::game::player::Player::{self, *};
+use crate::game::pos::Pos;
+use crate::game::state::State;
+use crate::game::variant::{Standard, Variant};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Atomic;
+
+/// Is `pos` attacked by `player` as far as the other player's king is
+/// concerned? Kings never attack, and a king next to the enemy king is
+/// safe, as capturing it would blow up the capturer's king too.
+fn attacked(board: &Board, pos: Pos, player: Player) -> bool {
+    let kings = board.pieces(player, King);
+    bitboard::king_attacks(pos) & kings == bitboard::EMPTY
+        && board.attackers(pos, player) & !kings != bitboard::EMPTY
+}
+
+fn has_king(board: &Board, player: Player) -> bool {
+    board.pieces(player, King) != bitboard::EMPTY
+}
+
+/// Is `player`'s king attacked on `board`?
+fn checked(board: &Board, player: Player) -> bool {
+    has_king(board, player) && attacked(board, board.get_king_pos(player), player.other())
+}
+
+/// Set off the explosion of a capture on `to`, from the position reached
+/// by the capture.
+fn explode(next: &State, to: Pos) -> State {
+    let pawns = next.board.pieces(White, Pawn) | next.board.pieces(Black, Pawn);
+    let blast: Bitboard = bitboard::bit(to) | (bitboard::king_attacks(to) & !pawns);
+    let mut board = next.board.clone();
+    let mut castling = next.castling;
+    for pos in bitboard::squares(blast & next.board.occupied()) {
+        board = board.place_piece(pos, None);
+        castling = castling.after_move(White, pos).after_move(Black, pos);
+    }
+    State::new(
+        board,
+        next.player,
+        None,
+        castling,
+        next.halfmove_clock,
+        next.fullmove_number,
+    )
+}
+
+impl Atomic {
+    /// Can the current player castle with `m0ve`? The king may not leave,
+    /// cross or land on an attacked square.
+    fn can_castle(state: &State, m0ve: &Move) -> bool {
+        let castleside = match m0ve.action {
+            Action::Castle { castleside } => castleside,
+            _ => return true,
+        };
+        let king_pos = state.board.get_king_pos(state.player);
+        let tracks = state.castling.king_tracks(state.player, castleside);
+        std::iter::once(&king_pos)
+            .chain(tracks.iter())
+            .all(|&pos| !attacked(&state.board, pos, state.player.other()))
+    }
+}
+
+impl Variant for Atomic {
+    fn name(&self) -> &'static str {
+        "Atomic"
+    }
+
+    fn start_position(&self) -> State {
+        Standard.start_position()
+    }
+
+    fn legal_moves(&self, state: &State) -> Vec<Move> {
+        let player = state.player;
+        if !has_king(&state.board, player) || !has_king(&state.board, player.other()) {
+            return vec![];
+        }
+        state
+            .pseudo_legal_moves()
+            .into_iter()
+            .filter(|m0ve| match (m0ve.action, m0ve.captured) {
+                (Action::Simple { from, .. }, Some(_)) => {
+                    state.board.piece_at(from) != Some((player, King))
+                }
+                _ => true,
+            })
+            .map(|m0ve| match (m0ve.action, m0ve.captured) {
+                (Action::Simple { to, .. }, Some(_)) | (Action::Promotion { to, .. }, Some(_)) => {
+                    let next = explode(&m0ve.next, to);
+                    Move { next, ..m0ve }
+                }
+                _ => m0ve,
+            })
+            .filter(|m0ve| {
+                let board = &m0ve.next.board;
+                has_king(board, player)
+                    && (!has_king(board, player.other()) || !checked(board, player))
+                    && Atomic::can_castle(state, m0ve)
+            })
+            .collect()
+    }
+
+    fn outcome(&self, state: &State, repetitions: usize) -> Option<Outcome> {
+        for &player in &[White, Black] {
+            if !has_king(&state.board, player) {
+                return Some(Outcome::Win {
+                    winner: player.other(),
+                    termination: Termination::KingExploded,
+                });
+            }
+        }
+        if self.legal_moves(state).is_empty() {
+            return Some(if self.in_check(state) {
+                Outcome::Win {
+                    winner: state.player.other(),
+                    termination: Termination::Checkmate,
+                }
+            } else {
+                Outcome::Draw {
+                    termination: Termination::Stalemate,
+                }
+            });
+        }
+
+        // With anything else on the board, a king can still be exploded.
+        let bare_kings = state.board.occupied().count_ones() == 2;
+        let termination = if state.halfmove_clock >= 100 {
+            Termination::FiftyMoveRule
+        } else if repetitions >= 3 {
+            Termination::ThreefoldRepetition
+        } else if bare_kings {
+            Termination::InsufficientMaterial
+        } else {
+            return None;
+        };
+        Some(Outcome::Draw { termination })
+    }
+
+    fn in_check(&self, state: &State) -> bool {
+        has_king(&state.board, state.player.other()) && checked(&state.board, state.player)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Game;
+    use crate::parsing::pgn::{parse_pgn, Pgn};
+    use crate::parsing::{parse_algebraic_notation, parse_fen};
+
+    fn play(state: &State, coordinate: &str) -> Move {
+        Atomic
+            .legal_moves(state)
+            .into_iter()
+            .find(|m0ve| m0ve.to_coordinate(state) == coordinate)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_explosion() {
+        let state = parse_fen("4k3/8/8/2npb3/3Q4/2P5/8/4K3 w - - 0 1").unwrap();
+        let next = play(&state, "d4d5").next;
+        // The queen, the pawn it took and the knight and bishop around go;
+        // the pawn on c3 is out of range.
+        assert_eq!(next.to_fen(), "4k3/8/8/8/8/2P5/8/4K3 b - - 0 1");
+
+        // Blowing up a rook on its home square takes the right to castle
+        // with it.
+        let state = parse_fen("r3k2r/8/8/8/8/8/8/R3K1NR w KQkq - 0 1").unwrap();
+        let next = play(&state, "h1h8").next;
+        assert_eq!(next.to_fen(), "r3k3/8/8/8/8/8/8/R3K1N1 b Qq - 0 1");
+    }
+
+    #[test]
+    fn test_kings_never_capture() {
+        let state = parse_fen("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1").unwrap();
+        assert!(Atomic
+            .legal_moves(&state)
+            .iter()
+            .all(|m0ve| m0ve.captured.is_none()));
+    }
+
+    #[test]
+    fn test_check() {
+        // Next to the enemy king, a king can't be captured.
+        let state = parse_fen("8/8/8/8/8/8/4kr2/4K3 w - - 0 1").unwrap();
+        assert!(state.in_check());
+        assert!(!Atomic.in_check(&state));
+
+        // A capture that would blow up the capturer's own king is illegal,
+        // unless it blows up the enemy king as well.
+        let state = parse_fen("4k3/4r3/8/8/8/8/3Pp3/4K3 w - - 0 1").unwrap();
+        assert!(Atomic
+            .legal_moves(&state)
+            .iter()
+            .all(|m0ve| m0ve.to_coordinate(&state) != "d2e3"));
+        let state = parse_fen("8/8/8/8/8/4kp2/4R3/4K3 w - - 0 1").unwrap();
+        let next = play(&state, "e2e3").next;
+        assert_eq!(
+            Atomic.outcome(&next, 1),
+            Some(Outcome::Win {
+                winner: White,
+                termination: Termination::KingExploded
+            })
+        );
+    }
+
+    #[test]
+    fn test_game() {
+        let mut game = Game::new(Atomic);
+        for coordinate in &["g1f3", "a7a6", "f3g5", "a6a5", "g5f7"] {
+            let m0ve = play(&game.state, coordinate);
+            game.play(m0ve);
+        }
+        assert_eq!(
+            game.outcome(),
+            Some(Outcome::Win {
+                winner: White,
+                termination: Termination::KingExploded
+            })
+        );
+        let sans: Vec<_> = game.plies().iter().map(|ply| ply.san.as_str()).collect();
+        assert_eq!(sans, vec!["Nf3", "a6", "Ng5", "a5", "Nxf7"]);
+        assert_eq!(
+            game.outcome().unwrap().to_string(),
+            "White wins by exploding the king"
+        );
+    }
+
+    #[test]
+    fn test_san_captures() {
+        // The knight explodes, so it's only on f7 before the move.
+        let mut game = Game::new(Atomic);
+        for san in &["Nf3", "a6", "Ng5", "a5", "Nxf7"] {
+            let description = parse_algebraic_notation(san).unwrap();
+            let m0ve = description.match_moves(&game.state, game.moves()).unwrap();
+            game.play(m0ve);
+        }
+        assert!(game.outcome().is_some());
+
+        let pgn = Pgn::from_game(&game, &[]);
+        assert_eq!(pgn.tag("Variant"), Some("Atomic"));
+        let replayed = parse_pgn(&pgn.to_string()).unwrap().replay().unwrap();
+        assert_eq!(replayed.len(), 5);
+        assert_eq!(replayed.last().unwrap().next, game.state);
+    }
+}
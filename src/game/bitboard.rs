@@ -0,0 +1,222 @@
+//! Square sets packed into a `u64`, one bit per square with a1 as bit 0
+//! and h8 as bit 63, plus precomputed attack tables.
+
+use crate::game::player::Player;
+use crate::game::pos::Pos;
+
+pub type Bitboard = u64;
+
+pub const EMPTY: Bitboard = 0;
+
+/// The first and eighth ranks.
+pub const BACK_RANKS: Bitboard = 0xFF00_0000_0000_00FF;
+
+/// The four centre squares, d4, e4, d5 and e5.
+pub const CENTRE: Bitboard = 0x0000_0018_1800_0000;
+
+/// The bitboard with only `pos` set.
+pub fn bit(pos: Pos) -> Bitboard {
+    1 << pos.to_offset(8)
+}
+
+/// The position of square index `index`.
+pub fn pos_at(index: u32) -> Pos {
+    Pos {
+        rank: (index / 8) as u8,
+        file: (index % 8) as u8,
+    }
+}
+
+/// Iterate over the positions set in a bitboard, from a1 to h8.
+pub fn squares(bitboard: Bitboard) -> Squares {
+    Squares(bitboard)
+}
+
+pub struct Squares(Bitboard);
+
+impl Iterator for Squares {
+    type Item = Pos;
+
+    fn next(&mut self) -> Option<Pos> {
+        if self.0 == 0 {
+            return None;
+        }
+        let index = self.0.trailing_zeros();
+        self.0 &= self.0 - 1;
+        Some(pos_at(index))
+    }
+}
+
+// Attack tables are built at compile time by stepping from each square
+// by a set of (rank, file) offsets.
+
+const fn step_attacks(steps: &[(i8, i8)]) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let rank = (square / 8) as i8;
+        let file = (square % 8) as i8;
+        let mut i = 0;
+        while i < steps.len() {
+            let (to_rank, to_file) = (rank + steps[i].0, file + steps[i].1);
+            if to_rank >= 0 && to_rank < 8 && to_file >= 0 && to_file < 8 {
+                table[square] |= 1 << (to_rank * 8 + to_file);
+            }
+            i += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+const fn ray_attacks(step: (i8, i8)) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let mut rank = (square / 8) as i8 + step.0;
+        let mut file = (square % 8) as i8 + step.1;
+        while rank >= 0 && rank < 8 && file >= 0 && file < 8 {
+            table[square] |= 1 << (rank * 8 + file);
+            rank += step.0;
+            file += step.1;
+        }
+        square += 1;
+    }
+    table
+}
+
+const KNIGHT_ATTACKS: [Bitboard; 64] = step_attacks(&[
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+]);
+
+const KING_ATTACKS: [Bitboard; 64] = step_attacks(&[
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+]);
+
+const WHITE_PAWN_ATTACKS: [Bitboard; 64] = step_attacks(&[(1, -1), (1, 1)]);
+const BLACK_PAWN_ATTACKS: [Bitboard; 64] = step_attacks(&[(-1, -1), (-1, 1)]);
+
+// Rays pointing towards higher square indices; the nearest blocker is the
+// lowest set bit.
+const NORTH: [Bitboard; 64] = ray_attacks((1, 0));
+const EAST: [Bitboard; 64] = ray_attacks((0, 1));
+const NORTH_EAST: [Bitboard; 64] = ray_attacks((1, 1));
+const NORTH_WEST: [Bitboard; 64] = ray_attacks((1, -1));
+
+// Rays pointing towards lower square indices; the nearest blocker is the
+// highest set bit.
+const SOUTH: [Bitboard; 64] = ray_attacks((-1, 0));
+const WEST: [Bitboard; 64] = ray_attacks((0, -1));
+const SOUTH_WEST: [Bitboard; 64] = ray_attacks((-1, -1));
+const SOUTH_EAST: [Bitboard; 64] = ray_attacks((-1, 1));
+
+fn positive_ray(rays: &[Bitboard; 64], square: usize, occupied: Bitboard) -> Bitboard {
+    let ray = rays[square];
+    let blockers = ray & occupied;
+    if blockers == 0 {
+        ray
+    } else {
+        ray ^ rays[blockers.trailing_zeros() as usize]
+    }
+}
+
+fn negative_ray(rays: &[Bitboard; 64], square: usize, occupied: Bitboard) -> Bitboard {
+    let ray = rays[square];
+    let blockers = ray & occupied;
+    if blockers == 0 {
+        ray
+    } else {
+        ray ^ rays[63 - blockers.leading_zeros() as usize]
+    }
+}
+
+pub fn knight_attacks(pos: Pos) -> Bitboard {
+    KNIGHT_ATTACKS[pos.to_offset(8)]
+}
+
+pub fn king_attacks(pos: Pos) -> Bitboard {
+    KING_ATTACKS[pos.to_offset(8)]
+}
+
+/// The squares a pawn of `player` standing on `pos` captures on.
+pub fn pawn_attacks(player: Player, pos: Pos) -> Bitboard {
+    match player {
+        Player::White => WHITE_PAWN_ATTACKS[pos.to_offset(8)],
+        Player::Black => BLACK_PAWN_ATTACKS[pos.to_offset(8)],
+    }
+}
+
+/// Squares a rook on `pos` attacks, stopping at (and including) the first
+/// occupied square in each direction.
+pub fn rook_attacks(pos: Pos, occupied: Bitboard) -> Bitboard {
+    let square = pos.to_offset(8);
+    positive_ray(&NORTH, square, occupied)
+        | positive_ray(&EAST, square, occupied)
+        | negative_ray(&SOUTH, square, occupied)
+        | negative_ray(&WEST, square, occupied)
+}
+
+/// Squares a bishop on `pos` attacks, stopping at (and including) the first
+/// occupied square in each direction.
+pub fn bishop_attacks(pos: Pos, occupied: Bitboard) -> Bitboard {
+    let square = pos.to_offset(8);
+    positive_ray(&NORTH_EAST, square, occupied)
+        | positive_ray(&NORTH_WEST, square, occupied)
+        | negative_ray(&SOUTH_WEST, square, occupied)
+        | negative_ray(&SOUTH_EAST, square, occupied)
+}
+
+pub fn queen_attacks(pos: Pos, occupied: Bitboard) -> Bitboard {
+    rook_attacks(pos, occupied) | bishop_attacks(pos, occupied)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::pos::*;
+
+    fn bits(positions: &[Pos]) -> Bitboard {
+        positions.iter().fold(EMPTY, |bb, &pos| bb | bit(pos))
+    }
+
+    #[test]
+    fn test_squares() {
+        let positions: Vec<Pos> = squares(bits(&[h8, a1, e4])).collect();
+        assert_eq!(positions, vec![a1, e4, h8]);
+        assert_eq!(squares(EMPTY).next(), None);
+    }
+
+    #[test]
+    fn test_step_attacks() {
+        assert_eq!(knight_attacks(a1), bits(&[b3, c2]));
+        assert_eq!(knight_attacks(d4).count_ones(), 8);
+        assert_eq!(king_attacks(h8), bits(&[g8, g7, h7]));
+        assert_eq!(pawn_attacks(Player::White, a2), bits(&[b3]));
+        assert_eq!(pawn_attacks(Player::Black, e7), bits(&[d6, f6]));
+    }
+
+    #[test]
+    fn test_sliding_attacks() {
+        assert_eq!(rook_attacks(a1, EMPTY).count_ones(), 14);
+        assert_eq!(
+            rook_attacks(d4, bits(&[d6, b4, d1])),
+            bits(&[d5, d6, c4, b4, e4, f4, g4, h4, d3, d2, d1])
+        );
+        assert_eq!(bishop_attacks(c1, bits(&[e3])), bits(&[b2, a3, d2, e3]));
+        assert_eq!(queen_attacks(d4, EMPTY).count_ones(), 27);
+    }
+}
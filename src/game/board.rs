@@ -1,3 +1,4 @@
+use crate::game::bitboard::{self, bit, Bitboard};
 use crate::game::{piece::Piece, piece::Piece::*, player::Player, player::Player::*, pos::Pos};
 use ansi_term::Colour;
 use ansi_term::Style;
@@ -10,15 +11,27 @@ pub type BoardMatrix = Vec<Square>;
 
 const NSIZE: u8 = 8;
 
-/// 3x3 board
-#[derive(Debug, Clone, PartialEq)]
+/// 8x8 board, stored as one bitboard per piece type and per player.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Board {
-    inner: BoardMatrix,
+    /// Indexed by `Piece as usize`.
+    pieces: [Bitboard; 6],
+    /// Indexed by `player_index`.
+    players: [Bitboard; 2],
 }
 
+fn player_index(player: Player) -> usize {
+    match player {
+        White => 0,
+        Black => 1,
+    }
+}
+
+const ALL_PIECES: [Piece; 6] = [Pawn, Bishop, King, Rook, Queen, Knight];
+
 impl Board {
     pub fn initial() -> Board {
-        let inner = vec![
+        let inner: BoardMatrix = vec![
             // rank 1
             Some((White, Rook)),
             Some((White, Knight)),
@@ -92,13 +105,21 @@ impl Board {
             Some((Black, Knight)),
             Some((Black, Rook)),
         ];
-        Board { inner }
+        Board::from_squares(&inner)
     }
 
+    /// Build a board from squares listed from a1 to h8, rank by rank.
     pub fn from_squares(squares: &[Square]) -> Board {
-        Board {
-            inner: squares.to_vec(),
-        }
+        let empty = Board {
+            pieces: [0; 6],
+            players: [0; 2],
+        };
+        squares
+            .iter()
+            .enumerate()
+            .fold(empty, |board, (index, &square)| {
+                board.place_piece(bitboard::pos_at(index as u32), square)
+            })
     }
 
     pub fn coords(&self) -> Vec<Pos> {
@@ -109,7 +130,49 @@ impl Board {
     }
 
     pub fn piece_at(&self, pos: Pos) -> Square {
-        self.inner[pos.to_offset(NSIZE)]
+        let mask = bit(pos);
+        let player = if self.players[0] & mask != 0 {
+            White
+        } else if self.players[1] & mask != 0 {
+            Black
+        } else {
+            return None;
+        };
+        ALL_PIECES
+            .iter()
+            .find(|&&piece| self.pieces[piece as usize] & mask != 0)
+            .map(|&piece| (player, piece))
+    }
+
+    /// All occupied squares.
+    pub fn occupied(&self) -> Bitboard {
+        self.players[0] | self.players[1]
+    }
+
+    /// The squares occupied by `player`'s pieces.
+    pub fn player_pieces(&self, player: Player) -> Bitboard {
+        self.players[player_index(player)]
+    }
+
+    /// The squares occupied by `player`'s pieces of type `piece`.
+    pub fn pieces(&self, player: Player, piece: Piece) -> Bitboard {
+        self.players[player_index(player)] & self.pieces[piece as usize]
+    }
+
+    /// Is `pos` attacked by any of `player`'s pieces?
+    pub fn is_attacked(&self, pos: Pos, player: Player) -> bool {
+        self.attackers(pos, player) != 0
+    }
+
+    /// The squares of `player`'s pieces that attack `pos`.
+    pub fn attackers(&self, pos: Pos, player: Player) -> Bitboard {
+        let occupied = self.occupied();
+        let queens = self.pieces(player, Queen);
+        (bitboard::knight_attacks(pos) & self.pieces(player, Knight))
+            | (bitboard::king_attacks(pos) & self.pieces(player, King))
+            | (bitboard::pawn_attacks(player.other(), pos) & self.pieces(player, Pawn))
+            | (bitboard::bishop_attacks(pos, occupied) & (self.pieces(player, Bishop) | queens))
+            | (bitboard::rook_attacks(pos, occupied) & (self.pieces(player, Rook) | queens))
     }
 
     pub fn all_empty(&self, positions: &[Pos]) -> bool {
@@ -119,25 +182,30 @@ impl Board {
     /// Find the position of the king for `player`. Panics if no king is
     /// found.
     pub fn get_king_pos(&self, player: Player) -> Pos {
-        for coord in self.coords() {
-            if let Some((plyr, King)) = self.piece_at(coord) {
-                if plyr == player {
-                    return coord;
-                }
-            }
+        match self.pieces(player, King) {
+            0 => panic!("No king on the board"),
+            kings => bitboard::pos_at(kings.trailing_zeros()),
         }
-
-        panic!("No king on the board")
     }
 
     /// Move the piece at `from_pos` to `to_pos` and return the new board.
     pub fn move_piece(&self, from: Pos, to: Pos) -> Board {
-        let mut new_inner = self.inner.clone();
         let from_piece = self.piece_at(from);
-        new_inner[from.to_offset(NSIZE)] = None;
-        new_inner[to.to_offset(NSIZE)] = from_piece;
+        self.place_piece(from, None).place_piece(to, from_piece)
+    }
 
-        Board { inner: new_inner }
+    /// Replace whatever is at `pos` with `square` and return the new board.
+    pub fn place_piece(&self, pos: Pos, square: Square) -> Board {
+        let mask = bit(pos);
+        let mut board = self.clone();
+        for bb in board.pieces.iter_mut().chain(board.players.iter_mut()) {
+            *bb &= !mask;
+        }
+        if let Some((player, piece)) = square {
+            board.pieces[piece as usize] |= mask;
+            board.players[player_index(player)] |= mask;
+        }
+        board
     }
 
     pub fn str(&self) -> String {
@@ -169,7 +237,7 @@ impl Board {
                     rank: rowi,
                     file: coli,
                 };
-                let square = self.inner[pos.to_offset(NSIZE)];
+                let square = self.piece_at(pos);
                 let color = if (rowi + coli) % 2 == 0 {
                     Colour::RGB(200, 200, 200)
                 } else {
@@ -190,9 +258,9 @@ impl Board {
                         .paint(format!("{} ", piece_str(square)))
                 ));
             }
-            buf.push_str("\n");
+            buf.push('\n');
         }
-        buf.push_str(" ");
+        buf.push(' ');
         for coli in 0..NSIZE {
             buf.push_str(&format!("{} ", (coli + b'A') as char));
         }
@@ -219,10 +287,10 @@ mod test {
     fn test_all_empty() {
         let board = Board::initial();
 
-        assert_eq!(board.all_empty(&[e2]), false);
-        assert_eq!(board.all_empty(&[b3]), true);
-        assert_eq!(board.all_empty(&[e2, b3]), false);
-        assert_eq!(board.all_empty(&[e3, b3]), true);
+        assert!(!board.all_empty(&[e2]));
+        assert!(board.all_empty(&[b3]));
+        assert!(!board.all_empty(&[e2, b3]));
+        assert!(board.all_empty(&[e3, b3]));
     }
 
     #[test]
@@ -243,4 +311,28 @@ mod test {
         assert_eq!(next_board.piece_at(e2), None);
         assert_eq!(next_board.piece_at(e3), Some((White, Pawn)));
     }
+
+    #[test]
+    fn test_place_piece() {
+        let board = Board::initial();
+
+        let next_board = board.place_piece(e2, Some((White, Queen)));
+        assert_eq!(next_board.piece_at(e2), Some((White, Queen)));
+
+        let next_board = next_board.place_piece(e1, None);
+        assert_eq!(next_board.piece_at(e1), None);
+    }
+
+    #[test]
+    fn test_attackers() {
+        let board = Board::initial();
+
+        assert_eq!(board.attackers(f3, White), bit(g1) | bit(e2) | bit(g2));
+        assert!(!board.is_attacked(e4, White));
+        assert!(board.is_attacked(e6, Black));
+        assert!(!board.is_attacked(e1, Black));
+
+        let board = board.move_piece(f2, f3).move_piece(d8, h4);
+        assert!(board.is_attacked(e1, Black));
+    }
 }
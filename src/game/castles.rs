@@ -1,9 +1,7 @@
-#![allow(non_upper_case_globals)]
-
 use crate::game::board::Board;
+use crate::game::piece::Piece::{King, Rook};
 use crate::game::player::Player;
 use crate::game::pos::Pos;
-use crate::game::pos::*;
 use std::fmt;
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -12,10 +10,39 @@ pub struct CastleAbility {
     pub queenside: bool,
 }
 
+/// The files the king and rooks start on, which castling moves them from.
+/// In standard chess they are e, h and a; in Chess960 they can be anywhere
+/// on the back rank with the king between the rooks.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct CastleFiles {
+    pub king: u8,
+    pub kingside: u8,
+    pub queenside: u8,
+}
+
+impl CastleFiles {
+    pub fn standard() -> Self {
+        CastleFiles {
+            king: 4,
+            kingside: 7,
+            queenside: 0,
+        }
+    }
+
+    /// The file of the rook castling on `castleside`.
+    pub fn rook(self, castleside: Castleside) -> u8 {
+        match castleside {
+            Castleside::Kingside => self.kingside,
+            Castleside::Queenside => self.queenside,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct Castles {
     pub white: CastleAbility,
     pub black: CastleAbility,
+    pub files: CastleFiles,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -44,6 +71,28 @@ impl Castles {
                 kingside: true,
                 queenside: true,
             },
+            files: CastleFiles::standard(),
+        }
+    }
+
+    /// No castling rights at all.
+    pub fn none() -> Self {
+        let ability = CastleAbility {
+            kingside: false,
+            queenside: false,
+        };
+        Castles {
+            white: ability,
+            black: ability,
+            files: CastleFiles::standard(),
+        }
+    }
+
+    /// Full castling rights for a king and rooks starting on `files`.
+    pub fn with_files(files: CastleFiles) -> Self {
+        Castles {
+            files,
+            ..Castles::initial()
         }
     }
 
@@ -85,51 +134,115 @@ impl Castles {
 
     /// If `player` moves a piece at `pos`, what's the next castling state?
     pub fn after_move(self, player: Player, pos: Pos) -> Self {
-        match player {
-            Player::White => match pos {
-                e1 => self.without(player, true, true),
-                h1 => self.without(player, true, false),
-                a1 => self.without(player, false, true),
-                _ => self,
-            },
-            Player::Black => match pos {
-                e8 => self.without(player, true, true),
-                h8 => self.without(player, true, false),
-                a8 => self.without(player, false, true),
-                _ => self,
-            },
+        if pos.rank != back_rank(player) {
+            return self;
+        }
+        match pos.file {
+            file if file == self.files.king => self.without(player, true, true),
+            file if file == self.files.kingside => self.without(player, true, false),
+            file if file == self.files.queenside => self.without(player, false, true),
+            _ => self,
+        }
+    }
+
+    /// If `player` captures on `pos`, what's the next castling state? Taking
+    /// a rook on its home square removes the opponent's right to castle with it.
+    pub fn after_capture(self, player: Player, pos: Pos) -> Self {
+        let opponent = player.other();
+        if pos.rank != back_rank(opponent) {
+            return self;
+        }
+        match pos.file {
+            file if file == self.files.kingside => self.without(opponent, true, false),
+            file if file == self.files.queenside => self.without(opponent, false, true),
+            _ => self,
         }
     }
 
     /// Castle. Returns the new castling and board state.
     pub fn castle(self, board: &Board, player: Player, castleside: Castleside) -> (Board, Self) {
         let next_castling = self.without(player, true, true);
-        let next_board = match (player, castleside) {
-            (Player::White, Castleside::Kingside) => board.move_piece(e1, g1).move_piece(h1, f1),
-            (Player::White, Castleside::Queenside) => board.move_piece(e1, c1).move_piece(a1, d1),
-            (Player::Black, Castleside::Kingside) => board.move_piece(e8, g8).move_piece(h8, f8),
-            (Player::Black, Castleside::Queenside) => board.move_piece(e8, c8).move_piece(a8, d8),
-        };
+        let (king_from, king_to) = self.king_squares(player, castleside);
+        let (rook_from, rook_to) = self.rook_squares(player, castleside);
+        // In Chess960 the king may land where the rook was, or the other
+        // way around, so both are lifted before either is put down.
+        let next_board = board
+            .place_piece(king_from, None)
+            .place_piece(rook_from, None)
+            .place_piece(king_to, Some((player, King)))
+            .place_piece(rook_to, Some((player, Rook)));
         (next_board, next_castling)
     }
 
-    /// Is the castling for `player` unobstructed at `castleside` on a given `board`?
-    pub fn free(board: &Board, player: Player, castleside: Castleside) -> bool {
-        match (player, castleside) {
-            (Player::White, Castleside::Kingside) => board.all_empty(&[f1, g1]),
-            (Player::White, Castleside::Queenside) => board.all_empty(&[b1, c1, d1]),
-            (Player::Black, Castleside::Kingside) => board.all_empty(&[f8, g8]),
-            (Player::Black, Castleside::Queenside) => board.all_empty(&[b8, c8, d8]),
+    /// Is the castling for `player` unobstructed at `castleside` on a given
+    /// `board`? Every square the king and rook cross or land on must be empty,
+    /// apart from the two of them.
+    pub fn free(self, board: &Board, player: Player, castleside: Castleside) -> bool {
+        let (king_from, king_to) = self.king_squares(player, castleside);
+        let (rook_from, rook_to) = self.rook_squares(player, castleside);
+        if board.piece_at(king_from) != Some((player, King))
+            || board.piece_at(rook_from) != Some((player, Rook))
+        {
+            return false;
         }
+        let crossed: Vec<Pos> = between(king_from, king_to)
+            .chain(between(rook_from, rook_to))
+            .filter(|&pos| pos != king_from && pos != rook_from)
+            .collect();
+        board.all_empty(&crossed)
     }
 
-    // Returns the two squares through which the king moves.
-    pub fn king_tracks(player: Player, castleside: Castleside) -> (Pos, Pos) {
-        match (player, castleside) {
-            (Player::White, Castleside::Kingside) => (f1, g1),
-            (Player::White, Castleside::Queenside) => (d1, c1),
-            (Player::Black, Castleside::Kingside) => (f8, g8),
-            (Player::Black, Castleside::Queenside) => (d8, c8),
-        }
+    /// Returns the squares the king moves from and to.
+    pub fn king_squares(self, player: Player, castleside: Castleside) -> (Pos, Pos) {
+        let rank = back_rank(player);
+        let to = match castleside {
+            Castleside::Kingside => 6,
+            Castleside::Queenside => 2,
+        };
+        (
+            Pos {
+                rank,
+                file: self.files.king,
+            },
+            Pos { rank, file: to },
+        )
     }
+
+    /// Returns the squares the rook moves from and to.
+    pub fn rook_squares(self, player: Player, castleside: Castleside) -> (Pos, Pos) {
+        let rank = back_rank(player);
+        let to = match castleside {
+            Castleside::Kingside => 5,
+            Castleside::Queenside => 3,
+        };
+        (
+            Pos {
+                rank,
+                file: self.files.rook(castleside),
+            },
+            Pos { rank, file: to },
+        )
+    }
+
+    /// Returns the squares the king passes through and lands on, none of
+    /// which may be attacked. Empty if the king is already on its castled
+    /// square.
+    pub fn king_tracks(self, player: Player, castleside: Castleside) -> Vec<Pos> {
+        let (from, to) = self.king_squares(player, castleside);
+        between(from, to).filter(|&pos| pos != from).collect()
+    }
+}
+
+/// The rank `player`'s king and rooks start on.
+fn back_rank(player: Player) -> u8 {
+    match player {
+        Player::White => 0,
+        Player::Black => 7,
+    }
+}
+
+/// The squares from `a` to `b` on their rank, inclusive.
+fn between(a: Pos, b: Pos) -> impl Iterator<Item = Pos> {
+    let rank = a.rank;
+    (a.file.min(b.file)..=a.file.max(b.file)).map(move |file| Pos { rank, file })
 }
@@ -0,0 +1,196 @@
+//! Chess960, or Fischer Random Chess: the pieces on the back rank start
+//! shuffled, with the bishops on opposite colours and the king between the
+//! rooks. The 960 start positions are numbered as in
+//! https://en.wikipedia.org/wiki/Fischer_random_chess_numbering_scheme
+
+use crate::game::board::Board;
+use crate::game::castles::{CastleFiles, Castles};
+use crate::game::piece::Piece::{self, *};
+use crate::game::player::Player::{self, *};
+use crate::game::state::State;
+
+/// The number of start positions.
+pub const POSITIONS: u32 = 960;
+
+/// The index of the standard start position.
+pub const STANDARD: u32 = 518;
+
+/// Where the knights go among the five squares left after the bishops and
+/// queen are placed.
+const KNIGHTS: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+/// The back rank of start position `index`, from the a file to the h file.
+/// Returns `None` if `index` is 960 or more.
+pub fn back_rank(index: u32) -> Option<[Piece; 8]> {
+    if index >= POSITIONS {
+        return None;
+    }
+    let mut rank = [None; 8];
+    let n = index as usize;
+    // The light-squared bishop on b, d, f or h, the dark-squared one on a,
+    // c, e or g.
+    rank[n % 4 * 2 + 1] = Some(Bishop);
+    let n = n / 4;
+    rank[n % 4 * 2] = Some(Bishop);
+    let n = n / 4;
+
+    // The rest fill the empty squares left to right.
+    let mut place = |nth: usize, piece: Piece| {
+        let file = (0..8)
+            .filter(|&file| rank[file].is_none())
+            .nth(nth)
+            .unwrap();
+        rank[file] = Some(piece);
+    };
+    place(n % 6, Queen);
+    let (first, second) = KNIGHTS[n / 6];
+    place(second, Knight);
+    place(first, Knight);
+    place(0, Rook);
+    place(0, King);
+    place(0, Rook);
+
+    let mut pieces = [Rook; 8];
+    for (piece, square) in pieces.iter_mut().zip(rank.iter()) {
+        *piece = square.unwrap();
+    }
+    Some(pieces)
+}
+
+/// The position at the start of a Chess960 game, with `index` from 0 to
+/// 959.
+pub fn start_position(index: u32) -> Result<State, String> {
+    let pieces = match back_rank(index) {
+        Some(pieces) => pieces,
+        None => {
+            return Err(format!(
+                "start index {} is out of range 0-{}",
+                index,
+                POSITIONS - 1
+            ))
+        }
+    };
+    let rank = |player: Player, piece: Option<Piece>| {
+        (0..8).map(move |file| Some((player, piece.unwrap_or(pieces[file]))))
+    };
+    let squares: Vec<_> = rank(White, None)
+        .chain(rank(White, Some(Pawn)))
+        .chain((0..32).map(|_| None))
+        .chain(rank(Black, Some(Pawn)))
+        .chain(rank(Black, None))
+        .collect();
+    let file = |piece: Piece, nth: usize| {
+        (0..8)
+            .filter(|&file| pieces[file] == piece)
+            .nth(nth)
+            .unwrap() as u8
+    };
+    let files = CastleFiles {
+        king: file(King, 0),
+        kingside: file(Rook, 1),
+        queenside: file(Rook, 0),
+    };
+    Ok(State::new(
+        Board::from_squares(&squares),
+        White,
+        None,
+        Castles::with_files(files),
+        0,
+        1,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::castles::Castleside;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_back_rank() {
+        assert_eq!(
+            back_rank(STANDARD),
+            Some([Rook, Knight, Bishop, Queen, King, Bishop, Knight, Rook])
+        );
+        assert_eq!(
+            back_rank(0),
+            Some([Bishop, Bishop, Queen, Knight, Knight, Rook, King, Rook])
+        );
+        assert_eq!(
+            back_rank(959),
+            Some([Rook, King, Rook, Knight, Knight, Queen, Bishop, Bishop])
+        );
+        assert_eq!(back_rank(POSITIONS), None);
+
+        let ranks: HashSet<_> = (0..POSITIONS).filter_map(back_rank).collect();
+        assert_eq!(ranks.len(), 960);
+        for rank in ranks {
+            let files = |piece| (0..8).filter(move |&file| rank[file] == piece);
+            let bishops: Vec<_> = files(Bishop).collect();
+            assert_ne!(bishops[0] % 2, bishops[1] % 2);
+            let rooks: Vec<_> = files(Rook).collect();
+            let king = files(King).next().unwrap();
+            assert!(rooks[0] < king && king < rooks[1]);
+        }
+    }
+
+    #[test]
+    fn test_start_position() {
+        assert_eq!(start_position(STANDARD).unwrap(), crate::new_game().state);
+        let state = start_position(0).unwrap();
+        assert_eq!(
+            state.to_fen(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+        assert!(start_position(960).is_err());
+    }
+
+    #[test]
+    fn test_castling() {
+        use crate::parsing::parse_fen;
+
+        // The king lands on the kingside rook's square and the other way
+        // around.
+        let state = parse_fen("r4k1r/8/8/8/8/8/8/1R3KR1 w KQ - 0 1").unwrap();
+        let castle = |coordinate: &str| {
+            state
+                .gen_moves()
+                .into_iter()
+                .find(|m0ve| m0ve.to_coordinate(&state) == coordinate)
+                .unwrap()
+                .next
+                .to_fen()
+        };
+        assert_eq!(castle("f1g1"), "r4k1r/8/8/8/8/8/8/1R3RK1 b - - 1 1");
+        assert_eq!(castle("f1b1"), "r4k1r/8/8/8/8/8/8/2KR2R1 b - - 1 1");
+
+        // Castling kingside would put the rook on f1, where the other rook
+        // is.
+        let state = start_position(0).unwrap();
+        assert!(state.castling.able(White, Castleside::Kingside));
+        assert!(!state
+            .castling
+            .free(&state.board, White, Castleside::Kingside));
+
+        // A rook on a1 attacks c1 once the castling rook leaves b1.
+        let state = parse_fen("4k3/8/8/8/8/8/8/rR1K4 w Q - 0 1").unwrap();
+        assert!(state
+            .castling
+            .free(&state.board, White, Castleside::Queenside));
+        assert!(state
+            .gen_moves()
+            .iter()
+            .all(|m0ve| m0ve.to_coordinate(&state) != "d1b1"));
+    }
+}
@@ -0,0 +1,101 @@
+//! King of the Hill: standard chess, except that a player also wins by
+//! bringing their king to one of the four centre squares.
+
+use crate::game::bitboard;
+use crate::game::m0ve::Move;
+use crate::game::outcome::{Outcome, Termination};
+use crate::game::piece::Piece::King;
+use crate::game::player::Player::{self, *};
+use crate::game::state::State;
+use crate::game::variant::{Standard, Variant};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KingOfTheHill;
+
+impl KingOfTheHill {
+    /// The player whose king stands in the centre, if any.
+    fn winner(state: &State) -> Option<Player> {
+        [White, Black]
+            .iter()
+            .copied()
+            .find(|&player| state.board.pieces(player, King) & bitboard::CENTRE != 0)
+    }
+}
+
+impl Variant for KingOfTheHill {
+    fn name(&self) -> &'static str {
+        "King of the Hill"
+    }
+
+    fn start_position(&self) -> State {
+        Standard.start_position()
+    }
+
+    fn legal_moves(&self, state: &State) -> Vec<Move> {
+        match KingOfTheHill::winner(state) {
+            Some(_) => vec![],
+            None => state.gen_moves(),
+        }
+    }
+
+    fn outcome(&self, state: &State, repetitions: usize) -> Option<Outcome> {
+        if let Some(winner) = KingOfTheHill::winner(state) {
+            return Some(Outcome::Win {
+                winner,
+                termination: Termination::CentreReached,
+            });
+        }
+        // Even a bare king can still walk to the centre.
+        match Standard.outcome(state, repetitions) {
+            Some(Outcome::Draw {
+                termination: Termination::InsufficientMaterial,
+            }) => None,
+            outcome => outcome,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Game;
+    use crate::parsing::parse_fen;
+
+    #[test]
+    fn test_king_reaches_the_centre() {
+        let state = parse_fen("4k3/8/8/8/8/3K4/8/8 w - - 0 1").unwrap();
+        let mut game = Game::with_variant_state(KingOfTheHill, state);
+        assert_eq!(game.outcome(), None);
+        let m0ve = game
+            .moves()
+            .into_iter()
+            .find(|m0ve| m0ve.to_coordinate(&game.state) == "d3e4")
+            .unwrap();
+        game.play(m0ve);
+        assert_eq!(
+            game.outcome(),
+            Some(Outcome::Win {
+                winner: White,
+                termination: Termination::CentreReached
+            })
+        );
+        assert!(game.moves().is_empty());
+    }
+
+    #[test]
+    fn test_otherwise_standard() {
+        let game = Game::new(KingOfTheHill);
+        assert_eq!(game.moves().len(), 20);
+
+        let state = parse_fen("6k1/5ppp/8/8/8/8/8/K3R3 b - - 0 1").unwrap();
+        assert_eq!(KingOfTheHill.outcome(&state, 1), None);
+        let state = parse_fen("4R1k1/5ppp/8/8/8/8/8/K7 b - - 0 1").unwrap();
+        assert_eq!(
+            KingOfTheHill.outcome(&state, 1),
+            Some(Outcome::Win {
+                winner: White,
+                termination: Termination::Checkmate
+            })
+        );
+    }
+}
@@ -1,16 +1,36 @@
-use crate::game::{castles::Castleside, pos::Pos, state::State};
+use crate::game::move_description::Check;
+use crate::game::{castles::Castleside, piece::Piece, player::Player, pos::Pos, state::State};
+use crate::parsing::coordinate_notation::action_to_coordinate;
+use crate::parsing::fen::piece_to_fen;
 use std::fmt;
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Action {
-    Simple { from: Pos, to: Pos },
-    Castle { castleside: Castleside },
+    Simple {
+        from: Pos,
+        to: Pos,
+    },
+    Castle {
+        castleside: Castleside,
+    },
+    Promotion {
+        from: Pos,
+        to: Pos,
+        piece: Piece,
+    },
+    /// Put a piece from the pocket on the board, in crazyhouse.
+    Drop {
+        piece: Piece,
+        to: Pos,
+    },
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct Move {
     pub action: Action,
     pub next: State,
+    /// The opponent's piece taken by this move, if any.
+    pub captured: Option<Piece>,
 }
 
 impl fmt::Display for Move {
@@ -25,6 +45,243 @@ impl fmt::Display for Move {
                 write!(f, "{}{} -> {}{}", from_file, from_rank, to_file, to_rank)
             }
             Action::Castle { castleside } => write!(f, "castle {}", castleside),
+            Action::Drop { piece, to } => {
+                let to_file = (to.file + b'A') as char;
+                let to_rank = to.rank + 1;
+                write!(
+                    f,
+                    "{}@{}{}",
+                    piece_to_fen((Player::White, *piece)),
+                    to_file,
+                    to_rank
+                )
+            }
+            Action::Promotion { from, to, piece } => {
+                let from_file = (from.file + b'A') as char;
+                let from_rank = from.rank + 1;
+                let to_file = (to.file + b'A') as char;
+                let to_rank = to.rank + 1;
+                write!(
+                    f,
+                    "{}{} -> {}{}={}",
+                    from_file,
+                    from_rank,
+                    to_file,
+                    to_rank,
+                    piece_to_fen((Player::White, *piece))
+                )
+            }
+        }
+    }
+}
+
+/// The piece letter used in standard algebraic notation. Pawns have none.
+fn san_piece(piece: Piece) -> &'static str {
+    match piece {
+        Piece::Pawn => "",
+        Piece::Bishop => "B",
+        Piece::King => "K",
+        Piece::Rook => "R",
+        Piece::Queen => "Q",
+        Piece::Knight => "N",
+    }
+}
+
+fn san_pos(pos: Pos) -> String {
+    format!("{}{}", (pos.file + b'a') as char, pos.rank + 1)
+}
+
+impl Action {
+    /// The source and destination squares of a move of one piece.
+    fn squares(&self) -> Option<(Pos, Pos)> {
+        match *self {
+            Action::Simple { from, to } | Action::Promotion { from, to, .. } => Some((from, to)),
+            Action::Castle { .. } | Action::Drop { .. } => None,
+        }
+    }
+}
+
+impl Move {
+    /// Render this move in coordinate notation, e.g. `e2e4` or `e7e8q`.
+    /// `state` is the position the move is played from.
+    pub fn to_coordinate(&self, state: &State) -> String {
+        action_to_coordinate(&self.action, state)
+    }
+
+    /// Render this move in standard algebraic notation. `state` is the
+    /// position the move is played from and `moves` the legal moves there,
+    /// and `check` is the marker to add, as the variant decides both; see
+    /// `Variant::san`.
+    pub fn to_san(&self, state: &State, moves: &[Move], check: Option<Check>) -> String {
+        let mut san = match &self.action {
+            Action::Castle {
+                castleside: Castleside::Kingside,
+            } => "O-O".to_string(),
+            Action::Castle {
+                castleside: Castleside::Queenside,
+            } => "O-O-O".to_string(),
+            Action::Drop { piece, to } => {
+                format!("{}@{}", piece_to_fen((Player::White, *piece)), san_pos(*to))
+            }
+            Action::Simple { from, to } | Action::Promotion { from, to, .. } => {
+                let (from, to) = (*from, *to);
+                let piece = match state.board.piece_at(from) {
+                    Some((_, piece)) => piece,
+                    None => panic!("No piece to move at {:?}", from),
+                };
+                let capture = self.captured.is_some();
+
+                let mut san = san_piece(piece).to_string();
+                if piece == Piece::Pawn {
+                    if capture {
+                        san.push((from.file + b'a') as char);
+                    }
+                } else {
+                    san.push_str(&Move::disambiguation(state, moves, piece, from, to));
+                }
+                if capture {
+                    san.push('x');
+                }
+                san.push_str(&san_pos(to));
+                if let Action::Promotion { piece, .. } = self.action {
+                    san.push('=');
+                    san.push_str(san_piece(piece));
+                }
+                san
+            }
+        };
+
+        match check {
+            Some(Check::Check) => san.push('+'),
+            Some(Check::Checkmate) => san.push('#'),
+            None => (),
+        }
+        san
+    }
+
+    /// The minimal file and/or rank needed to tell a move of `piece` from
+    /// `from` to `to` apart from the other legal `moves` in `state`.
+    fn disambiguation(state: &State, moves: &[Move], piece: Piece, from: Pos, to: Pos) -> String {
+        let rivals: Vec<Pos> = moves
+            .iter()
+            .filter_map(|m| m.action.squares())
+            .filter(|&(rival_from, rival_to)| {
+                rival_to == to
+                    && rival_from != from
+                    && state.board.piece_at(rival_from).map(|(_, p)| p) == Some(piece)
+            })
+            .map(|(rival_from, _)| rival_from)
+            .collect();
+
+        let pos = san_pos(from);
+        if rivals.is_empty() {
+            String::new()
+        } else if rivals.iter().all(|rival| rival.file != from.file) {
+            pos[..1].to_string()
+        } else if rivals.iter().all(|rival| rival.rank != from.rank) {
+            pos[1..].to_string()
+        } else {
+            pos
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::variant::{Standard, Variant};
+    use crate::game::Game;
+    use crate::parsing::{parse_algebraic_notation, parse_fen};
+
+    fn sans(state: &State) -> Vec<String> {
+        state
+            .gen_moves()
+            .iter()
+            .map(|m| Standard.san(state, m))
+            .collect()
+    }
+
+    fn assert_san_round_trips(state: &State) {
+        for m0ve in state.gen_moves() {
+            let san = Standard.san(state, &m0ve);
+            let desc = parse_algebraic_notation(&san).unwrap();
+            let matched = desc.match_moves(state, state.gen_moves());
+            assert_eq!(matched.map(|m| m.action), Some(m0ve.action), "{}", san);
+        }
+    }
+
+    #[test]
+    fn test_to_san_initial() {
+        let mut moves = sans(&Game::default().state);
+        moves.sort();
+        assert_eq!(
+            moves,
+            vec![
+                "Na3", "Nc3", "Nf3", "Nh3", "a3", "a4", "b3", "b4", "c3", "c4", "d3", "d4", "e3",
+                "e4", "f3", "f4", "g3", "g4", "h3", "h4"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_san_disambiguation() {
+        let state = parse_fen("8/3k4/8/8/8/2N1N3/3K4/8 w - - 0 1").unwrap();
+        let moves = sans(&state);
+        assert!(moves.contains(&"Ncd5".to_string()));
+        assert!(moves.contains(&"Ned5".to_string()));
+        assert!(moves.contains(&"Nb5".to_string()));
+
+        let state = parse_fen("8/3k4/8/1N6/8/1N6/3K4/8 w - - 0 1").unwrap();
+        let moves = sans(&state);
+        assert!(moves.contains(&"N3d4".to_string()));
+        assert!(moves.contains(&"N5d4".to_string()));
+
+        let state = parse_fen("8/7k/8/8/8/Q7/4K3/Q1Q5 w - - 0 1").unwrap();
+        let moves = sans(&state);
+        assert!(moves.contains(&"Qa1b2".to_string()));
+        assert!(moves.contains(&"Q3b2".to_string()));
+        assert!(moves.contains(&"Qcb2".to_string()));
+    }
+
+    #[test]
+    fn test_to_san_captures_checks_and_promotions() {
+        let state = parse_fen("3r3k/4P3/8/3p4/4P3/8/8/K7 w - - 0 1").unwrap();
+        let moves = sans(&state);
+        assert!(moves.contains(&"exd5".to_string()));
+        assert!(moves.contains(&"e8=Q+".to_string()));
+        assert!(moves.contains(&"exd8=N".to_string()));
+        assert!(moves.contains(&"e5".to_string()));
+
+        let state = parse_fen("6k1/5ppp/8/8/8/8/8/K3R3 w - - 0 1").unwrap();
+        assert!(sans(&state).contains(&"Re8#".to_string()));
+
+        let state =
+            parse_fen("rnbqkbnr/ppppp1p1/7p/4Pp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3").unwrap();
+        assert!(sans(&state).contains(&"exf6".to_string()));
+
+        let state = parse_fen("r3k2r/pppqpppp/8/8/8/8/PPPQPPPP/R3K2R w KQkq - 0 1").unwrap();
+        let moves = sans(&state);
+        assert!(moves.contains(&"O-O".to_string()));
+        assert!(moves.contains(&"O-O-O".to_string()));
+
+        let state = parse_fen("6k1/5ppp/8/8/8/8/8/K7[RP] w - - 0 1").unwrap();
+        let moves = sans(&state);
+        assert!(moves.contains(&"P@e4".to_string()));
+        assert!(moves.contains(&"R@e8#".to_string()));
+        assert!(moves.contains(&"R@a8#".to_string()));
+    }
+
+    #[test]
+    fn test_to_san_round_trip() {
+        for fen in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "3r3k/4P3/8/3p4/4P3/8/8/K7 w - - 0 1",
+            "8/7k/8/8/8/Q7/4K3/Q1Q5 w - - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppppp1p1/7p/4Pp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "r1bqk2r/pppp1ppp/2n5/4p3/1b2P3/2N5/PPPP1PPP/R1BQK1NR[NPbp] w KQkq - 0 5",
+        ] {
+            assert_san_round_trips(&parse_fen(fen).unwrap());
         }
     }
 }
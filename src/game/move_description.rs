@@ -2,6 +2,27 @@ use crate::game::castles::Castleside;
 use crate::game::m0ve::{Action, Move};
 use crate::game::piece::Piece;
 use crate::game::pos::Pos;
+use crate::game::state::State;
+
+/// A check or checkmate marker (`+` or `#`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    Check,
+    Checkmate,
+}
+
+/// A move annotation glyph such as `!` or `?!`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Annotation {
+    Good,
+    Mistake,
+    Brilliant,
+    Blunder,
+    Interesting,
+    Dubious,
+    /// A numeric annotation glyph written `$n`, as in PGN.
+    Nag(u8),
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MoveDescription {
@@ -9,16 +30,33 @@ pub enum MoveDescription {
         src_piece: Piece,
         src_rank: Option<u8>,
         src_file: Option<u8>,
+        capture: bool,
         dst_pos: Pos,
+        promotion: Option<Piece>,
+        check: Option<Check>,
+        annotation: Option<Annotation>,
     },
     Castle {
         castleside: Castleside,
+        check: Option<Check>,
+        annotation: Option<Annotation>,
+    },
+    Drop {
+        piece: Piece,
+        dst_pos: Pos,
+        check: Option<Check>,
+        annotation: Option<Annotation>,
     },
 }
 
 impl MoveDescription {
-    pub fn match_moves(&self, moves: Vec<Move>) -> Option<Move> {
-        let matched: Vec<Move> = moves.into_iter().filter(|m| self.match_move(m)).collect();
+    /// The one move of `moves`, the legal moves from `state`, that this
+    /// describes, if exactly one matches.
+    pub fn match_moves(&self, state: &State, moves: Vec<Move>) -> Option<Move> {
+        let matched: Vec<Move> = moves
+            .into_iter()
+            .filter(|m| self.match_move(state, m))
+            .collect();
         if matched.len() == 1 {
             matched.into_iter().next()
         } else {
@@ -26,7 +64,9 @@ impl MoveDescription {
         }
     }
 
-    fn match_move(&self, m0ve: &Move) -> bool {
+    /// Matches a single move from `state`. A capture marker must correspond
+    /// to an actual capture, but a missing marker is tolerated.
+    fn match_move(&self, state: &State, m0ve: &Move) -> bool {
         match (&m0ve.action, self) {
             (
                 Action::Simple { from, to },
@@ -34,9 +74,16 @@ impl MoveDescription {
                     src_file,
                     src_rank,
                     src_piece,
+                    capture,
                     dst_pos,
+                    promotion: None,
+                    ..
                 },
             ) => {
+                if *capture && m0ve.captured.is_none() {
+                    return false;
+                }
+
                 if src_file.is_some() && src_file != &Some(from.file) {
                     return false;
                 }
@@ -45,9 +92,36 @@ impl MoveDescription {
                     return false;
                 }
 
-                let dst_piece = m0ve.next.board.piece_at(*to).map(|(_, piece)| piece);
+                // The piece may be gone after the move, as in atomic.
+                let piece = state.board.piece_at(*from).map(|(_, piece)| piece);
+
+                dst_pos == to && Some(*src_piece) == piece
+            }
+            (
+                Action::Promotion { from, to, piece },
+                MoveDescription::Simple {
+                    src_file,
+                    src_rank,
+                    src_piece: Piece::Pawn,
+                    capture,
+                    dst_pos,
+                    promotion: Some(promotion),
+                    ..
+                },
+            ) => {
+                if *capture && m0ve.captured.is_none() {
+                    return false;
+                }
+
+                if src_file.is_some() && src_file != &Some(from.file) {
+                    return false;
+                }
+
+                if src_rank.is_some() && src_rank != &Some(from.rank) {
+                    return false;
+                }
 
-                dst_pos == to && Some(*src_piece) == dst_piece
+                dst_pos == to && promotion == piece
             }
             (
                 Action::Castle {
@@ -55,8 +129,17 @@ impl MoveDescription {
                 },
                 MoveDescription::Castle {
                     castleside: description_castleside,
+                    ..
                 },
             ) => action_castleside == description_castleside,
+            (
+                Action::Drop { piece, to },
+                MoveDescription::Drop {
+                    piece: description_piece,
+                    dst_pos,
+                    ..
+                },
+            ) => piece == description_piece && to == dst_pos,
             (_, _) => false,
         }
     }
@@ -83,9 +166,13 @@ mod test {
             src_file: None,
             src_rank: None,
             src_piece: Piece::Knight,
+            capture: false,
             dst_pos: d5,
+            promotion: None,
+            check: None,
+            annotation: None,
         };
-        let matched = desc.match_moves(moves);
+        let matched = desc.match_moves(&state, moves);
         assert_eq!(matched, None);
     }
 
@@ -97,9 +184,13 @@ mod test {
             src_file: Some(2),
             src_rank: None,
             src_piece: Piece::Knight,
+            capture: false,
             dst_pos: d5,
+            promotion: None,
+            check: None,
+            annotation: None,
         };
-        let matched = desc.match_moves(moves);
+        let matched = desc.match_moves(&state, moves);
         assert_ne!(matched, None);
     }
 
@@ -111,9 +202,13 @@ mod test {
             src_file: None,
             src_rank: None,
             src_piece: Piece::Knight,
+            capture: false,
             dst_pos: d4,
+            promotion: None,
+            check: None,
+            annotation: None,
         };
-        let matched = desc.match_moves(moves);
+        let matched = desc.match_moves(&state, moves);
         assert_eq!(matched, None);
     }
 
@@ -125,9 +220,13 @@ mod test {
             src_file: None,
             src_rank: Some(2),
             src_piece: Piece::Knight,
+            capture: false,
             dst_pos: d4,
+            promotion: None,
+            check: None,
+            annotation: None,
         };
-        let matched = desc.match_moves(moves);
+        let matched = desc.match_moves(&state, moves);
         assert_ne!(matched, None);
     }
 
@@ -137,9 +236,7 @@ mod test {
         for desc in &["e3", "e6", "Ke2", "e5", "Kd3", "e4"] {
             let next_moves = game.state.gen_moves();
             let move_desc = parse_algebraic_notation(desc).unwrap();
-            game = Game {
-                state: move_desc.match_moves(next_moves).unwrap().next,
-            };
+            game.play(move_desc.match_moves(&game.state, next_moves).unwrap());
         }
 
         assert_eq!(
@@ -161,9 +258,7 @@ mod test {
         ] {
             let next_moves = game.state.gen_moves();
             let move_desc = parse_algebraic_notation(desc).unwrap();
-            game = Game {
-                state: move_desc.match_moves(next_moves).unwrap().next,
-            };
+            game.play(move_desc.match_moves(&game.state, next_moves).unwrap());
         }
 
         assert_eq!(
@@ -184,4 +279,44 @@ mod test {
             Some((Player::Black, Piece::Rook))
         );
     }
+
+    #[test]
+    fn test_match_moves_promotion() {
+        let state = parse_fen("3r4/4P3/8/8/8/k7/8/K7 w - - 0 1").unwrap();
+
+        let desc = parse_algebraic_notation("e8").unwrap();
+        assert_eq!(desc.match_moves(&state, state.gen_moves()), None);
+
+        let desc = parse_algebraic_notation("e8=R").unwrap();
+        let matched = desc.match_moves(&state, state.gen_moves()).unwrap();
+        assert_eq!(
+            matched.action,
+            Action::Promotion {
+                from: e7,
+                to: e8,
+                piece: Piece::Rook
+            }
+        );
+
+        let desc = parse_algebraic_notation("ed8N").unwrap();
+        let matched = desc.match_moves(&state, state.gen_moves()).unwrap();
+        assert_eq!(
+            matched.next.board.piece_at(d8),
+            Some((Player::White, Piece::Knight))
+        );
+    }
+
+    #[test]
+    fn test_match_moves_verifies_capture() {
+        let state = parse_fen("4k3/8/8/3p4/8/8/8/3RK3 w - - 0 1").unwrap();
+
+        let desc = parse_algebraic_notation("Rxd5+").unwrap();
+        assert_ne!(desc.match_moves(&state, state.gen_moves()), None);
+
+        let desc = parse_algebraic_notation("Rd5").unwrap();
+        assert_ne!(desc.match_moves(&state, state.gen_moves()), None);
+
+        let desc = parse_algebraic_notation("Rxd4").unwrap();
+        assert_eq!(desc.match_moves(&state, state.gen_moves()), None);
+    }
 }
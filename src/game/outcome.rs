@@ -0,0 +1,84 @@
+use crate::game::player::Player;
+use std::fmt;
+
+/// Why a game ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    FiftyMoveRule,
+    ThreefoldRepetition,
+    InsufficientMaterial,
+    /// The king was blown up, in atomic chess.
+    KingExploded,
+    /// The winner has no pieces left, in antichess.
+    AllPiecesLost,
+    /// The winner gave a third check, in three-check.
+    ThreeChecks,
+    /// The winner's king reached the centre, in King of the Hill.
+    CentreReached,
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Termination::Checkmate => write!(f, "checkmate"),
+            Termination::Stalemate => write!(f, "stalemate"),
+            Termination::FiftyMoveRule => write!(f, "the fifty-move rule"),
+            Termination::ThreefoldRepetition => write!(f, "threefold repetition"),
+            Termination::InsufficientMaterial => write!(f, "insufficient material"),
+            Termination::KingExploded => write!(f, "exploding the king"),
+            Termination::AllPiecesLost => write!(f, "losing all pieces"),
+            Termination::ThreeChecks => write!(f, "giving three checks"),
+            Termination::CentreReached => write!(f, "reaching the centre"),
+        }
+    }
+}
+
+/// The result of a finished game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Win {
+        winner: Player,
+        termination: Termination,
+    },
+    Draw {
+        termination: Termination,
+    },
+}
+
+impl Outcome {
+    pub fn termination(self) -> Termination {
+        match self {
+            Outcome::Win { termination, .. } | Outcome::Draw { termination } => termination,
+        }
+    }
+
+    pub fn winner(self) -> Option<Player> {
+        match self {
+            Outcome::Win { winner, .. } => Some(winner),
+            Outcome::Draw { .. } => None,
+        }
+    }
+
+    /// The PGN result token: `1-0`, `0-1` or `1/2-1/2`.
+    pub fn result_token(self) -> &'static str {
+        match self.winner() {
+            Some(Player::White) => "1-0",
+            Some(Player::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Win {
+                winner,
+                termination,
+            } => write!(f, "{} wins by {}", winner, termination),
+            Outcome::Draw { termination } => write!(f, "Draw by {}", termination),
+        }
+    }
+}
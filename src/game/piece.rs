@@ -1,10 +1,9 @@
-use crate::game::board::Board;
+use crate::game::bitboard::{self, bit, Bitboard, EMPTY};
 use crate::game::player::Player::*;
 use crate::game::pos::Pos;
 use crate::game::state::State;
-use crate::util::from_to_step::FromToStep;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Piece {
     Pawn,
     Bishop,
@@ -14,69 +13,48 @@ pub enum Piece {
     Knight,
 }
 
-impl Piece {
-    fn lateral_eyes(board: &Board, from: Pos, to: Pos) -> bool {
-        if from == to {
-            return false;
-        }
+/// The pieces a pawn can promote to, most valuable first.
+pub const PROMOTION_PIECES: [Piece; 4] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
 
-        if to.file == from.file {
-            let range = if to.rank > from.rank {
-                (from.rank + 1)..to.rank
-            } else {
-                (to.rank + 1)..from.rank
-            };
+impl Piece {
+    /// The squares this piece, standing on `from` and owned by the current
+    /// player, can move to for a given `state`. Squares held by the current
+    /// player's own pieces are included when they block a line, and the
+    /// king is not taken into account.
+    pub fn targets(self, from: Pos, state: &State) -> Bitboard {
+        let board = &state.board;
+        let occupied = board.occupied();
 
-            for next_rank in range {
-                let next_pos = Pos {
-                    rank: next_rank,
-                    file: to.file,
+        match self {
+            Piece::Pawn => {
+                let (forward, start_rank) = match state.player {
+                    White => (1, 1),
+                    Black => (-1, 6),
+                };
+                let step = |pos: Pos| Pos {
+                    rank: (i32::from(pos.rank) + forward) as u8,
+                    file: pos.file,
                 };
-                if board.piece_at(next_pos).is_some() {
-                    return false;
+                let mut pushes = EMPTY;
+                if from.rank > 0 && from.rank < 7 && board.piece_at(step(from)).is_none() {
+                    pushes |= bit(step(from));
+                    if from.rank == start_rank && board.piece_at(step(step(from))).is_none() {
+                        pushes |= bit(step(step(from)));
+                    }
                 }
+                let en_passant = state.en_passant.map_or(EMPTY, bit);
+                pushes | (bitboard::pawn_attacks(state.player, from) & (occupied | en_passant))
             }
 
-            true
-        } else if to.rank == from.rank {
-            let range = if to.file > from.file {
-                (from.file + 1)..to.file
-            } else {
-                (to.file + 1)..from.file
-            };
+            Piece::King => bitboard::king_attacks(from),
 
-            for next_file in range {
-                let next_pos = Pos {
-                    rank: to.rank,
-                    file: next_file,
-                };
-                if board.piece_at(next_pos).is_some() {
-                    return false;
-                }
-            }
+            Piece::Rook => bitboard::rook_attacks(from, occupied),
 
-            true
-        } else {
-            false
-        }
-    }
+            Piece::Bishop => bitboard::bishop_attacks(from, occupied),
 
-    fn diagonal_eyes(board: &Board, from: Pos, to: Pos) -> bool {
-        let diff = from.abs_diff(to);
-
-        if diff.rank == diff.file && diff.rank > 0 {
-            let ranks = FromToStep::from_to(from.rank, to.rank);
-            let files = FromToStep::from_to(from.file, to.file);
-            let coords = ranks.zip(files);
-            for (rank, file) in coords {
-                let pos = Pos { rank, file };
-                if board.piece_at(pos).is_some() {
-                    return false;
-                }
-            }
-            true
-        } else {
-            false
+            Piece::Queen => bitboard::queen_attacks(from, occupied),
+
+            Piece::Knight => bitboard::knight_attacks(from),
         }
     }
 
@@ -84,83 +62,21 @@ impl Piece {
     /// is owned by the current player. This does not take into account whether or not
     /// the king is in check.
     pub fn eyes(self, from: Pos, to: Pos, state: &State) -> bool {
-        let board = &state.board;
-        let player = state.player;
-
-        match self {
-            Piece::Pawn => {
-                let capture = board.piece_at(to).is_some();
-                if !capture && from.file == to.file {
-                    match (player, from.rank, to.rank) {
-                        (White, 1, 3) => {
-                            return board
-                                .piece_at(Pos {
-                                    rank: 2,
-                                    file: from.file,
-                                })
-                                .is_none();
-                        }
-                        (Black, 6, 4) => {
-                            return board
-                                .piece_at(Pos {
-                                    rank: 5,
-                                    file: from.file,
-                                })
-                                .is_none();
-                        }
-                        _ => (),
-                    }
-                }
-
-                let next_rank = i32::from(from.rank) + if player == White { 1 } else { -1 };
-                if to.rank != next_rank as u8 {
-                    return false;
-                }
-
-                if capture {
-                    (to.file > 0 /* u8 guard */ && from.file == to.file - 1)
-                        || from.file == to.file + 1
-                } else {
-                    Some(to) == state.en_passant || from.file == to.file
-                }
-            }
-
-            Piece::King => {
-                let diff = from.abs_diff(to);
-                diff.rank <= 1 && diff.file <= 1
-            }
-
-            Piece::Rook => Piece::lateral_eyes(board, from, to),
-
-            Piece::Bishop => Piece::diagonal_eyes(board, from, to),
-
-            Piece::Queen => {
-                Piece::lateral_eyes(board, from, to) || Piece::diagonal_eyes(board, from, to)
-            }
-
-            Piece::Knight => {
-                let diff = from.abs_diff(to);
-                diff.rank >= 1 && diff.file >= 1 && diff.rank + diff.file == 3
-            }
-        }
+        self.targets(from, state) & bit(to) != 0
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::game::board::Board;
     use crate::game::castles::Castles;
     use crate::game::player::Player;
     use crate::game::pos::*;
     use crate::parsing::parse_fen;
 
     fn simple_state(board: Board, player: Player) -> State {
-        State {
-            board,
-            player,
-            en_passant: None,
-            castling: Castles::initial(),
-        }
+        State::new(board, player, None, Castles::initial(), 0, 1)
     }
 
     fn test_simple_board_for_piece_lateral_king() -> Board {
@@ -235,7 +151,7 @@ mod test {
         let white_move = &simple_state(board, White);
         let piece = Piece::Knight;
 
-        let valid_moves = vec![b6, a5, a3, b2, d2, e3, e5, d6];
+        let valid_moves = [b6, a5, a3, b2, d2, e3, e5, d6];
         for pos in white_move.board.coords().iter() {
             if valid_moves.contains(pos) {
                 assert!(piece.eyes(c4, *pos, white_move));
@@ -0,0 +1,173 @@
+//! Pockets for crazyhouse: a player's captured pieces change sides and wait
+//! in their pocket to be dropped back onto the board as a move.
+
+use crate::game::bitboard::{self, Bitboard};
+use crate::game::piece::Piece;
+use crate::game::player::Player;
+use crate::game::pos::Pos;
+
+/// The pieces that can be in a pocket, in the order they are written.
+pub const POCKET_PIECES: [Piece; 5] = [
+    Piece::Queen,
+    Piece::Rook,
+    Piece::Bishop,
+    Piece::Knight,
+    Piece::Pawn,
+];
+
+/// How many of each piece one player holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Pocket {
+    counts: [u8; 6],
+}
+
+impl Pocket {
+    pub fn count(self, piece: Piece) -> u8 {
+        self.counts[piece as usize]
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.counts.iter().all(|&count| count == 0)
+    }
+
+    /// The pocket with one more `piece`, unless it already holds 255.
+    pub fn with(mut self, piece: Piece) -> Option<Self> {
+        self.counts[piece as usize] = self.counts[piece as usize].checked_add(1)?;
+        Some(self)
+    }
+
+    /// The pocket with one fewer `piece`. Panics if there is none.
+    pub fn without(mut self, piece: Piece) -> Self {
+        self.counts[piece as usize] -= 1;
+        self
+    }
+
+    /// The kinds of piece held, in `POCKET_PIECES` order.
+    pub fn pieces(self) -> impl Iterator<Item = Piece> {
+        POCKET_PIECES
+            .iter()
+            .copied()
+            .filter(move |&piece| self.count(piece) > 0)
+    }
+}
+
+/// Both players' pockets, and which pieces on the board were promoted from
+/// pawns, as those go back into a pocket as pawns when captured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pockets {
+    pub white: Pocket,
+    pub black: Pocket,
+    pub promoted: Bitboard,
+}
+
+impl Pockets {
+    pub fn get(self, player: Player) -> Pocket {
+        match player {
+            Player::White => self.white,
+            Player::Black => self.black,
+        }
+    }
+
+    fn set(mut self, player: Player, pocket: Pocket) -> Self {
+        match player {
+            Player::White => self.white = pocket,
+            Player::Black => self.black = pocket,
+        }
+        self
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.white.is_empty() && self.black.is_empty()
+    }
+
+    /// If `player` moves a piece from `from` to `to`, capturing `captured`
+    /// on `captured_pos`, what are the next pockets?
+    pub fn after_move(
+        self,
+        player: Player,
+        from: Pos,
+        to: Pos,
+        captured: Option<(Piece, Pos)>,
+    ) -> Self {
+        let mut next = self;
+        if let Some((piece, pos)) = captured {
+            let piece = if self.promoted & bitboard::bit(pos) != 0 {
+                Piece::Pawn
+            } else {
+                piece
+            };
+            // Only a made up position can have a full pocket.
+            if let Some(pocket) = self.get(player).with(piece) {
+                next = next.set(player, pocket);
+            }
+            next.promoted &= !bitboard::bit(pos);
+        }
+        if self.promoted & bitboard::bit(from) != 0 {
+            next.promoted = next.promoted & !bitboard::bit(from) | bitboard::bit(to);
+        }
+        next
+    }
+
+    /// The pockets after a pawn of the player to move promotes on `pos`.
+    pub fn after_promotion(mut self, pos: Pos) -> Self {
+        self.promoted |= bitboard::bit(pos);
+        self
+    }
+
+    /// The pockets after `player` drops `piece`.
+    pub fn after_drop(self, player: Player, piece: Piece) -> Self {
+        self.set(player, self.get(player).without(piece))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::pos::*;
+
+    #[test]
+    fn test_captures_fill_the_pocket() {
+        let pockets = Pockets::default().after_promotion(a8).after_move(
+            Player::Black,
+            b7,
+            a8,
+            Some((Piece::Queen, a8)),
+        );
+        assert_eq!(pockets.black.count(Piece::Pawn), 1);
+        assert_eq!(pockets.black.count(Piece::Queen), 0);
+        assert_eq!(pockets.promoted, 0);
+
+        let pockets = pockets.after_move(Player::White, e4, d5, Some((Piece::Knight, d5)));
+        assert_eq!(
+            pockets.white.pieces().collect::<Vec<_>>(),
+            vec![Piece::Knight]
+        );
+        let pockets = pockets.after_drop(Player::White, Piece::Knight);
+        assert!(pockets.white.is_empty());
+    }
+
+    #[test]
+    fn test_full_pocket() {
+        let mut pocket = Pocket::default();
+        for _ in 0..255 {
+            pocket = pocket.with(Piece::Pawn).unwrap();
+        }
+        assert_eq!(pocket.with(Piece::Pawn), None);
+
+        let pockets = Pockets {
+            black: pocket,
+            ..Pockets::default()
+        };
+        let pockets = pockets.after_move(Player::Black, d4, e3, Some((Piece::Pawn, e3)));
+        assert_eq!(pockets.black.count(Piece::Pawn), 255);
+    }
+
+    #[test]
+    fn test_promoted_pieces_are_followed() {
+        let pockets =
+            Pockets::default()
+                .after_promotion(h8)
+                .after_move(Player::White, h8, h1, None);
+        assert_eq!(pockets.promoted, bitboard::bit(h1));
+    }
+}
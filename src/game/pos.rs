@@ -15,8 +15,8 @@ impl Pos {
     /// Returns a position representing the absolute difference
     /// between `self` and `other`.
     pub fn abs_diff(self, other: Pos) -> Pos {
-        let rank = (other.rank as i8 - self.rank as i8).abs() as u8;
-        let file = (other.file as i8 - self.file as i8).abs() as u8;
+        let rank = (other.rank as i8 - self.rank as i8).unsigned_abs();
+        let file = (other.file as i8 - self.file as i8).unsigned_abs();
 
         Pos { rank, file }
     }
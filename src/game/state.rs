@@ -1,8 +1,10 @@
 use crate::game::{
-    board::Board, castles::Castles, castles::Castleside, m0ve::Action, m0ve::Move, piece::Piece::*,
-    player::Player, pos::Pos,
+    bitboard, bitboard::Bitboard, board::Board, castles::Castles, castles::Castleside,
+    m0ve::Action, m0ve::Move, piece::Piece, piece::Piece::*, piece::PROMOTION_PIECES,
+    player::Player, player::Player::*, pocket::Pockets, pos::Pos, three_check::Checks, zobrist,
 };
-use itertools::Itertools;
+use crate::parsing::fen::state_to_fen;
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct State {
@@ -10,58 +12,155 @@ pub struct State {
     pub player: Player,
     pub en_passant: Option<Pos>,
     pub castling: Castles,
+    /// Half-moves since the last capture or pawn advance.
+    pub halfmove_clock: u32,
+    /// Starts at 1 and is incremented after Black's move.
+    pub fullmove_number: u32,
+    /// The pieces in hand, in crazyhouse. `None` in variants without drops.
+    pub pockets: Option<Pockets>,
+    /// The checks each side has given, in three-check. `None` in variants
+    /// that don't count them.
+    pub checks: Option<Checks>,
+    /// Polyglot-compatible Zobrist key of the position, see `zobrist_key`.
+    key: u64,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_fen())
+    }
 }
 
 impl State {
-    /// Is the current player in check?
-    fn in_check(&self) -> bool {
-        let to_pos = self.board.get_king_pos(self.player);
-        let next_move_state = State {
-            board: self.board.clone(),
-            player: self.player.other(),
-            en_passant: None,
-            castling: self.castling,
-        };
+    pub fn new(
+        board: Board,
+        player: Player,
+        en_passant: Option<Pos>,
+        castling: Castles,
+        halfmove_clock: u32,
+        fullmove_number: u32,
+    ) -> State {
+        let key = zobrist::key(&board, player, en_passant, castling);
+        State {
+            board,
+            player,
+            en_passant,
+            castling,
+            halfmove_clock,
+            fullmove_number,
+            pockets: None,
+            checks: None,
+            key,
+        }
+    }
+
+    /// This state with `pockets`, as in crazyhouse.
+    pub fn with_pockets(self, pockets: Option<Pockets>) -> State {
+        State {
+            key: self.key ^ zobrist::pockets_key(self.pockets) ^ zobrist::pockets_key(pockets),
+            pockets,
+            ..self
+        }
+    }
+
+    /// This state with `checks` counted, as in three-check.
+    pub fn with_checks(self, checks: Option<Checks>) -> State {
+        State {
+            key: self.key ^ zobrist::checks_key(self.checks) ^ zobrist::checks_key(checks),
+            checks,
+            ..self
+        }
+    }
 
-        for from_pos in self.board.coords() {
-            if next_move_state.can_move_pseudo(from_pos, to_pos) {
-                return true;
+    /// `next`, reached by a move of the player to move, with a check it
+    /// gives counted if checks are being counted.
+    fn count_check(&self, next: State) -> State {
+        match next.checks {
+            Some(checks) if next.in_check() => {
+                next.with_checks(Some(checks.after_check(self.player)))
             }
+            _ => next,
         }
-        false
     }
 
-    // Can the current player move the piece, not taking into account
-    // whether the king is in check?
-    fn can_move_pseudo(&self, from: Pos, to: Pos) -> bool {
-        let from_piece = self.board.piece_at(from);
-        let to_piece = self.board.piece_at(to);
+    /// A 64-bit hash of the position (not the move clocks), the same as
+    /// Polyglot's. It is kept up to date as moves are made; a state whose
+    /// fields are changed by hand should be rebuilt with `State::new`.
+    pub fn zobrist_key(&self) -> u64 {
+        self.key
+    }
 
-        match (from_piece, to_piece) {
-            (None, _) => false,
-            (Some((fp, _)), _) if fp != self.player => false,
-            (_, Some((tp, _))) if tp == self.player => false,
-            (Some((_, piece)), _) => piece.eyes(from, to, self),
+    /// Is the current player in check? Never if they have no king, as in
+    /// variants where it can be captured.
+    pub fn in_check(&self) -> bool {
+        match self.board.pieces(self.player, King) {
+            bitboard::EMPTY => false,
+            _ => {
+                let king_pos = self.board.get_king_pos(self.player);
+                self.board.is_attacked(king_pos, self.player.other())
+            }
         }
     }
 
-    fn move_puts_current_player_in_check(&self, from_pos: Pos, to_pos: Pos) -> bool {
-        let next_state = State {
-            player: self.player,
-            board: self.board.move_piece(from_pos, to_pos),
-            en_passant: None,
-            castling: self.castling,
+    /// Would the current player's king be attacked on `board`?
+    fn leaves_king_attacked(&self, board: &Board) -> bool {
+        board.is_attacked(board.get_king_pos(self.player), self.player.other())
+    }
+
+    /// The fullmove number once the current player has moved.
+    fn next_fullmove_number(&self) -> u32 {
+        match self.player {
+            White => self.fullmove_number,
+            Black => self.fullmove_number + 1,
+        }
+    }
+
+    /// Do `self` and `other` have the same position for the purposes of
+    /// repetition, ignoring the move clocks? As for the Zobrist key, an en
+    /// passant square only counts if a pawn could capture there.
+    pub fn same_position(&self, other: &State) -> bool {
+        let en_passant = |state: &State| {
+            zobrist::capturable_en_passant(&state.board, state.player, state.en_passant)
         };
-        next_state.in_check()
+        self.player == other.player
+            && en_passant(self) == en_passant(other)
+            && self.castling == other.castling
+            && self.board == other.board
+            && self.pockets == other.pockets
+            && self.checks == other.checks
     }
 
-    /// Can the current player move the piece in `from_pos` to `to_pos`?
-    fn can_move(&self, from_pos: Pos, to_pos: Pos) -> bool {
-        if !self.can_move_pseudo(from_pos, to_pos) {
+    /// Is there too little material left for either side to checkmate?
+    /// This covers king against king, king and minor piece against king,
+    /// and kings with bishops all on the same square colour. Pieces in a
+    /// pocket can always be dropped to mate.
+    pub fn insufficient_material(&self) -> bool {
+        if self.pockets.is_some_and(|pockets| !pockets.is_empty()) {
             return false;
         }
+        let mut minors = 0;
+        let mut bishop_colours = vec![];
+        for pos in self.board.coords() {
+            match self.board.piece_at(pos) {
+                None | Some((_, King)) => (),
+                Some((_, Knight)) => minors += 1,
+                Some((_, Bishop)) => {
+                    minors += 1;
+                    bishop_colours.push((pos.rank + pos.file) % 2);
+                }
+                Some(_) => return false,
+            }
+        }
+        minors <= 1
+            || (minors == bishop_colours.len()
+                && bishop_colours
+                    .iter()
+                    .all(|&colour| colour == bishop_colours[0]))
+    }
 
-        !self.move_puts_current_player_in_check(from_pos, to_pos)
+    /// Serialize this state to Forsyth-Edwards notation.
+    pub fn to_fen(&self) -> String {
+        state_to_fen(self)
     }
 
     fn en_passant_pos(&self, from: Pos, to: Pos) -> Option<Pos> {
@@ -97,16 +196,98 @@ impl State {
         } else {
             self.board.move_piece(from, to)
         };
-        let next_castling = self.castling.after_move(self.player, from);
+        let captured = if is_en_passant_capture {
+            Some(Pawn)
+        } else {
+            self.board.piece_at(to).map(|(_, piece)| piece)
+        };
+        let next_castling = self
+            .castling
+            .after_move(self.player, from)
+            .after_capture(self.player, to);
+        let moved = self.board.piece_at(from).map(|(_, piece)| piece);
+        let resets_clock = captured.is_some() || moved == Some(Pawn);
+        let en_passant = self.en_passant_pos(from, to);
+
+        let mut key = self.key
+            ^ zobrist::turn_key(self.player)
+            ^ zobrist::turn_key(self.player.other())
+            ^ zobrist::castling_key(self.castling)
+            ^ zobrist::castling_key(next_castling)
+            ^ zobrist::en_passant_key(&self.board, self.player, self.en_passant)
+            ^ zobrist::en_passant_key(&next_board, self.player.other(), en_passant);
+        if let Some(piece) = moved {
+            key ^= zobrist::piece_key(self.player, piece, from)
+                ^ zobrist::piece_key(self.player, piece, to);
+        }
+        let captured_pos = if is_en_passant_capture {
+            Pos {
+                rank: from.rank,
+                file: to.file,
+            }
+        } else {
+            to
+        };
+        if let Some(piece) = captured {
+            key ^= zobrist::piece_key(self.player.other(), piece, captured_pos);
+        }
+        let pockets = self.pockets.map(|pockets| {
+            pockets.after_move(
+                self.player,
+                from,
+                to,
+                captured.map(|piece| (piece, captured_pos)),
+            )
+        });
+        key ^= zobrist::pockets_key(self.pockets) ^ zobrist::pockets_key(pockets);
+
         let next_state = State {
             board: next_board,
             player: self.player.other(),
-            en_passant: self.en_passant_pos(from, to),
+            en_passant,
             castling: next_castling,
+            halfmove_clock: if resets_clock {
+                0
+            } else {
+                self.halfmove_clock + 1
+            },
+            fullmove_number: self.next_fullmove_number(),
+            pockets,
+            checks: self.checks,
+            key,
         };
         Move {
             action: Action::Simple { from, to },
-            next: next_state,
+            next: self.count_check(next_state),
+            captured,
+        }
+    }
+
+    /// Is moving the piece at `from` to `to` a pawn reaching the last rank?
+    fn is_promotion(&self, from: Pos, to: Pos) -> bool {
+        match self.board.piece_at(from) {
+            Some((White, Pawn)) => to.rank == 7,
+            Some((Black, Pawn)) => to.rank == 0,
+            _ => false,
+        }
+    }
+
+    fn build_promotion_move(&self, from: Pos, to: Pos, piece: Piece) -> Move {
+        let simple = self.build_simple_move(from, to);
+        // Whether the move gives check depends on the piece promoted to.
+        let pawn_move = simple.next.with_checks(self.checks);
+        let next_state = State {
+            board: pawn_move.board.place_piece(to, Some((self.player, piece))),
+            key: pawn_move.key
+                ^ zobrist::piece_key(self.player, Pawn, to)
+                ^ zobrist::piece_key(self.player, piece, to),
+            pockets: pawn_move.pockets.map(|pockets| pockets.after_promotion(to)),
+            ..pawn_move
+        };
+        Move {
+            action: Action::Promotion { from, to, piece },
+            next: self.count_check(next_state),
+            captured: simple.captured,
         }
     }
 
@@ -114,80 +295,237 @@ impl State {
         // Return early if it's not possible to castle, before
         // calculating passing through checks.
         if !(self.castling.able(self.player, castleside)
-            && Castles::free(&self.board, self.player, castleside))
+            && self.castling.free(&self.board, self.player, castleside))
         {
             return false;
         }
 
         let king_pos = self.board.get_king_pos(self.player);
-        let (pos_1, pos_2) = Castles::king_tracks(self.player, castleside);
+        let tracks = self.castling.king_tracks(self.player, castleside);
 
-        !self.in_check()
-            && !self.move_puts_current_player_in_check(king_pos, pos_1)
-            && !self.move_puts_current_player_in_check(king_pos, pos_2)
+        std::iter::once(&king_pos)
+            .chain(tracks.iter())
+            .all(|&pos| !self.board.is_attacked(pos, self.player.other()))
     }
 
     fn build_castle_move(&self, castleside: Castleside) -> Move {
         let (next_board, next_castling) =
             self.castling.castle(&self.board, self.player, castleside);
+        let (king_from, king_to) = self.castling.king_squares(self.player, castleside);
+        let (rook_from, rook_to) = self.castling.rook_squares(self.player, castleside);
+        let key = self.key
+            ^ zobrist::turn_key(self.player)
+            ^ zobrist::turn_key(self.player.other())
+            ^ zobrist::castling_key(self.castling)
+            ^ zobrist::castling_key(next_castling)
+            ^ zobrist::en_passant_key(&self.board, self.player, self.en_passant)
+            ^ zobrist::piece_key(self.player, King, king_from)
+            ^ zobrist::piece_key(self.player, King, king_to)
+            ^ zobrist::piece_key(self.player, Rook, rook_from)
+            ^ zobrist::piece_key(self.player, Rook, rook_to);
         let next_state = State {
             board: next_board,
             player: self.player.other(),
-            en_passant: self.en_passant,
+            en_passant: None,
             castling: next_castling,
+            halfmove_clock: self.halfmove_clock + 1,
+            fullmove_number: self.next_fullmove_number(),
+            pockets: self.pockets,
+            checks: self.checks,
+            key,
         };
         Move {
             action: Action::Castle { castleside },
-            next: next_state,
+            next: self.count_check(next_state),
+            captured: None,
         }
     }
 
     fn make_castle_move(&self, castleside: Castleside) -> Option<Move> {
-        if self.can_castle(castleside) {
-            Some(self.build_castle_move(castleside))
-        } else {
+        if !self.can_castle(castleside) {
+            return None;
+        }
+        // In Chess960 the castling rook can be all that stood between the
+        // king's new square and an attacker along the back rank.
+        let castle = self.build_castle_move(castleside);
+        if self.leaves_king_attacked(&castle.next.board) {
             None
+        } else {
+            Some(castle)
         }
     }
 
-    fn make_simple_move(&self, from: Pos, to: Pos) -> Option<Move> {
-        if self.can_move(from, to) {
-            Some(self.build_simple_move(from, to))
+    /// The moves from `from` to `to`, one for each promotion piece if a
+    /// pawn reaches the last rank.
+    fn build_moves(&self, from: Pos, to: Pos) -> Vec<Move> {
+        if self.is_promotion(from, to) {
+            PROMOTION_PIECES
+                .iter()
+                .map(|&piece| self.build_promotion_move(from, to, piece))
+                .collect()
         } else {
-            None
+            vec![self.build_simple_move(from, to)]
+        }
+    }
+
+    /// The legal moves from `from` to `to`, given the move is pseudo-legal.
+    fn make_simple_moves(&self, from: Pos, to: Pos) -> Vec<Move> {
+        let simple = self.build_simple_move(from, to);
+        if self.leaves_king_attacked(&simple.next.board) {
+            vec![]
+        } else if self.is_promotion(from, to) {
+            self.build_moves(from, to)
+        } else {
+            vec![simple]
+        }
+    }
+
+    /// Drop `piece` from the current player's pocket on `to`, given there
+    /// is one and `to` is empty. Like pawn moves, pawn drops reset the
+    /// halfmove clock.
+    fn build_drop_move(&self, piece: Piece, to: Pos) -> Move {
+        let pockets = self
+            .pockets
+            .map(|pockets| pockets.after_drop(self.player, piece));
+        let key = self.key
+            ^ zobrist::turn_key(self.player)
+            ^ zobrist::turn_key(self.player.other())
+            ^ zobrist::en_passant_key(&self.board, self.player, self.en_passant)
+            ^ zobrist::piece_key(self.player, piece, to)
+            ^ zobrist::pockets_key(self.pockets)
+            ^ zobrist::pockets_key(pockets);
+        let next_state = State {
+            board: self.board.place_piece(to, Some((self.player, piece))),
+            player: self.player.other(),
+            en_passant: None,
+            castling: self.castling,
+            halfmove_clock: if piece == Pawn {
+                0
+            } else {
+                self.halfmove_clock + 1
+            },
+            fullmove_number: self.next_fullmove_number(),
+            pockets,
+            checks: self.checks,
+            key,
+        };
+        Move {
+            action: Action::Drop { piece, to },
+            next: self.count_check(next_state),
+            captured: None,
+        }
+    }
+
+    /// The drops from the current player's pocket, whether or not they
+    /// leave the king attacked. Pawns may not be dropped on the first or
+    /// last rank.
+    fn build_drops(&self) -> Vec<Move> {
+        let pocket = match self.pockets {
+            Some(pockets) => pockets.get(self.player),
+            None => return vec![],
+        };
+        let empty = !self.board.occupied();
+        pocket
+            .pieces()
+            .flat_map(|piece| {
+                let targets = if piece == Pawn {
+                    empty & !bitboard::BACK_RANKS
+                } else {
+                    empty
+                };
+                bitboard::squares(targets).map(move |to| self.build_drop_move(piece, to))
+            })
+            .collect()
+    }
+
+    /// The legal drops from the current player's pocket.
+    fn drop_moves(&self) -> Vec<Move> {
+        self.build_drops()
+            .into_iter()
+            .filter(|drop| !self.leaves_king_attacked(&drop.next.board))
+            .collect()
+    }
+
+    /// The squares the current player's piece on `from` can move to.
+    fn targets(&self, from: Pos) -> Bitboard {
+        match self.board.piece_at(from) {
+            Some((_, piece)) => piece.targets(from, self) & !self.board.player_pieces(self.player),
+            None => bitboard::EMPTY,
         }
     }
 
+    /// The moves from this state without checking whether they leave the
+    /// king attacked, for variants with their own idea of check. Castling
+    /// is included whenever the king and rook have a clear path, so those
+    /// variants also decide whether the king may castle out of or through
+    /// an attack.
+    pub fn pseudo_legal_moves(&self) -> Vec<Move> {
+        let own = self.board.player_pieces(self.player);
+        let castles = [Castleside::Kingside, Castleside::Queenside]
+            .iter()
+            .filter(|&&castleside| {
+                self.castling.able(self.player, castleside)
+                    && self.castling.free(&self.board, self.player, castleside)
+            })
+            .map(|&castleside| self.build_castle_move(castleside));
+        let simples = bitboard::squares(own).flat_map(|from| {
+            bitboard::squares(self.targets(from)).flat_map(move |to| self.build_moves(from, to))
+        });
+        castles.chain(simples).chain(self.build_drops()).collect()
+    }
+
     /// Generate the next legal moves for this game state.
-    /// On^2 for n squares
     pub fn gen_moves(&self) -> Vec<Move> {
-        let coords = self.board.coords();
+        let own = self.board.player_pieces(self.player);
         let castles = [Castleside::Kingside, Castleside::Queenside]
             .iter()
             .filter_map(|&castleside| self.make_castle_move(castleside));
-        let simples = coords
-            .iter()
-            .cartesian_product(coords.iter())
-            .filter_map(|(&from, &to)| self.make_simple_move(from, to));
-        castles.chain(simples).collect()
+        let simples = bitboard::squares(own).flat_map(|from| {
+            bitboard::squares(self.targets(from))
+                .flat_map(move |to| self.make_simple_moves(from, to))
+        });
+        let mut moves: Vec<Move> = castles.chain(simples).collect();
+        moves.extend(self.drop_moves());
+        moves
+    }
+
+    /// Count the leaf nodes of the legal move tree `depth` plies deep.
+    pub fn perft(&self, depth: u32) -> u64 {
+        match depth {
+            0 => 1,
+            1 => self.gen_moves().len() as u64,
+            _ => self
+                .gen_moves()
+                .iter()
+                .map(|m0ve| m0ve.next.perft(depth - 1))
+                .sum(),
+        }
+    }
+
+    /// Like `perft`, but broken down by the first move. Useful for finding
+    /// where a move generator goes wrong by comparing against another one.
+    pub fn divide(&self, depth: u32) -> Vec<(Move, u64)> {
+        if depth == 0 {
+            return vec![];
+        }
+        self.gen_moves()
+            .into_iter()
+            .map(|m0ve| {
+                let nodes = m0ve.next.perft(depth - 1);
+                (m0ve, nodes)
+            })
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::game::piece::Piece;
-    use crate::game::player::Player::*;
     use crate::game::pos::*;
     use crate::parsing::parse_fen;
 
     fn simple_state(board: Board, player: Player) -> State {
-        State {
-            board,
-            player,
-            en_passant: None,
-            castling: Castles::initial(),
-        }
+        State::new(board, player, None, Castles::initial(), 0, 1)
     }
 
     fn test_board() -> Board {
@@ -195,16 +533,26 @@ mod test {
     }
 
     #[test]
-    fn test_can_move_pseudo() {
+    fn test_gen_moves_only_moves_own_pieces() {
         let board = test_board();
 
         let white_move = simple_state(board.clone(), White);
         let black_move = simple_state(board.clone(), Black);
+        let can_move = |state: &State, from, to| {
+            state
+                .gen_moves()
+                .iter()
+                .any(|m0ve| m0ve.action == Action::Simple { from, to })
+        };
 
-        assert!(white_move.can_move_pseudo(e2, e3));
-        assert!(!white_move.can_move_pseudo(a1, a3));
-        assert!(!white_move.can_move_pseudo(b7, b6));
-        assert!(black_move.can_move_pseudo(b7, b6));
+        assert!(can_move(&white_move, e2, e3));
+        assert!(!can_move(&white_move, a1, a3));
+        assert!(!can_move(&white_move, b7, b6));
+        assert!(can_move(&black_move, b7, b6));
+        assert_eq!(
+            white_move.targets(b1),
+            bitboard::bit(a3) | bitboard::bit(c3)
+        );
     }
 
     #[test]
@@ -217,6 +565,11 @@ mod test {
 
         let in_check_state_2 = parse_fen("8/8/8/8/8/pk6/P1p5/1KP5 b - -").unwrap();
         assert!(in_check_state_2.in_check());
+
+        let no_king_state = parse_fen("8/8/8/8/8/1k6/8/1R6 b - -").unwrap();
+        assert!(no_king_state.in_check());
+        let no_king_state = parse_fen("8/8/8/8/8/1k6/8/1R6 w - -").unwrap();
+        assert!(!no_king_state.in_check());
     }
 
     #[test]
@@ -233,10 +586,10 @@ mod test {
             next_state.board.piece_at(f1),
             Some((Player::White, Piece::Rook))
         );
-        assert_eq!(next_state.castling.white.kingside, false);
-        assert_eq!(next_state.castling.white.queenside, false);
-        assert_eq!(next_state.castling.black.kingside, true);
-        assert_eq!(next_state.castling.black.queenside, true);
+        assert!(!next_state.castling.white.kingside);
+        assert!(!next_state.castling.white.queenside);
+        assert!(next_state.castling.black.kingside);
+        assert!(next_state.castling.black.queenside);
     }
 
     #[test]
@@ -252,8 +605,8 @@ mod test {
             parse_fen("rnbqkb1r/pp2pppp/3p1n2/2p5/2B5/4PN2/PPPP1PPP/RNBQK2R w KQkq - 0 4").unwrap();
         assert!(initial_state.can_castle(Castleside::Kingside));
         let next_state = initial_state.build_simple_move(h1, g1).next;
-        assert_eq!(next_state.castling.white.kingside, false);
-        assert_eq!(next_state.castling.white.queenside, true);
+        assert!(!next_state.castling.white.kingside);
+        assert!(next_state.castling.white.queenside);
     }
 
     #[test]
@@ -271,10 +624,10 @@ mod test {
             next_state.board.piece_at(d1),
             Some((Player::White, Piece::Rook))
         );
-        assert_eq!(next_state.castling.white.kingside, false);
-        assert_eq!(next_state.castling.white.queenside, false);
-        assert_eq!(next_state.castling.black.kingside, true);
-        assert_eq!(next_state.castling.black.queenside, true);
+        assert!(!next_state.castling.white.kingside);
+        assert!(!next_state.castling.white.queenside);
+        assert!(next_state.castling.black.kingside);
+        assert!(next_state.castling.black.queenside);
     }
 
     #[test]
@@ -292,8 +645,8 @@ mod test {
                 .unwrap();
         assert!(initial_state.can_castle(Castleside::Queenside));
         let next_state = initial_state.build_simple_move(a1, b1).next;
-        assert_eq!(next_state.castling.white.kingside, true);
-        assert_eq!(next_state.castling.white.queenside, false);
+        assert!(next_state.castling.white.kingside);
+        assert!(!next_state.castling.white.queenside);
     }
 
     #[test]
@@ -303,8 +656,8 @@ mod test {
                 .unwrap();
         assert!(initial_state.can_castle(Castleside::Queenside));
         let next_state = initial_state.build_simple_move(e1, f1).next;
-        assert_eq!(next_state.castling.white.kingside, false);
-        assert_eq!(next_state.castling.white.queenside, false);
+        assert!(!next_state.castling.white.kingside);
+        assert!(!next_state.castling.white.queenside);
     }
 
     #[test]
@@ -358,10 +711,10 @@ mod test {
             next_state.board.piece_at(f8),
             Some((Player::Black, Piece::Rook))
         );
-        assert_eq!(next_state.castling.black.kingside, false);
-        assert_eq!(next_state.castling.black.queenside, false);
-        assert_eq!(next_state.castling.white.kingside, true);
-        assert_eq!(next_state.castling.white.queenside, true);
+        assert!(!next_state.castling.black.kingside);
+        assert!(!next_state.castling.black.queenside);
+        assert!(next_state.castling.white.kingside);
+        assert!(next_state.castling.white.queenside);
     }
 
     #[test]
@@ -377,8 +730,8 @@ mod test {
             parse_fen("rnbqk2r/pppp1ppp/5n2/2b1p3/4P3/3P4/PPPB1PPP/RN1QKBNR b KQkq - 2 4").unwrap();
         assert!(initial_state.can_castle(Castleside::Kingside));
         let next_state = initial_state.build_simple_move(h8, g8).next;
-        assert_eq!(next_state.castling.black.kingside, false);
-        assert_eq!(next_state.castling.black.queenside, true);
+        assert!(!next_state.castling.black.kingside);
+        assert!(next_state.castling.black.queenside);
     }
 
     #[test]
@@ -387,8 +740,8 @@ mod test {
             parse_fen("rnbqk2r/pppp1ppp/5n2/2b1p3/4P3/3P4/PPPB1PPP/RN1QKBNR b KQkq - 2 4").unwrap();
         assert!(initial_state.can_castle(Castleside::Kingside));
         let next_state = initial_state.build_simple_move(e8, f8).next;
-        assert_eq!(next_state.castling.black.kingside, false);
-        assert_eq!(next_state.castling.black.queenside, false);
+        assert!(!next_state.castling.black.kingside);
+        assert!(!next_state.castling.black.queenside);
     }
 
     #[test]
@@ -405,10 +758,10 @@ mod test {
             next_state.board.piece_at(d8),
             Some((Player::Black, Piece::Rook))
         );
-        assert_eq!(next_state.castling.black.kingside, false);
-        assert_eq!(next_state.castling.black.queenside, false);
-        assert_eq!(next_state.castling.white.kingside, true);
-        assert_eq!(next_state.castling.white.queenside, true);
+        assert!(!next_state.castling.black.kingside);
+        assert!(!next_state.castling.black.queenside);
+        assert!(next_state.castling.white.kingside);
+        assert!(next_state.castling.white.queenside);
     }
 
     #[test]
@@ -424,8 +777,8 @@ mod test {
             parse_fen("r3kbnr/pppqpppp/2npb3/8/3P4/2P1PN2/PP3PPP/RNBQKB1R b KQkq - 0 5").unwrap();
         assert!(initial_state.can_castle(Castleside::Queenside));
         let next_state = initial_state.build_simple_move(a8, b8).next;
-        assert_eq!(next_state.castling.black.kingside, true);
-        assert_eq!(next_state.castling.black.queenside, false);
+        assert!(next_state.castling.black.kingside);
+        assert!(!next_state.castling.black.queenside);
     }
 
     #[test]
@@ -465,4 +818,218 @@ mod test {
             parse_fen("r3kbnr/p1pqpppp/Bpnp4/8/3P4/2P1PNP1/PP3P1P/RNBQK2R b KQkq - 0 6").unwrap();
         assert!(!initial_state.can_castle(Castleside::Queenside));
     }
+
+    #[test]
+    fn test_gen_moves_promotions() {
+        let state = parse_fen("8/4P3/8/8/8/k7/8/K7 w - - 0 1").unwrap();
+        let moves = state.gen_moves();
+        let mut promotions: Vec<Piece> = moves
+            .iter()
+            .filter_map(|m| match m.action {
+                Action::Promotion { from, to, piece } if from == e7 && to == e8 => Some(piece),
+                _ => None,
+            })
+            .collect();
+        promotions.sort_by_key(|&piece| piece as u8);
+        assert_eq!(promotions, vec![Bishop, Rook, Queen, Knight]);
+        assert!(!moves
+            .iter()
+            .any(|m| m.action == Action::Simple { from: e7, to: e8 }));
+    }
+
+    #[test]
+    fn test_divide() {
+        let state = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let divided = state.divide(2);
+        assert_eq!(divided.len(), 20);
+        assert!(divided.iter().all(|(_, nodes)| *nodes == 20));
+        assert_eq!(state.perft(0), 1);
+        assert_eq!(state.perft(2), 400);
+    }
+
+    #[test]
+    fn test_zobrist_key_is_updated_incrementally() {
+        fn walk(state: &State, depth: u32) {
+            let recomputed = State::new(
+                state.board.clone(),
+                state.player,
+                state.en_passant,
+                state.castling,
+                0,
+                1,
+            );
+            assert_eq!(state.zobrist_key(), recomputed.zobrist_key(), "{}", state);
+            if depth > 0 {
+                for m0ve in state.gen_moves() {
+                    walk(&m0ve.next, depth - 1);
+                }
+            }
+        }
+        for fen in &[
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            walk(&parse_fen(fen).unwrap(), 2);
+        }
+
+        let initial =
+            parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let mut state = initial.clone();
+        for &(from, to) in &[(g1, f3), (g8, f6), (f3, g1), (f6, g8)] {
+            state = state.build_simple_move(from, to).next;
+        }
+        assert_eq!(state.zobrist_key(), initial.zobrist_key());
+    }
+
+    #[test]
+    fn test_castling_white_kingside_not_allowed_after_rook_captured() {
+        let state = parse_fen("r3k2r/8/8/8/8/8/6n1/R3K2R b KQkq - 0 1").unwrap();
+        let m0ve = state.build_simple_move(g2, h1);
+        assert!(!m0ve.next.castling.white.kingside);
+        assert!(m0ve.next.castling.white.queenside);
+        assert!(m0ve.next.castling.black.kingside);
+    }
+
+    #[test]
+    fn test_gen_moves_en_passant_discovered_check() {
+        // bxc6 would open the fifth rank to the rook on h5.
+        let state = parse_fen("8/8/8/KPp4r/8/8/8/7k w - c6 0 1").unwrap();
+        let moves = state.gen_moves();
+        assert!(!moves
+            .iter()
+            .any(|m| m.action == Action::Simple { from: b5, to: c6 }));
+        assert!(moves
+            .iter()
+            .any(|m| m.action == Action::Simple { from: b5, to: b6 }));
+    }
+
+    #[test]
+    fn test_build_promotion_move() {
+        let state = parse_fen("3r4/4P3/8/8/8/k7/8/K7 w - - 0 1").unwrap();
+        let next_state = state.build_promotion_move(e7, d8, Knight).next;
+        assert_eq!(next_state.board.piece_at(d8), Some((White, Knight)));
+        assert_eq!(next_state.board.piece_at(e7), None);
+        assert_eq!(next_state.player, Black);
+
+        let state = parse_fen("8/8/8/8/8/K7/5p2/k7 b - - 0 1").unwrap();
+        let next_state = state.build_promotion_move(f2, f1, Queen).next;
+        assert_eq!(next_state.board.piece_at(f1), Some((Black, Queen)));
+    }
+
+    #[test]
+    fn test_clocks_after_moves() {
+        let state = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let state = state.build_simple_move(g1, f3).next;
+        assert_eq!((state.halfmove_clock, state.fullmove_number), (1, 1));
+        let state = state.build_simple_move(g8, f6).next;
+        assert_eq!((state.halfmove_clock, state.fullmove_number), (2, 2));
+        let state = state.build_simple_move(e2, e4).next;
+        assert_eq!((state.halfmove_clock, state.fullmove_number), (0, 2));
+        let state = state.build_simple_move(f6, e4).next;
+        assert_eq!((state.halfmove_clock, state.fullmove_number), (0, 3));
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        for fen in &[
+            "8/8/4k3/8/8/3K4/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3KB3/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/8/6n1 w - - 0 1",
+            "8/8/3bk3/8/8/3KB3/8/8 w - - 0 1",
+        ] {
+            assert!(parse_fen(fen).unwrap().insufficient_material(), "{}", fen);
+        }
+        for fen in &[
+            "8/8/4k3/8/8/3K4/4P3/8 w - - 0 1",
+            "8/8/4k3/8/8/3KBB2/8/8 w - - 0 1",
+            "8/8/2b1k3/8/8/3KB3/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3KN3/8/6n1 w - - 0 1",
+            "8/8/4k3/8/8/3K4/8/7R w - - 0 1",
+            "8/8/4k3/8/8/3K4/8/8[p] w - - 0 1",
+        ] {
+            assert!(!parse_fen(fen).unwrap().insufficient_material(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_same_position_en_passant() {
+        let same = |a: &str, b: &str| parse_fen(a).unwrap().same_position(&parse_fen(b).unwrap());
+        // No black pawn can take on e3.
+        assert!(same(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        ));
+        // The pawn on d4 can.
+        assert!(!same(
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        ));
+    }
+
+    #[test]
+    fn test_drops() {
+        let state = parse_fen("4k3/8/8/8/8/8/8/4K3[Nn] w - - 3 10").unwrap();
+        let drops: Vec<_> = state
+            .gen_moves()
+            .into_iter()
+            .filter(|m| matches!(m.action, Action::Drop { .. }))
+            .collect();
+        assert_eq!(drops.len(), 62);
+        let drop = drops
+            .iter()
+            .find(|m| {
+                m.action
+                    == Action::Drop {
+                        piece: Knight,
+                        to: e4,
+                    }
+            })
+            .unwrap();
+        assert_eq!(drop.next.board.piece_at(e4), Some((White, Knight)));
+        assert!(drop.next.pockets.unwrap().white.is_empty());
+        assert_eq!(drop.next.pockets.unwrap().black.count(Knight), 1);
+        assert_eq!(
+            (drop.next.halfmove_clock, drop.next.fullmove_number),
+            (4, 10)
+        );
+
+        // Pawns can't be dropped on the first or last rank.
+        let state = parse_fen("4k3/8/8/8/8/8/8/4K3[P] w - - 3 10").unwrap();
+        let drops = state.drop_moves();
+        assert_eq!(drops.len(), 48);
+        assert!(drops.iter().all(|m| m.next.halfmove_clock == 0));
+
+        // In check, only drops that block count.
+        let state = parse_fen("4k3/8/8/8/8/8/8/r3K3[N] w - - 0 1").unwrap();
+        let blocks: Vec<_> = state
+            .gen_moves()
+            .into_iter()
+            .filter_map(|m| match m.action {
+                Action::Drop { to, .. } => Some(to),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(blocks, vec![b1, c1, d1]);
+
+        // No drops without pockets.
+        let state = parse_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(state.drop_moves().is_empty());
+    }
+
+    #[test]
+    fn test_captures_go_to_the_pocket() {
+        let state = parse_fen("4k3/1P6/8/3p4/4P3/8/8/4K3[] w - - 0 1").unwrap();
+        let capture = state.build_simple_move(e4, d5).next;
+        assert_eq!(capture.pockets.unwrap().white.count(Pawn), 1);
+
+        // A promoted piece goes back into the pocket as a pawn.
+        let promoted = state.build_promotion_move(b7, b8, Queen).next;
+        assert_eq!(promoted.to_fen(), "1Q~2k3/8/8/3p4/4P3/8/8/4K3[] b - - 0 1");
+        let state = parse_fen("1Q~k5/8/8/8/8/8/8/4K3[] b - - 0 1").unwrap();
+        let recapture = state.build_simple_move(c8, b8).next;
+        assert_eq!(recapture.pockets.unwrap().promoted, bitboard::EMPTY);
+        let black = recapture.pockets.unwrap().black;
+        assert_eq!((black.count(Pawn), black.count(Queen)), (1, 0));
+    }
 }
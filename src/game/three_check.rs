@@ -0,0 +1,155 @@
+//! Three-check: standard chess, except that a player also wins by giving
+//! check three times. Each side's checks are counted in `State::checks`.
+
+use crate::game::m0ve::Move;
+use crate::game::outcome::{Outcome, Termination};
+use crate::game::player::Player::{self, *};
+use crate::game::state::State;
+use crate::game::variant::{Standard, Variant};
+
+/// The number of checks that wins the game.
+pub const CHECKS_TO_WIN: u32 = 3;
+
+/// How many checks each player has given.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Checks {
+    pub white: u32,
+    pub black: u32,
+}
+
+impl Checks {
+    pub fn get(self, player: Player) -> u32 {
+        match player {
+            White => self.white,
+            Black => self.black,
+        }
+    }
+
+    /// The counts once `player` gives another check.
+    pub fn after_check(mut self, player: Player) -> Self {
+        match player {
+            White => self.white += 1,
+            Black => self.black += 1,
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThreeCheck;
+
+impl ThreeCheck {
+    /// The player who has given three checks, if any.
+    fn winner(state: &State) -> Option<Player> {
+        let checks = state.checks?;
+        [White, Black]
+            .iter()
+            .copied()
+            .find(|&player| checks.get(player) >= CHECKS_TO_WIN)
+    }
+}
+
+impl Variant for ThreeCheck {
+    fn name(&self) -> &'static str {
+        "Three-check"
+    }
+
+    fn start_position(&self) -> State {
+        self.set_up(Standard.start_position())
+    }
+
+    fn legal_moves(&self, state: &State) -> Vec<Move> {
+        match ThreeCheck::winner(state) {
+            Some(_) => vec![],
+            None => state.gen_moves(),
+        }
+    }
+
+    fn outcome(&self, state: &State, repetitions: usize) -> Option<Outcome> {
+        if let Some(winner) = ThreeCheck::winner(state) {
+            return Some(Outcome::Win {
+                winner,
+                termination: Termination::ThreeChecks,
+            });
+        }
+        // Any piece but a king can still give check.
+        let bare_kings = state.board.occupied().count_ones() == 2;
+        match Standard.outcome(state, repetitions) {
+            Some(Outcome::Draw {
+                termination: Termination::InsufficientMaterial,
+            }) if !bare_kings => None,
+            outcome => outcome,
+        }
+    }
+
+    fn set_up(&self, state: State) -> State {
+        match state.checks {
+            Some(_) => state,
+            None => state.with_checks(Some(Checks::default())),
+        }
+    }
+
+    /// Marks check and mate by the standard rules, so the third check is
+    /// only `#` if it would also be checkmate.
+    fn san(&self, state: &State, m0ve: &Move) -> String {
+        Standard.san(state, m0ve)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Game;
+    use crate::parsing::parse_fen;
+
+    fn play(game: &mut Game<ThreeCheck>, coordinates: &[&str]) {
+        for coordinate in coordinates {
+            let m0ve = game
+                .moves()
+                .into_iter()
+                .find(|m0ve| m0ve.to_coordinate(&game.state) == *coordinate)
+                .unwrap();
+            game.play(m0ve);
+        }
+    }
+
+    #[test]
+    fn test_checks_are_counted() {
+        let mut game = Game::new(ThreeCheck);
+        play(&mut game, &["e2e4", "e7e5", "f1c4", "d7d6", "c4f7"]);
+        assert_eq!(game.state.checks, Some(Checks { white: 1, black: 0 }));
+        assert_eq!(
+            game.state.to_fen(),
+            "rnbqkbnr/ppp2Bpp/3p4/4p3/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 0 3 +1+0"
+        );
+        play(&mut game, &["e8f7", "d1h5", "g7g6"]);
+        assert_eq!(game.state.checks, Some(Checks { white: 2, black: 0 }));
+        assert_eq!(game.outcome(), None);
+    }
+
+    #[test]
+    fn test_three_checks_win() {
+        let state = parse_fen("4k3/8/8/8/8/8/8/4K2R w - - 0 1 +2+0").unwrap();
+        let mut game = Game::with_variant_state(ThreeCheck, state);
+        play(&mut game, &["h1h8"]);
+        assert_eq!(
+            game.outcome(),
+            Some(Outcome::Win {
+                winner: White,
+                termination: Termination::ThreeChecks
+            })
+        );
+        assert!(game.moves().is_empty());
+        assert_eq!(game.plies()[0].san, "Rh8+");
+
+        let state = parse_fen("6k1/1R6/8/8/8/8/8/R3K3 w - - 0 1 +2+0").unwrap();
+        let mut game = Game::with_variant_state(ThreeCheck, state);
+        play(&mut game, &["a1a8"]);
+        assert_eq!(game.plies()[0].san, "Ra8#");
+
+        // A lone knight can still give check.
+        let state = parse_fen("4k3/8/8/8/8/8/8/4K1N1 w - - 0 1 +0+0").unwrap();
+        assert_eq!(ThreeCheck.outcome(&state, 1), None);
+        assert!(Standard.outcome(&state, 1).is_some());
+    }
+}
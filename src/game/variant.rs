@@ -0,0 +1,330 @@
+//! Rule variants. A `Variant` decides where a game starts, which moves are
+//! legal and when the game is over, so a `Game` can be played under rules
+//! other than standard chess without changing `State`.
+
+use crate::game::antichess::Antichess;
+use crate::game::atomic::Atomic;
+use crate::game::board::Board;
+use crate::game::castles::Castles;
+use crate::game::king_of_the_hill::KingOfTheHill;
+use crate::game::m0ve::Move;
+use crate::game::move_description::Check;
+use crate::game::outcome::{Outcome, Termination};
+use crate::game::player::Player;
+use crate::game::pocket::Pockets;
+use crate::game::state::State;
+use crate::game::three_check::ThreeCheck;
+use std::fmt::Debug;
+
+/// A set of chess rules.
+pub trait Variant: Debug {
+    /// The variant's name, as in the PGN `Variant` tag.
+    fn name(&self) -> &'static str;
+
+    /// The position games start from.
+    fn start_position(&self) -> State;
+
+    /// The legal moves from `state`.
+    fn legal_moves(&self, state: &State) -> Vec<Move>;
+
+    /// How the game has ended at `state`, if it has, given the number of
+    /// times the position has occurred in the game so far.
+    fn outcome(&self, state: &State, repetitions: usize) -> Option<Outcome>;
+
+    /// `state`, with what this variant tracks beyond standard chess, such
+    /// as crazyhouse pockets, added if it's missing.
+    fn set_up(&self, state: State) -> State {
+        state
+    }
+
+    /// Is the player to move in check?
+    fn in_check(&self, state: &State) -> bool {
+        state.in_check()
+    }
+
+    /// `m0ve`, played from `state`, in standard algebraic notation, with
+    /// check and mate marked by this variant's rules.
+    fn san(&self, state: &State, m0ve: &Move) -> String {
+        let check = if !self.in_check(&m0ve.next) {
+            None
+        } else if self.legal_moves(&m0ve.next).is_empty() {
+            Some(Check::Checkmate)
+        } else {
+            Some(Check::Check)
+        };
+        m0ve.to_san(state, &self.legal_moves(state), check)
+    }
+
+    /// Count the leaf nodes of the legal move tree `depth` plies deep.
+    fn perft(&self, state: &State, depth: u32) -> u64 {
+        match depth {
+            0 => 1,
+            1 => self.legal_moves(state).len() as u64,
+            _ => self
+                .legal_moves(state)
+                .iter()
+                .map(|m0ve| self.perft(&m0ve.next, depth - 1))
+                .sum(),
+        }
+    }
+}
+
+/// Rules chosen at run time, e.g. from the command line or a PGN tag.
+impl<V: Variant + ?Sized> Variant for Box<V> {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn start_position(&self) -> State {
+        (**self).start_position()
+    }
+
+    fn legal_moves(&self, state: &State) -> Vec<Move> {
+        (**self).legal_moves(state)
+    }
+
+    fn outcome(&self, state: &State, repetitions: usize) -> Option<Outcome> {
+        (**self).outcome(state, repetitions)
+    }
+
+    fn set_up(&self, state: State) -> State {
+        (**self).set_up(state)
+    }
+
+    fn in_check(&self, state: &State) -> bool {
+        (**self).in_check(state)
+    }
+
+    fn san(&self, state: &State, m0ve: &Move) -> String {
+        (**self).san(state, m0ve)
+    }
+
+    fn perft(&self, state: &State, depth: u32) -> u64 {
+        (**self).perft(state, depth)
+    }
+}
+
+/// The variant called `name`, ignoring case, spaces and hyphens, so both
+/// PGN `Variant` tags like `King of the Hill` and `kingofthehill` work.
+/// Chess960 is standard chess from another start position.
+pub fn from_name(name: &str) -> Option<Box<dyn Variant>> {
+    let name: String = name
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-'))
+        .collect::<String>()
+        .to_ascii_lowercase();
+    Some(match name.as_str() {
+        "standard" | "chess960" | "fromposition" => Box::new(Standard),
+        "crazyhouse" => Box::new(Crazyhouse),
+        "atomic" => Box::new(Atomic),
+        "antichess" => Box::new(Antichess),
+        "threecheck" => Box::new(ThreeCheck),
+        "kingofthehill" => Box::new(KingOfTheHill),
+        _ => return None,
+    })
+}
+
+/// Standard chess, under FIDE rules. Draws by the fifty-move rule and
+/// threefold repetition are treated as automatic rather than claimed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Standard;
+
+impl Variant for Standard {
+    fn name(&self) -> &'static str {
+        "Standard"
+    }
+
+    fn start_position(&self) -> State {
+        State::new(
+            Board::initial(),
+            Player::White,
+            None,
+            Castles::initial(),
+            0,
+            1,
+        )
+    }
+
+    fn legal_moves(&self, state: &State) -> Vec<Move> {
+        state.gen_moves()
+    }
+
+    fn outcome(&self, state: &State, repetitions: usize) -> Option<Outcome> {
+        if self.legal_moves(state).is_empty() {
+            return Some(if state.in_check() {
+                Outcome::Win {
+                    winner: state.player.other(),
+                    termination: Termination::Checkmate,
+                }
+            } else {
+                Outcome::Draw {
+                    termination: Termination::Stalemate,
+                }
+            });
+        }
+
+        let termination = if state.halfmove_clock >= 100 {
+            Termination::FiftyMoveRule
+        } else if repetitions >= 3 {
+            Termination::ThreefoldRepetition
+        } else if state.insufficient_material() {
+            Termination::InsufficientMaterial
+        } else {
+            return None;
+        };
+        Some(Outcome::Draw { termination })
+    }
+}
+
+/// Crazyhouse: captured pieces join the capturer's pocket, and instead of
+/// moving, a player may drop a piece from their pocket on any empty square.
+/// Otherwise the rules are those of standard chess.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Crazyhouse;
+
+impl Variant for Crazyhouse {
+    fn name(&self) -> &'static str {
+        "Crazyhouse"
+    }
+
+    fn start_position(&self) -> State {
+        self.set_up(Standard.start_position())
+    }
+
+    fn legal_moves(&self, state: &State) -> Vec<Move> {
+        state.gen_moves()
+    }
+
+    fn outcome(&self, state: &State, repetitions: usize) -> Option<Outcome> {
+        Standard.outcome(state, repetitions)
+    }
+
+    fn set_up(&self, state: State) -> State {
+        match state.pockets {
+            Some(_) => state,
+            None => state.with_pockets(Some(Pockets::default())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Game;
+    use crate::parsing::parse_fen;
+    use crate::parsing::parse_pgn;
+    use crate::parsing::pgn::Pgn;
+
+    /// Standard chess where nothing may be captured.
+    #[derive(Debug)]
+    struct NoCaptures;
+
+    impl Variant for NoCaptures {
+        fn name(&self) -> &'static str {
+            "No captures"
+        }
+
+        fn start_position(&self) -> State {
+            Standard.start_position()
+        }
+
+        fn legal_moves(&self, state: &State) -> Vec<Move> {
+            Standard
+                .legal_moves(state)
+                .into_iter()
+                .filter(|m0ve| m0ve.captured.is_none())
+                .collect()
+        }
+
+        fn outcome(&self, state: &State, repetitions: usize) -> Option<Outcome> {
+            if self.legal_moves(state).is_empty() {
+                return Some(Outcome::Draw {
+                    termination: Termination::Stalemate,
+                });
+            }
+            Standard.outcome(state, repetitions)
+        }
+    }
+
+    #[test]
+    fn test_standard() {
+        let game = Game::default();
+        assert_eq!(game.state, crate::new_game().state);
+        assert_eq!(game.moves().len(), 20);
+        assert_eq!(game.outcome(), None);
+    }
+
+    #[test]
+    fn test_crazyhouse() {
+        let mut game = Game::new(Crazyhouse);
+        for san in &["e4", "d5", "exd5", "Qxd5", "Nc3", "Qa5", "P@d5"] {
+            let description = crate::parsing::parse_algebraic_notation(san).unwrap();
+            let m0ve = description.match_moves(&game.state, game.moves()).unwrap();
+            game.play(m0ve);
+        }
+        assert_eq!(
+            game.state.to_fen(),
+            "rnb1kbnr/ppp1pppp/8/q2P4/8/2N5/PPPP1PPP/R1BQKBNR[p] b KQkq - 0 4"
+        );
+        assert_eq!(game.outcome(), None);
+
+        let pgn = Pgn::from_game(&game, &[]);
+        assert_eq!(pgn.tag("Variant"), Some("Crazyhouse"));
+        assert_eq!(pgn.tag("FEN"), None);
+        let replayed = parse_pgn(&pgn.to_string()).unwrap().replay().unwrap();
+        assert_eq!(replayed.last().unwrap().next, game.state);
+    }
+
+    #[test]
+    fn test_from_name() {
+        for (name, expected) in &[
+            ("Standard", "Standard"),
+            ("chess960", "Standard"),
+            ("Crazyhouse", "Crazyhouse"),
+            ("atomic", "Atomic"),
+            ("Antichess", "Antichess"),
+            ("Three-check", "Three-check"),
+            ("threecheck", "Three-check"),
+            ("King of the Hill", "King of the Hill"),
+            ("kingofthehill", "King of the Hill"),
+        ] {
+            assert_eq!(
+                from_name(name).map(|variant| variant.name()),
+                Some(*expected)
+            );
+        }
+        assert!(from_name("Horde").is_none());
+
+        let mut game = Game::new(from_name("threecheck").unwrap());
+        assert_eq!(game.state.to_fen(), ThreeCheck.start_position().to_fen());
+        let m0ve = game.moves().pop().unwrap();
+        game.play(m0ve);
+        assert!(game.state.checks.is_some());
+    }
+
+    #[test]
+    fn test_custom_variant() {
+        let mut game = Game::new(NoCaptures);
+        for coordinate in &["e2e4", "d7d5"] {
+            let m0ve = game
+                .moves()
+                .into_iter()
+                .find(|m0ve| m0ve.to_coordinate(&game.state) == *coordinate)
+                .unwrap();
+            game.play(m0ve);
+        }
+        assert_eq!(game.moves().len(), game.state.gen_moves().len() - 1);
+
+        // The king's only move takes the pawn.
+        let state = parse_fen("8/8/8/8/8/2k5/p7/K7 w - - 0 1").unwrap();
+        let game = Game::with_variant_state(NoCaptures, state);
+        assert_eq!(
+            game.outcome(),
+            Some(Outcome::Draw {
+                termination: Termination::Stalemate
+            })
+        );
+        let pgn = Pgn::from_game(&game, &[]);
+        assert_eq!(pgn.tag("Variant"), Some("No captures"));
+    }
+}
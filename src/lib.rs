@@ -1,5 +1,10 @@
+pub mod book;
+pub mod engine;
+pub mod eval;
 pub mod game;
 pub mod parsing;
+pub mod protocol;
+pub mod tablebase;
 pub mod util;
 
 use crate::game::Game;
@@ -1,6 +1,11 @@
 pub mod algebraic_notation;
+pub mod coordinate_notation;
 pub mod fen;
+pub mod pgn;
 
 pub use self::algebraic_notation::parse_algebraic_notation;
 pub use self::algebraic_notation::parse_algebraic_notation_multiple;
+pub use self::coordinate_notation::parse_coordinate_notation;
 pub use self::fen::parse_fen;
+pub use self::pgn::parse_pgn;
+pub use self::pgn::parse_pgn_multiple;
@@ -1,11 +1,13 @@
 use crate::game::castles::Castleside;
-use crate::game::move_description::MoveDescription;
+use crate::game::move_description::{Annotation, Check, MoveDescription};
 use crate::game::piece::Piece;
 use crate::game::pos::Pos;
 use nom::branch::alt;
 use nom::bytes::complete::tag;
-use nom::combinator::value;
+use nom::character::complete::digit1;
+use nom::combinator::{map, map_res, opt, value};
 use nom::multi::separated_list0;
+use nom::sequence::preceded;
 use nom::IResult;
 
 fn piece(input: &str) -> IResult<&str, Piece> {
@@ -45,70 +47,114 @@ fn file(input: &str) -> IResult<&str, u8> {
     ))(input)
 }
 
+fn promotion_piece(input: &str) -> IResult<&str, Piece> {
+    alt((
+        value(Piece::Bishop, tag("B")),
+        value(Piece::Rook, tag("R")),
+        value(Piece::Queen, tag("Q")),
+        value(Piece::Knight, tag("N")),
+    ))(input)
+}
+
+/// Parses a piece a pawn can promote to, including the king as in
+/// antichess.
+fn promotion_target(input: &str) -> IResult<&str, Piece> {
+    alt((promotion_piece, value(Piece::King, tag("K"))))(input)
+}
+
+/// Parses an optional promotion suffix, either `=Q` or `Q`.
+fn promotion(input: &str) -> IResult<&str, Option<Piece>> {
+    opt(alt((
+        preceded(tag("="), promotion_target),
+        promotion_target,
+    )))(input)
+}
+
+/// Parses an optional capture marker.
+fn capture(input: &str) -> IResult<&str, bool> {
+    map(opt(tag("x")), |x| x.is_some())(input)
+}
+
+/// Parses an optional check or checkmate marker.
+fn check(input: &str) -> IResult<&str, Option<Check>> {
+    opt(alt((
+        value(Check::Check, tag("+")),
+        value(Check::Checkmate, tag("#")),
+    )))(input)
+}
+
+/// Parses an optional annotation glyph, either a symbol or a `$n` NAG.
+fn annotation(input: &str) -> IResult<&str, Option<Annotation>> {
+    opt(alt((
+        map_res(preceded(tag("$"), digit1), |n: &str| {
+            n.parse().map(Annotation::Nag)
+        }),
+        value(Annotation::Brilliant, tag("!!")),
+        value(Annotation::Blunder, tag("??")),
+        value(Annotation::Interesting, tag("!?")),
+        value(Annotation::Dubious, tag("?!")),
+        value(Annotation::Good, tag("!")),
+        value(Annotation::Mistake, tag("?")),
+    )))(input)
+}
+
 pub fn pos(input: &str) -> IResult<&str, Pos> {
     let (input, file) = file(input)?;
     let (input, rank) = rank(input)?;
     Ok((input, Pos { file, rank }))
 }
 
+/// Parses everything after the source of a simple move: the capture
+/// marker, destination, promotion, check and annotation.
+fn simple_rest(
+    src_piece: Piece,
+    src_rank: Option<u8>,
+    src_file: Option<u8>,
+) -> impl Fn(&str) -> IResult<&str, MoveDescription> {
+    move |input| {
+        let (input, capture) = capture(input)?;
+        let (input, dst_pos) = pos(input)?;
+        let (input, promotion) = promotion(input)?;
+        let (input, check) = check(input)?;
+        let (input, annotation) = annotation(input)?;
+        Ok((
+            input,
+            MoveDescription::Simple {
+                src_piece,
+                src_rank,
+                src_file,
+                capture,
+                dst_pos,
+                promotion,
+                check,
+                annotation,
+            },
+        ))
+    }
+}
+
 fn simple_disambiguate_all(input: &str) -> IResult<&str, MoveDescription> {
     let (input, src_piece) = piece(input)?;
     let (input, src_file) = file(input)?;
     let (input, src_rank) = rank(input)?;
-    let (input, dst_pos) = pos(input)?;
-    Ok((
-        input,
-        MoveDescription::Simple {
-            src_piece,
-            src_rank: Some(src_rank),
-            src_file: Some(src_file),
-            dst_pos,
-        },
-    ))
+    simple_rest(src_piece, Some(src_rank), Some(src_file))(input)
 }
 
 fn simple_disambiguate_rank(input: &str) -> IResult<&str, MoveDescription> {
     let (input, src_piece) = piece(input)?;
     let (input, src_rank) = rank(input)?;
-    let (input, dst_pos) = pos(input)?;
-    Ok((
-        input,
-        MoveDescription::Simple {
-            src_piece,
-            src_rank: Some(src_rank),
-            src_file: None,
-            dst_pos,
-        },
-    ))
+    simple_rest(src_piece, Some(src_rank), None)(input)
 }
 
 fn simple_disambiguate_file(input: &str) -> IResult<&str, MoveDescription> {
     let (input, src_piece) = piece(input)?;
     let (input, src_file) = file(input)?;
-    let (input, dst_pos) = pos(input)?;
-    Ok((
-        input,
-        MoveDescription::Simple {
-            src_piece,
-            src_rank: None,
-            src_file: Some(src_file),
-            dst_pos,
-        },
-    ))
+    simple_rest(src_piece, None, Some(src_file))(input)
 }
 
 fn simple_no_disambiguation(input: &str) -> IResult<&str, MoveDescription> {
     let (input, src_piece) = piece(input)?;
-    let (input, dst_pos) = pos(input)?;
-    Ok((
-        input,
-        MoveDescription::Simple {
-            src_piece,
-            src_rank: None,
-            src_file: None,
-            dst_pos,
-        },
-    ))
+    simple_rest(src_piece, None, None)(input)
 }
 
 pub fn simple(input: &str) -> IResult<&str, MoveDescription> {
@@ -120,25 +166,50 @@ pub fn simple(input: &str) -> IResult<&str, MoveDescription> {
     ))(input)
 }
 
+/// Parses castling, written with either letter O or digit zero.
 fn castle(input: &str) -> IResult<&str, MoveDescription> {
-    alt((
-        value(
-            MoveDescription::Castle {
-                castleside: Castleside::Queenside,
-            },
-            tag("O-O-O"),
-        ),
-        value(
-            MoveDescription::Castle {
-                castleside: Castleside::Kingside,
-            },
-            tag("O-O"),
-        ),
-    ))(input)
+    let (input, castleside) = alt((
+        value(Castleside::Queenside, tag("O-O-O")),
+        value(Castleside::Queenside, tag("0-0-0")),
+        value(Castleside::Kingside, tag("O-O")),
+        value(Castleside::Kingside, tag("0-0")),
+    ))(input)?;
+    let (input, check) = check(input)?;
+    let (input, annotation) = annotation(input)?;
+    Ok((
+        input,
+        MoveDescription::Castle {
+            castleside,
+            check,
+            annotation,
+        },
+    ))
+}
+
+/// Parses a crazyhouse drop such as `N@f3` or `P@e4`, where the pawn's
+/// letter may be left out.
+fn drop(input: &str) -> IResult<&str, MoveDescription> {
+    let (input, piece) = alt((
+        value(Piece::Pawn, tag("P")),
+        promotion_piece,
+        value(Piece::Pawn, tag("")),
+    ))(input)?;
+    let (input, dst_pos) = preceded(tag("@"), pos)(input)?;
+    let (input, check) = check(input)?;
+    let (input, annotation) = annotation(input)?;
+    Ok((
+        input,
+        MoveDescription::Drop {
+            piece,
+            dst_pos,
+            check,
+            annotation,
+        },
+    ))
 }
 
 fn algebraic_notation(input: &str) -> IResult<&str, MoveDescription> {
-    alt((simple, castle))(input)
+    alt((drop, simple, castle))(input)
 }
 
 fn algebraic_notation_multiple(input: &str) -> IResult<&str, Vec<MoveDescription>> {
@@ -148,9 +219,7 @@ fn algebraic_notation_multiple(input: &str) -> IResult<&str, Vec<MoveDescription
 /// Parses a movement description from algebraic notation.
 pub fn parse_algebraic_notation(input: &str) -> Result<MoveDescription, String> {
     match algebraic_notation(input) {
-        Ok((ref rem, ref _md)) if !rem.is_empty() => {
-            Err("parsing error: extra characters".to_string())
-        }
+        Ok((rem, _md)) if !rem.is_empty() => Err("parsing error: extra characters".to_string()),
         Ok((_remaining, md)) => Ok(md),
         Err(e) => Err(format!("parsing error: {:?}", e)),
     }
@@ -216,7 +285,11 @@ mod test {
                     src_piece: Piece::King,
                     src_rank: None,
                     src_file: None,
+                    capture: false,
                     dst_pos: e2,
+                    promotion: None,
+                    check: None,
+                    annotation: None,
                 }
             ))
         );
@@ -228,7 +301,11 @@ mod test {
                     src_piece: Piece::Pawn,
                     src_rank: None,
                     src_file: None,
+                    capture: false,
                     dst_pos: a1,
+                    promotion: None,
+                    check: None,
+                    annotation: None,
                 }
             ))
         );
@@ -242,7 +319,11 @@ mod test {
                 src_piece: Piece::King,
                 src_rank: None,
                 src_file: None,
+                capture: false,
                 dst_pos: e2,
+                promotion: None,
+                check: None,
+                annotation: None,
             })
         );
         assert_eq!(
@@ -251,7 +332,11 @@ mod test {
                 src_piece: Piece::Bishop,
                 src_rank: None,
                 src_file: Some(3),
+                capture: false,
                 dst_pos: b8,
+                promotion: None,
+                check: None,
+                annotation: None,
             })
         );
         assert_eq!(
@@ -260,7 +345,11 @@ mod test {
                 src_piece: Piece::Rook,
                 src_rank: Some(0),
                 src_file: None,
+                capture: false,
                 dst_pos: a3,
+                promotion: None,
+                check: None,
+                annotation: None,
             })
         );
         assert_eq!(
@@ -269,21 +358,95 @@ mod test {
                 src_piece: Piece::Queen,
                 src_rank: Some(3),
                 src_file: Some(7),
+                capture: false,
                 dst_pos: e1,
+                promotion: None,
+                check: None,
+                annotation: None,
+            })
+        );
+        assert_eq!(
+            parse_algebraic_notation("e8=Q"),
+            Ok(MoveDescription::Simple {
+                src_piece: Piece::Pawn,
+                src_rank: None,
+                src_file: None,
+                capture: false,
+                dst_pos: e8,
+                promotion: Some(Piece::Queen),
+                check: None,
+                annotation: None,
+            })
+        );
+        assert_eq!(
+            parse_algebraic_notation("e8N"),
+            Ok(MoveDescription::Simple {
+                src_piece: Piece::Pawn,
+                src_rank: None,
+                src_file: None,
+                capture: false,
+                dst_pos: e8,
+                promotion: Some(Piece::Knight),
+                check: None,
+                annotation: None,
+            })
+        );
+        assert_eq!(
+            parse_algebraic_notation("e8=K"),
+            Ok(MoveDescription::Simple {
+                src_piece: Piece::Pawn,
+                src_rank: None,
+                src_file: None,
+                capture: false,
+                dst_pos: e8,
+                promotion: Some(Piece::King),
+                check: None,
+                annotation: None,
             })
         );
+        assert_eq!(
+            parse_algebraic_notation("e8=P"),
+            Err(r#"parsing error: extra characters"#.to_string())
+        );
         assert_eq!(
             parse_algebraic_notation("O-O"),
             Ok(MoveDescription::Castle {
-                castleside: Castleside::Kingside
+                castleside: Castleside::Kingside,
+                check: None,
+                annotation: None,
             })
         );
         assert_eq!(
             parse_algebraic_notation("O-O-O"),
             Ok(MoveDescription::Castle {
-                castleside: Castleside::Queenside
+                castleside: Castleside::Queenside,
+                check: None,
+                annotation: None,
+            })
+        );
+        assert_eq!(
+            parse_algebraic_notation("P@e4"),
+            Ok(MoveDescription::Drop {
+                piece: Piece::Pawn,
+                dst_pos: e4,
+                check: None,
+                annotation: None,
+            })
+        );
+        assert_eq!(
+            parse_algebraic_notation("@e4"),
+            parse_algebraic_notation("P@e4")
+        );
+        assert_eq!(
+            parse_algebraic_notation("N@f7+"),
+            Ok(MoveDescription::Drop {
+                piece: Piece::Knight,
+                dst_pos: f7,
+                check: Some(Check::Check),
+                annotation: None,
             })
         );
+        assert!(parse_algebraic_notation("K@e4").is_err());
         assert_eq!(
             parse_algebraic_notation("Ze2"),
             Err(r#"parsing error: Error(Error { input: "Ze2", code: Tag })"#.to_string())
@@ -293,4 +456,132 @@ mod test {
             Err(r#"parsing error: extra characters"#.to_string())
         );
     }
+
+    #[test]
+    fn test_parse_captures_checks_and_annotations() {
+        assert_eq!(
+            parse_algebraic_notation("Nxe5"),
+            Ok(MoveDescription::Simple {
+                src_piece: Piece::Knight,
+                src_rank: None,
+                src_file: None,
+                capture: true,
+                dst_pos: e5,
+                promotion: None,
+                check: None,
+                annotation: None,
+            })
+        );
+        assert_eq!(
+            parse_algebraic_notation("exd5"),
+            Ok(MoveDescription::Simple {
+                src_piece: Piece::Pawn,
+                src_rank: None,
+                src_file: Some(4),
+                capture: true,
+                dst_pos: d5,
+                promotion: None,
+                check: None,
+                annotation: None,
+            })
+        );
+        assert_eq!(
+            parse_algebraic_notation("Qh5+"),
+            Ok(MoveDescription::Simple {
+                src_piece: Piece::Queen,
+                src_rank: None,
+                src_file: None,
+                capture: false,
+                dst_pos: h5,
+                promotion: None,
+                check: Some(Check::Check),
+                annotation: None,
+            })
+        );
+        assert_eq!(
+            parse_algebraic_notation("Qxf7#"),
+            Ok(MoveDescription::Simple {
+                src_piece: Piece::Queen,
+                src_rank: None,
+                src_file: None,
+                capture: true,
+                dst_pos: f7,
+                promotion: None,
+                check: Some(Check::Checkmate),
+                annotation: None,
+            })
+        );
+        assert_eq!(
+            parse_algebraic_notation("dxe8=Q+!!"),
+            Ok(MoveDescription::Simple {
+                src_piece: Piece::Pawn,
+                src_rank: None,
+                src_file: Some(3),
+                capture: true,
+                dst_pos: e8,
+                promotion: Some(Piece::Queen),
+                check: Some(Check::Check),
+                annotation: Some(Annotation::Brilliant),
+            })
+        );
+        assert_eq!(
+            parse_algebraic_notation("e4!?"),
+            Ok(MoveDescription::Simple {
+                src_piece: Piece::Pawn,
+                src_rank: None,
+                src_file: None,
+                capture: false,
+                dst_pos: e4,
+                promotion: None,
+                check: None,
+                annotation: Some(Annotation::Interesting),
+            })
+        );
+        assert_eq!(
+            parse_algebraic_notation("Nf3$14"),
+            Ok(MoveDescription::Simple {
+                src_piece: Piece::Knight,
+                src_rank: None,
+                src_file: None,
+                capture: false,
+                dst_pos: f3,
+                promotion: None,
+                check: None,
+                annotation: Some(Annotation::Nag(14)),
+            })
+        );
+        assert!(parse_algebraic_notation("e4$").is_err());
+        assert!(parse_algebraic_notation("e4$256").is_err());
+        assert_eq!(
+            parse_algebraic_notation("O-O+"),
+            Ok(MoveDescription::Castle {
+                castleside: Castleside::Kingside,
+                check: Some(Check::Check),
+                annotation: None,
+            })
+        );
+        assert_eq!(
+            parse_algebraic_notation("0-0"),
+            Ok(MoveDescription::Castle {
+                castleside: Castleside::Kingside,
+                check: None,
+                annotation: None,
+            })
+        );
+        assert_eq!(
+            parse_algebraic_notation("0-0-0?"),
+            Ok(MoveDescription::Castle {
+                castleside: Castleside::Queenside,
+                check: None,
+                annotation: Some(Annotation::Mistake),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_algebraic_notation_multiple() {
+        let descs =
+            parse_algebraic_notation_multiple("e4 e5 Nf3 Nc6 Bb5 a6 Bxc6 dxc6 0-0").unwrap();
+        assert_eq!(descs.len(), 9);
+    }
 }
@@ -0,0 +1,300 @@
+//! Coordinate notation, also called long algebraic or UCI notation: the
+//! source and destination squares, then the promotion piece if any, as in
+//! `e2e4` or `e7e8q`. Castling is written as the king's move, `e1g1`, or in
+//! Chess960 positions as the king taking its own rook, `b1a1`. Crazyhouse
+//! drops are written as the piece letter, `@` and the square, `N@f3`.
+
+use crate::game::castles::{CastleFiles, Castleside};
+use crate::game::m0ve::{Action, Move};
+use crate::game::piece::Piece;
+use crate::game::player::Player;
+use crate::game::pos::Pos;
+use crate::game::state::State;
+use crate::parsing::algebraic_notation::pos;
+use crate::parsing::fen::piece_to_fen;
+use nom::branch::alt;
+use nom::bytes::complete::{tag, tag_no_case};
+use nom::combinator::{opt, value};
+use nom::sequence::terminated;
+use nom::IResult;
+
+/// A move as written in coordinate notation, before it is matched against
+/// a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoordinateMove {
+    pub from: Pos,
+    pub to: Pos,
+    pub promotion: Option<Piece>,
+    /// The piece dropped on `to`, for a crazyhouse drop. `from` is then the
+    /// same as `to`.
+    pub drop: Option<Piece>,
+}
+
+impl CoordinateMove {
+    /// The action this move stands for when played from `state`: a king
+    /// moving two files or onto its own castling rook is castling.
+    pub fn to_action(self, state: &State) -> Action {
+        if let Some(piece) = self.drop {
+            return Action::Drop { piece, to: self.to };
+        }
+        let king_pos = state.board.get_king_pos(state.player);
+        let castleside = [Castleside::Kingside, Castleside::Queenside]
+            .iter()
+            .copied()
+            .find(|&castleside| {
+                let (_, king_to) = state.castling.king_squares(state.player, castleside);
+                let (rook_from, _) = state.castling.rook_squares(state.player, castleside);
+                let takes_rook = self.to == rook_from
+                    && state.board.piece_at(rook_from) == Some((state.player, Piece::Rook));
+                takes_rook || (self.to == king_to && self.from.abs_diff(self.to).file == 2)
+            });
+        match (self.promotion, castleside) {
+            (Some(piece), _) => Action::Promotion {
+                from: self.from,
+                to: self.to,
+                piece,
+            },
+            (None, Some(castleside)) if self.from == king_pos => Action::Castle { castleside },
+            _ => Action::Simple {
+                from: self.from,
+                to: self.to,
+            },
+        }
+    }
+
+    /// Find the legal move from `state` this describes.
+    pub fn match_moves(self, state: &State) -> Option<Move> {
+        let action = self.to_action(state);
+        state
+            .gen_moves()
+            .into_iter()
+            .find(|m0ve| m0ve.action == action)
+    }
+}
+
+fn promotion_piece(input: &str) -> IResult<&str, Piece> {
+    alt((
+        value(Piece::Queen, tag_no_case("q")),
+        value(Piece::Rook, tag_no_case("r")),
+        value(Piece::Bishop, tag_no_case("b")),
+        value(Piece::Knight, tag_no_case("n")),
+    ))(input)
+}
+
+/// A promotion piece, or the king, which pawns promote to in antichess.
+fn promotion_target(input: &str) -> IResult<&str, Piece> {
+    alt((promotion_piece, value(Piece::King, tag_no_case("k"))))(input)
+}
+
+fn drop_piece(input: &str) -> IResult<&str, Piece> {
+    alt((promotion_piece, value(Piece::Pawn, tag_no_case("p"))))(input)
+}
+
+fn drop_move(input: &str) -> IResult<&str, CoordinateMove> {
+    let (input, piece) = terminated(drop_piece, tag("@"))(input)?;
+    let (input, to) = pos(input)?;
+    Ok((
+        input,
+        CoordinateMove {
+            from: to,
+            to,
+            promotion: None,
+            drop: Some(piece),
+        },
+    ))
+}
+
+fn coordinate_notation(input: &str) -> IResult<&str, CoordinateMove> {
+    if let Ok(result) = drop_move(input) {
+        return Ok(result);
+    }
+    let (input, from) = pos(input)?;
+    let (input, to) = pos(input)?;
+    let (input, promotion) = opt(promotion_target)(input)?;
+    Ok((
+        input,
+        CoordinateMove {
+            from,
+            to,
+            promotion,
+            drop: None,
+        },
+    ))
+}
+
+/// Parse a move in coordinate notation.
+pub fn parse_coordinate_notation(input: &str) -> Result<CoordinateMove, String> {
+    match coordinate_notation(input) {
+        Ok(("", m0ve)) => Ok(m0ve),
+        Ok(_) => Err("parsing error: extra characters".to_string()),
+        Err(e) => Err(format!("parsing error: {:?}", e)),
+    }
+}
+
+fn pos_to_coordinate(pos: Pos) -> String {
+    format!("{}{}", (pos.file + b'a') as char, pos.rank + 1)
+}
+
+/// Write `action`, played from `state`, in coordinate notation.
+pub fn action_to_coordinate(action: &Action, state: &State) -> String {
+    match *action {
+        Action::Simple { from, to } => {
+            format!("{}{}", pos_to_coordinate(from), pos_to_coordinate(to))
+        }
+        Action::Promotion { from, to, piece } => format!(
+            "{}{}{}",
+            pos_to_coordinate(from),
+            pos_to_coordinate(to),
+            match piece {
+                Piece::Queen => "q",
+                Piece::Rook => "r",
+                Piece::Bishop => "b",
+                Piece::Knight => "n",
+                Piece::King => "k",
+                Piece::Pawn => "",
+            }
+        ),
+        Action::Castle { castleside } => {
+            let (from, king_to) = state.castling.king_squares(state.player, castleside);
+            let (rook_from, _) = state.castling.rook_squares(state.player, castleside);
+            let to = if state.castling.files == CastleFiles::standard() {
+                king_to
+            } else {
+                rook_from
+            };
+            format!("{}{}", pos_to_coordinate(from), pos_to_coordinate(to))
+        }
+        Action::Drop { piece, to } => format!(
+            "{}@{}",
+            piece_to_fen((Player::White, piece)),
+            pos_to_coordinate(to)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::pos::*;
+    use crate::parsing::parse_fen;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse_coordinate_notation("e2e4"),
+            Ok(CoordinateMove {
+                from: e2,
+                to: e4,
+                promotion: None,
+                drop: None
+            })
+        );
+        assert_eq!(
+            parse_coordinate_notation("a7a8N"),
+            Ok(CoordinateMove {
+                from: a7,
+                to: a8,
+                promotion: Some(Piece::Knight),
+                drop: None
+            })
+        );
+        assert_eq!(
+            parse_coordinate_notation("P@e4"),
+            Ok(CoordinateMove {
+                from: e4,
+                to: e4,
+                promotion: None,
+                drop: Some(Piece::Pawn)
+            })
+        );
+        assert_eq!(
+            parse_coordinate_notation("a7a8k").unwrap().promotion,
+            Some(Piece::King)
+        );
+        assert!(parse_coordinate_notation("e2e4q5").is_err());
+        assert!(parse_coordinate_notation("e2").is_err());
+        assert!(parse_coordinate_notation("Nf3").is_err());
+    }
+
+    #[test]
+    fn test_to_action() {
+        let state = parse_fen("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let action = |text| parse_coordinate_notation(text).unwrap().to_action(&state);
+
+        assert_eq!(
+            action("e1g1"),
+            Action::Castle {
+                castleside: Castleside::Kingside
+            }
+        );
+        assert_eq!(
+            action("e1c1"),
+            Action::Castle {
+                castleside: Castleside::Queenside
+            }
+        );
+        assert_eq!(action("e1f1"), Action::Simple { from: e1, to: f1 });
+        assert_eq!(action("a1c1"), Action::Simple { from: a1, to: c1 });
+        assert_eq!(
+            action("b7a8q"),
+            Action::Promotion {
+                from: b7,
+                to: a8,
+                piece: Piece::Queen
+            }
+        );
+    }
+
+    #[test]
+    fn test_action_to_coordinate() {
+        let state = parse_fen("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let moves: Vec<String> = state
+            .gen_moves()
+            .iter()
+            .map(|m0ve| action_to_coordinate(&m0ve.action, &state))
+            .collect();
+        for expected in &["e1g1", "e1c1", "b7b8q", "b7a8n", "a1a8", "e1f2"] {
+            assert!(moves.contains(&expected.to_string()), "{}", expected);
+        }
+    }
+
+    #[test]
+    fn test_match_moves() {
+        let state = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+        let m0ve = parse_coordinate_notation("e8c8")
+            .unwrap()
+            .match_moves(&state);
+        assert_eq!(
+            m0ve.unwrap().action,
+            Action::Castle {
+                castleside: Castleside::Queenside
+            }
+        );
+        let illegal = parse_coordinate_notation("e8e6")
+            .unwrap()
+            .match_moves(&state);
+        assert!(illegal.is_none());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for fen in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            // Chess960, where castling is written as taking the rook.
+            "r4k1r/8/8/8/8/8/8/1R3KR1 w KQ - 0 1",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            // Crazyhouse, with drops.
+            "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1",
+        ] {
+            let state = parse_fen(fen).unwrap();
+            for m0ve in state.gen_moves() {
+                let text = m0ve.to_coordinate(&state);
+                let parsed = parse_coordinate_notation(&text).unwrap();
+                assert_eq!(parsed.match_moves(&state), Some(m0ve), "{}", text);
+            }
+        }
+    }
+}
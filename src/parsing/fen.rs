@@ -1,17 +1,21 @@
+use crate::game::bitboard::{self, Bitboard};
 use crate::game::board::Board;
-use crate::game::castles::{CastleAbility, Castles};
+use crate::game::castles::{CastleFiles, Castles, Castleside};
 use crate::game::piece::Piece;
 use crate::game::player::Player;
+use crate::game::pocket::{Pocket, Pockets, POCKET_PIECES};
 use crate::game::pos::Pos;
 use crate::game::state::State;
+use crate::game::three_check::Checks;
 use crate::parsing::algebraic_notation::pos;
 
 use nom::branch::alt;
 use nom::bytes::complete::tag;
-use nom::character::complete::space1;
-use nom::combinator::{map, value};
+use nom::character::complete::{one_of, space1, u32};
+use nom::combinator::{map, opt, value};
 use nom::error::{make_error, ErrorKind};
-use nom::multi::{many1, separated_list0};
+use nom::multi::{many0, many1, separated_list0};
+use nom::sequence::delimited;
 use nom::Err;
 use nom::IResult;
 
@@ -19,6 +23,8 @@ use nom::IResult;
 enum SquareBuilder {
     Empty(u8),
     Occupied((Player, Piece)),
+    /// A piece promoted from a pawn, marked `~` in crazyhouse.
+    Promoted((Player, Piece)),
 }
 
 // parsers
@@ -58,10 +64,20 @@ fn piece(player: Player) -> impl Fn(&str) -> IResult<&str, SquareBuilder> {
     }
 }
 
+fn player_piece(input: &str) -> IResult<&str, SquareBuilder> {
+    let (input, square) = alt((piece(Player::White), piece(Player::Black)))(input)?;
+    let (input, promoted) = opt(tag("~"))(input)?;
+    match (square, promoted) {
+        (SquareBuilder::Occupied(player_piece), Some(_)) => {
+            Ok((input, SquareBuilder::Promoted(player_piece)))
+        }
+        (square, _) => Ok((input, square)),
+    }
+}
+
 fn square_builder(input: &str) -> IResult<&str, SquareBuilder> {
     alt((
-        piece(Player::White),
-        piece(Player::Black),
+        player_piece,
         // empty squares
         value(SquareBuilder::Empty(1), tag("1")),
         value(SquareBuilder::Empty(2), tag("2")),
@@ -113,22 +129,166 @@ fn en_passant_pos(input: &str) -> IResult<&str, Option<Pos>> {
     alt((value(None, tag("-")), map(pos, Some)))(input)
 }
 
-fn castling(input: &str) -> IResult<&str, Castles> {
-    let (input, res) = many1(alt((tag("-"), tag("K"), tag("Q"), tag("k"), tag("q"))))(input)?;
+/// The crazyhouse pockets written after the board, as in `[QNpp]`, with
+/// White's pieces in upper case.
+fn pockets(input: &str) -> IResult<&str, (Pocket, Pocket)> {
+    let (input, pieces) = delimited(
+        tag("["),
+        many0(alt((piece(Player::White), piece(Player::Black)))),
+        tag("]"),
+    )(input)?;
+    let mut white = Pocket::default();
+    let mut black = Pocket::default();
+    for square in pieces {
+        let (pocket, piece) = match square {
+            SquareBuilder::Occupied((_, Piece::King)) => {
+                return Err(Err::Error(make_error(input, ErrorKind::Verify)))
+            }
+            SquareBuilder::Occupied((Player::White, piece)) => (&mut white, piece),
+            SquareBuilder::Occupied((Player::Black, piece)) => (&mut black, piece),
+            _ => continue,
+        };
+        *pocket = match pocket.with(piece) {
+            Some(next) => next,
+            // More than 255 of a piece.
+            None => return Err(Err::Error(make_error(input, ErrorKind::TooLarge))),
+        };
+    }
+    Ok((input, (white, black)))
+}
 
-    Ok((
-        input,
-        Castles {
-            white: CastleAbility {
-                kingside: res.contains(&"K"),
-                queenside: res.contains(&"Q"),
-            },
-            black: CastleAbility {
-                kingside: res.contains(&"k"),
-                queenside: res.contains(&"q"),
-            },
+/// A castling right as written in FEN, before it is matched to a rook on
+/// the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CastlingRight {
+    /// `K` or `Q`: with the outermost rook on that side of the king.
+    Outermost(Player, Castleside),
+    /// `A`-`H`: with the rook on this file, as in Shredder-FEN and X-FEN.
+    File(Player, u8),
+}
+
+fn castling_right(input: &str) -> IResult<&str, CastlingRight> {
+    let (input, c) = one_of("KQkqABCDEFGHabcdefgh")(input)?;
+    let player = if c.is_ascii_uppercase() {
+        Player::White
+    } else {
+        Player::Black
+    };
+    let right = match c.to_ascii_lowercase() {
+        'k' => CastlingRight::Outermost(player, Castleside::Kingside),
+        'q' => CastlingRight::Outermost(player, Castleside::Queenside),
+        file => CastlingRight::File(player, file as u8 - b'a'),
+    };
+    Ok((input, right))
+}
+
+fn castling(input: &str) -> IResult<&str, Vec<CastlingRight>> {
+    alt((value(vec![], tag("-")), many1(castling_right)))(input)
+}
+
+/// Match castling `rights` to the king and rooks on `board`. Standard
+/// chess is assumed where the board doesn't say otherwise, e.g. `K`
+/// without a rook on the king's right.
+fn castles(board: &Board, rights: &[CastlingRight]) -> Option<Castles> {
+    let back_rank = |player| match player {
+        Player::White => 0,
+        Player::Black => 7,
+    };
+    let rook_files = |player| {
+        (0..8).filter(move |&file| {
+            board.piece_at(Pos {
+                rank: back_rank(player),
+                file,
+            }) == Some((player, Piece::Rook))
+        })
+    };
+
+    let mut king = None;
+    for right in rights {
+        let (CastlingRight::Outermost(player, _) | CastlingRight::File(player, _)) = *right;
+        let file = (0..8).find(|&file| {
+            board.piece_at(Pos {
+                rank: back_rank(player),
+                file,
+            }) == Some((player, Piece::King))
+        });
+        match (king, file) {
+            (Some(king), Some(file)) if king != file => return None,
+            (_, Some(file)) => king = Some(file),
+            _ => (),
+        }
+    }
+    let king = king.unwrap_or(4);
+
+    let mut castles = Castles {
+        files: CastleFiles {
+            king,
+            ..CastleFiles::standard()
         },
-    ))
+        ..Castles::none()
+    };
+    let mut set = [false, false];
+    for right in rights {
+        let (player, castleside, file) = match *right {
+            CastlingRight::Outermost(player, Castleside::Kingside) => {
+                let file = rook_files(player).filter(|&file| file > king).max();
+                (player, Castleside::Kingside, file.unwrap_or(7))
+            }
+            CastlingRight::Outermost(player, Castleside::Queenside) => {
+                let file = rook_files(player).filter(|&file| file < king).min();
+                (player, Castleside::Queenside, file.unwrap_or(0))
+            }
+            CastlingRight::File(player, file) if file > king => {
+                (player, Castleside::Kingside, file)
+            }
+            CastlingRight::File(player, file) if file < king => {
+                (player, Castleside::Queenside, file)
+            }
+            CastlingRight::File(..) => return None,
+        };
+        // Both sides castle with rooks on the same files.
+        let (index, rook) = match castleside {
+            Castleside::Kingside => (0, &mut castles.files.kingside),
+            Castleside::Queenside => (1, &mut castles.files.queenside),
+        };
+        if set[index] && *rook != file {
+            return None;
+        }
+        set[index] = true;
+        *rook = file;
+        let ability = match player {
+            Player::White => &mut castles.white,
+            Player::Black => &mut castles.black,
+        };
+        match castleside {
+            Castleside::Kingside => ability.kingside = true,
+            Castleside::Queenside => ability.queenside = true,
+        }
+    }
+    Some(castles)
+}
+
+/// Parses the halfmove clock and fullmove number.
+fn clocks(input: &str) -> IResult<&str, (u32, u32)> {
+    let (input, _) = space1(input)?;
+    let (input, halfmove_clock) = u32(input)?;
+    let (input, _) = space1(input)?;
+    let (input, fullmove_number) = u32(input)?;
+    if fullmove_number == 0 {
+        return Err(Err::Error(make_error(input, ErrorKind::Verify)));
+    }
+    Ok((input, (halfmove_clock, fullmove_number)))
+}
+
+/// Parses the checks given in three-check, written after the clocks as
+/// `+1+0`: White's count, then Black's.
+fn checks(input: &str) -> IResult<&str, Checks> {
+    let (input, _) = space1(input)?;
+    let (input, _) = tag("+")(input)?;
+    let (input, white) = u32(input)?;
+    let (input, _) = tag("+")(input)?;
+    let (input, black) = u32(input)?;
+    Ok((input, Checks { white, black }))
 }
 
 pub fn piece_to_fen(player_piece: (Player, Piece)) -> String {
@@ -156,11 +316,16 @@ fn fen(input: &str) -> IResult<&str, State> {
     }
 
     let mut squares = Vec::new();
+    let mut promoted = bitboard::EMPTY;
     for row in rows.iter().rev() {
         // fen starts at 8th rank and moves back
         for builder in row {
             match builder {
                 SquareBuilder::Occupied((player, piece)) => squares.push(Some((*player, *piece))),
+                SquareBuilder::Promoted((player, piece)) => {
+                    promoted |= bitboard::bit(bitboard::pos_at(squares.len() as u32));
+                    squares.push(Some((*player, *piece)))
+                }
                 SquareBuilder::Empty(n) => {
                     for _ in 0..*n {
                         squares.push(None)
@@ -175,31 +340,172 @@ fn fen(input: &str) -> IResult<&str, State> {
         64,
         "parsed board matrix expected to be length 64"
     );
+    let (input, pockets) = opt(pockets)(input)?;
+    // Promoted pieces only matter with pockets.
+    if pockets.is_none() && promoted != bitboard::EMPTY {
+        return Err(Err::Error(make_error(input, ErrorKind::Verify)));
+    }
 
     let (input, _) = space1(input)?;
     let (input, player) = current_player(input)?;
     let (input, _) = space1(input)?;
-    let (input, castling) = castling(input)?;
+    let (input, rights) = castling(input)?;
     let (input, _) = space1(input)?;
     let (input, en_passant) = en_passant_pos(input)?;
+    let (input, clocks) = opt(clocks)(input)?;
+    let (halfmove_clock, fullmove_number) = clocks.unwrap_or((0, 1));
+    let (input, checks) = match clocks {
+        Some(_) => opt(checks)(input)?,
+        None => (input, None),
+    };
 
     let board = Board::from_squares(squares.as_slice());
+    let castling = match castles(&board, &rights) {
+        Some(castling) => castling,
+        None => return Err(Err::Error(make_error(input, ErrorKind::Verify))),
+    };
     Ok((
         input,
-        State {
+        State::new(
             board,
             player,
             en_passant,
             castling,
-        },
+            halfmove_clock,
+            fullmove_number,
+        )
+        .with_pockets(pockets.map(|(white, black)| Pockets {
+            white,
+            black,
+            promoted,
+        }))
+        .with_checks(checks),
     ))
 }
 
+/// The board part of FEN, with `~` after the `promoted` pieces.
+fn board_to_fen(board: &Board, promoted: Bitboard) -> String {
+    let mut buf = String::new();
+    for rank in (0..8).rev() {
+        let mut empty = 0;
+        for file in 0..8 {
+            match board.piece_at(Pos { rank, file }) {
+                None => empty += 1,
+                Some(player_piece) => {
+                    if empty > 0 {
+                        buf.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    buf.push_str(&piece_to_fen(player_piece));
+                    if promoted & bitboard::bit(Pos { rank, file }) != 0 {
+                        buf.push('~');
+                    }
+                }
+            }
+        }
+        if empty > 0 {
+            buf.push_str(&empty.to_string());
+        }
+        if rank > 0 {
+            buf.push('/');
+        }
+    }
+    buf
+}
+
+/// Crazyhouse pockets in brackets, White's then Black's.
+fn pockets_to_fen(pockets: Pockets) -> String {
+    let mut buf = "[".to_string();
+    for &(player, pocket) in &[
+        (Player::White, pockets.white),
+        (Player::Black, pockets.black),
+    ] {
+        for &piece in &POCKET_PIECES {
+            for _ in 0..pocket.count(piece) {
+                buf.push_str(&piece_to_fen((player, piece)));
+            }
+        }
+    }
+    buf.push(']');
+    buf
+}
+
+/// Castling rights in X-FEN: `KQkq` when castling with the outermost rook on
+/// each side, as in standard chess, otherwise the file of the rook.
+fn castling_to_fen(board: &Board, castling: Castles) -> String {
+    let mut buf = String::new();
+    for &player in &[Player::White, Player::Black] {
+        for &castleside in &[Castleside::Kingside, Castleside::Queenside] {
+            if !castling.able(player, castleside) {
+                continue;
+            }
+            let (rook, _) = castling.rook_squares(player, castleside);
+            let outer_rook = (0..8)
+                .map(|file| Pos {
+                    rank: rook.rank,
+                    file,
+                })
+                .filter(|pos| match castleside {
+                    Castleside::Kingside => pos.file > rook.file,
+                    Castleside::Queenside => pos.file < rook.file,
+                })
+                .any(|pos| board.piece_at(pos) == Some((player, Piece::Rook)));
+            let c = if castling.files == CastleFiles::standard() || !outer_rook {
+                match castleside {
+                    Castleside::Kingside => 'k',
+                    Castleside::Queenside => 'q',
+                }
+            } else {
+                (b'a' + rook.file) as char
+            };
+            buf.push(match player {
+                Player::White => c.to_ascii_uppercase(),
+                Player::Black => c,
+            });
+        }
+    }
+    if buf.is_empty() {
+        buf.push('-');
+    }
+    buf
+}
+
+/// Serializes a state to Forsyth-Edwards notation, including the
+/// halfmove clock and fullmove number, and the checks given in three-check.
+pub fn state_to_fen(state: &State) -> String {
+    let player = match state.player {
+        Player::White => "w",
+        Player::Black => "b",
+    };
+    let en_passant = match state.en_passant {
+        None => "-".to_string(),
+        Some(pos) => format!("{}{}", (pos.file + b'a') as char, pos.rank + 1),
+    };
+    let board = match state.pockets {
+        Some(pockets) => board_to_fen(&state.board, pockets.promoted) + &pockets_to_fen(pockets),
+        None => board_to_fen(&state.board, bitboard::EMPTY),
+    };
+    let checks = match state.checks {
+        Some(checks) => format!(" +{}+{}", checks.white, checks.black),
+        None => String::new(),
+    };
+    format!(
+        "{} {} {} {} {} {}{}",
+        board,
+        player,
+        castling_to_fen(&state.board, state.castling),
+        en_passant,
+        state.halfmove_clock,
+        state.fullmove_number,
+        checks
+    )
+}
+
 /// Parses Forsyth-Edwards notation:
 /// https://en.wikipedia.org/wiki/Forsyth–Edwards_Notation
 pub fn parse_fen(input: &str) -> Result<State, String> {
     match fen(input) {
-        Ok((_, state)) => Ok(state),
+        Ok((rem, state)) if rem.trim().is_empty() => Ok(state),
         _ => Err(format!("parsing error: {}", input)),
     }
 }
@@ -207,6 +513,7 @@ pub fn parse_fen(input: &str) -> Result<State, String> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::game::castles::CastleAbility;
     use crate::game::pos::*;
 
     #[test]
@@ -250,39 +557,106 @@ mod test {
 
     #[test]
     fn test_parse_castling() {
-        assert_eq!(castling("KQkq"), Ok(("", Castles::initial())));
+        use CastlingRight::*;
         assert_eq!(
             castling("Qkq"),
             Ok((
                 "",
-                Castles {
-                    white: CastleAbility {
-                        queenside: true,
-                        kingside: false,
-                    },
-                    black: CastleAbility {
-                        queenside: true,
-                        kingside: true,
-                    },
-                }
+                vec![
+                    Outermost(Player::White, Castleside::Queenside),
+                    Outermost(Player::Black, Castleside::Kingside),
+                    Outermost(Player::Black, Castleside::Queenside),
+                ]
             ))
         );
         assert_eq!(
-            castling("-"),
+            castling("HAd"),
             Ok((
                 "",
-                Castles {
-                    white: CastleAbility {
-                        queenside: false,
-                        kingside: false,
-                    },
-                    black: CastleAbility {
-                        queenside: false,
-                        kingside: false,
-                    },
-                }
+                vec![
+                    File(Player::White, 7),
+                    File(Player::White, 0),
+                    File(Player::Black, 3)
+                ]
             ))
         );
+        assert_eq!(castling("-"), Ok(("", vec![])));
+        assert!(castling("X").is_err());
+    }
+
+    #[test]
+    fn test_parse_chess960_castling() {
+        // Shredder-FEN and X-FEN for the same position.
+        let shredder =
+            parse_fen("qrkbbnnr/pppppppp/8/8/8/8/PPPPPPPP/QRKBBNNR w HBhb - 0 1").unwrap();
+        let x_fen = parse_fen("qrkbbnnr/pppppppp/8/8/8/8/PPPPPPPP/QRKBBNNR w KQkq - 0 1").unwrap();
+        assert_eq!(shredder, x_fen);
+        assert_eq!(
+            shredder.castling,
+            Castles::with_files(CastleFiles {
+                king: 2,
+                kingside: 7,
+                queenside: 1,
+            })
+        );
+        assert_eq!(
+            shredder.to_fen(),
+            "qrkbbnnr/pppppppp/8/8/8/8/PPPPPPPP/QRKBBNNR w KQkq - 0 1"
+        );
+
+        // X-FEN names the file when another rook is further out.
+        let inner = "rkr4r/8/8/8/8/8/8/RKR4R w Cc - 0 1";
+        let state = parse_fen(inner).unwrap();
+        assert_eq!(state.castling.files.kingside, 2);
+        assert_eq!(state.to_fen(), inner);
+
+        // Rights with rooks on files that differ between the sides, or
+        // kings on different files, aren't Chess960.
+        assert!(parse_fen("rk5r/8/8/8/8/8/8/RK4R1 w GAha - 0 1").is_err());
+        assert!(parse_fen("1k5r/8/8/8/8/8/8/K6R w Hh - 0 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_crazyhouse_pockets() {
+        let input = "r1bqk2r/pppp1ppp/2n5/4p3/1b2P3/2N5/PPPP1PPP/R1BQK1NR~[NPbp] w KQkq - 0 5";
+        let state = parse_fen(input).unwrap();
+        let pockets = state.pockets.unwrap();
+        assert_eq!(pockets.white.count(Piece::Knight), 1);
+        assert_eq!(pockets.white.count(Piece::Pawn), 1);
+        assert_eq!(pockets.black.count(Piece::Bishop), 1);
+        assert_eq!(pockets.black.count(Piece::Pawn), 1);
+        assert_eq!(pockets.promoted, bitboard::bit(h1));
+        assert_eq!(state.to_fen(), input);
+
+        let empty = parse_fen("8/8/8/8/8/8/8/K6k[] w - - 0 1").unwrap();
+        assert_eq!(empty.pockets, Some(Pockets::default()));
+        assert_eq!(empty.to_fen(), "8/8/8/8/8/8/8/K6k[] w - - 0 1");
+        assert_eq!(
+            parse_fen("8/8/8/8/8/8/8/K6k w - - 0 1").unwrap().pockets,
+            None
+        );
+        assert!(parse_fen("8/8/8/8/8/8/8/K6k[K] w - - 0 1").is_err());
+        assert!(parse_fen("8/8/8/8/8/8/8/K5kQ~ w - - 0 1").is_err());
+        assert!(parse_fen("8/8/8/8/8/8/8/K5kQ~[] w - - 0 1").is_ok());
+        let pawns = "p".repeat(255);
+        let full = parse_fen(&format!("8/8/8/8/8/8/8/K6k[{}] w - - 0 1", pawns)).unwrap();
+        assert_eq!(full.pockets.unwrap().black.count(Piece::Pawn), 255);
+        assert!(parse_fen(&format!("8/8/8/8/8/8/8/K6k[{}p] w - - 0 1", pawns)).is_err());
+    }
+
+    #[test]
+    fn test_parse_three_check_counts() {
+        let input = "rnbqkbnr/ppp2Bpp/3p4/4p3/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 0 3 +1+0";
+        let state = parse_fen(input).unwrap();
+        assert_eq!(state.checks, Some(Checks { white: 1, black: 0 }));
+        assert_eq!(state.to_fen(), input);
+
+        assert_eq!(
+            parse_fen("8/8/8/8/8/8/8/K6k w - - 0 1").unwrap().checks,
+            None
+        );
+        assert!(parse_fen("8/8/8/8/8/8/8/K6k w - - 0 1 +1").is_err());
+        assert!(parse_fen("8/8/8/8/8/8/8/K6k w - - +1+0").is_err());
     }
 
     #[test]
@@ -307,7 +681,8 @@ mod test {
                 black: CastleAbility {
                     kingside: false,
                     queenside: true,
-                }
+                },
+                files: CastleFiles::standard(),
             }
         )
     }
@@ -318,4 +693,68 @@ mod test {
         let state_res = fen(input);
         assert!(state_res.is_err(), "recognizes invalid fen");
     }
+
+    #[test]
+    fn test_parse_clocks() {
+        let state =
+            parse_fen("rnbqkb1r/pp2pppp/3p1n2/2p5/2B5/4PN2/PPPP1PPP/RNBQK2R w KQkq - 3 4").unwrap();
+        assert_eq!(state.halfmove_clock, 3);
+        assert_eq!(state.fullmove_number, 4);
+
+        let state = parse_fen("8/8/8/8/8/pkp5/8/PKP5 w - -").unwrap();
+        assert_eq!(state.halfmove_clock, 0);
+        assert_eq!(state.fullmove_number, 1);
+    }
+
+    #[test]
+    fn test_parse_invalid_clocks() {
+        assert!(parse_fen("8/8/8/8/8/pkp5/8/PKP5 w - - 0 0").is_err());
+        assert!(parse_fen("8/8/8/8/8/pkp5/8/PKP5 w - - x 1").is_err());
+        assert!(parse_fen("8/8/8/8/8/pkp5/8/PKP5 w - - 3").is_err());
+        assert!(parse_fen("8/8/8/8/8/pkp5/8/PKP5 w - - 3 1 junk").is_err());
+    }
+
+    #[test]
+    fn test_state_to_fen() {
+        let input = "r1b1kb1r/pppppppp/8/8/4P3/8/PPPP1PPP/R1B1KB1R b Kq e3 0 1";
+        assert_eq!(parse_fen(input).unwrap().to_fen(), input);
+
+        let input = "8/8/8/8/8/pkp5/8/PKP5 w - - 12 40";
+        assert_eq!(parse_fen(input).unwrap().to_fen(), input);
+
+        let initial = crate::new_game().state;
+        assert_eq!(
+            initial.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn test_fen_round_trip_over_generated_positions() {
+        // Walk a handful of pseudo-random games and check every position
+        // survives serialization.
+        for seed in 1..=8u64 {
+            let mut rng = seed;
+            let mut state = crate::new_game().state;
+            if seed % 2 == 0 {
+                // Crazyhouse, so that drops and pockets are covered too.
+                state = state.with_pockets(Some(Pockets::default()));
+            } else if seed % 4 == 3 {
+                // Three-check, so that check counts are covered.
+                state = state.with_checks(Some(Checks::default()));
+            }
+            for _ in 0..40 {
+                assert_eq!(parse_fen(&state.to_fen()), Ok(state.clone()));
+                let mut moves = state.gen_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                rng = rng
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                let index = (rng >> 33) as usize % moves.len();
+                state = moves.swap_remove(index).next;
+            }
+        }
+    }
 }
@@ -0,0 +1,677 @@
+use crate::game::castles::CastleFiles;
+use crate::game::m0ve::Move;
+use crate::game::move_description::MoveDescription;
+use crate::game::player::Player;
+use crate::game::state::State;
+use crate::game::variant::{self, Standard, Variant};
+use crate::game::Game;
+use crate::parsing::algebraic_notation::parse_algebraic_notation;
+use crate::parsing::fen::parse_fen;
+
+use nom::branch::alt;
+use nom::bytes::complete::{is_not, tag, take_till, take_while1};
+use nom::character::complete::{char, digit1, multispace0, space0};
+use nom::combinator::{map, map_res, opt, value};
+use nom::error::{make_error, ErrorKind};
+use nom::multi::{many0, many1};
+use nom::sequence::{delimited, preceded, terminated};
+use nom::Err;
+use nom::IResult;
+use std::fmt;
+
+/// The tags every PGN export is expected to carry, in order.
+pub const SEVEN_TAG_ROSTER: [&str; 7] =
+    ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+/// The longest line written in exported movetext.
+const MAX_LINE_LENGTH: usize = 80;
+
+/// A move in PGN movetext, along with the commentary attached to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnMove {
+    pub san: String,
+    pub description: MoveDescription,
+    /// Numeric annotation glyphs, e.g. `$1`.
+    pub nags: Vec<u8>,
+    /// Comments before the move, which only the first move of a game or
+    /// variation can have.
+    pub leading_comments: Vec<String>,
+    /// Comments following the move.
+    pub comments: Vec<String>,
+    /// Alternatives to this move.
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+/// A single game parsed from PGN.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pgn {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<PgnMove>,
+    /// The game termination marker: `1-0`, `0-1`, `1/2-1/2` or `*`.
+    pub result: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    InvalidFen(String),
+    /// The `Variant` tag names rules this crate doesn't know.
+    UnsupportedVariant(String),
+    /// The mainline move at `ply` (counting from 1) is illegal or ambiguous.
+    IllegalMove {
+        ply: usize,
+        san: String,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::InvalidFen(fen) => write!(f, "invalid FEN tag: {}", fen),
+            ReplayError::UnsupportedVariant(name) => write!(f, "unsupported variant: {}", name),
+            ReplayError::IllegalMove { ply, san } => {
+                write!(f, "illegal move at ply {}: {}", ply, san)
+            }
+        }
+    }
+}
+
+impl Pgn {
+    /// Build a PGN record of `game`. The seven tag roster is filled in
+    /// with `tags`, falling back to unknown values, and `FEN`/`SetUp` tags
+    /// are added when the game did not start from the initial position,
+    /// along with `Variant` for other rules or Chess960.
+    pub fn from_game<V: Variant>(game: &Game<V>, tags: &[(&str, &str)]) -> Pgn {
+        let result = game
+            .outcome()
+            .map_or("*", |outcome| outcome.result_token())
+            .to_string();
+
+        let mut all_tags: Vec<(String, String)> = SEVEN_TAG_ROSTER
+            .iter()
+            .map(|&name| {
+                let value = match name {
+                    "Result" => result.as_str(),
+                    "Date" => "????.??.??",
+                    _ => "?",
+                };
+                (name.to_string(), value.to_string())
+            })
+            .collect();
+        for &(name, value) in tags {
+            if name == "Result" {
+                continue;
+            }
+            match all_tags.iter_mut().find(|(tag_name, _)| tag_name == name) {
+                Some(tag) => tag.1 = value.to_string(),
+                None => all_tags.push((name.to_string(), value.to_string())),
+            }
+        }
+        if game.variant.name() != Standard.name() {
+            all_tags.push(("Variant".to_string(), game.variant.name().to_string()));
+        } else if game.initial.castling.files != CastleFiles::standard() {
+            all_tags.push(("Variant".to_string(), "Chess960".to_string()));
+        }
+        if game.initial != game.variant.start_position() {
+            all_tags.push(("SetUp".to_string(), "1".to_string()));
+            all_tags.push(("FEN".to_string(), game.initial.to_fen()));
+        }
+
+        let moves = game
+            .plies()
+            .iter()
+            .map(|ply| PgnMove {
+                san: ply.san.clone(),
+                description: parse_algebraic_notation(&ply.san)
+                    .expect("generated SAN is parseable"),
+                nags: vec![],
+                leading_comments: vec![],
+                comments: vec![],
+                variations: vec![],
+            })
+            .collect();
+
+        Pgn {
+            tags: all_tags,
+            moves,
+            result: Some(result),
+        }
+    }
+
+    /// The value of the tag `name`, if present.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag_name, _)| tag_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The rules named by the `Variant` tag, standard chess without one.
+    pub fn variant(&self) -> Result<Box<dyn Variant>, ReplayError> {
+        match self.tag("Variant") {
+            Some(name) => variant::from_name(name)
+                .ok_or_else(|| ReplayError::UnsupportedVariant(name.to_string())),
+            None => Ok(Box::new(Standard)),
+        }
+    }
+
+    /// The position the game starts from: the `FEN` tag if there is one,
+    /// otherwise the variant's start position. A FEN that leaves out what
+    /// the variant tracks, such as crazyhouse pockets, starts it empty.
+    pub fn initial_state(&self) -> Result<State, ReplayError> {
+        let variant = self.variant()?;
+        let state = match self.tag("FEN") {
+            Some(fen) => parse_fen(fen).map_err(|_| ReplayError::InvalidFen(fen.to_string()))?,
+            None => variant.start_position(),
+        };
+        Ok(variant.set_up(state))
+    }
+
+    /// Play through the mainline under the rules of the game's variant,
+    /// returning every move made.
+    pub fn replay(&self) -> Result<Vec<Move>, ReplayError> {
+        let variant = self.variant()?;
+        let mut state = self.initial_state()?;
+        let mut moves = Vec::with_capacity(self.moves.len());
+        for (i, pgn_move) in self.moves.iter().enumerate() {
+            match pgn_move
+                .description
+                .match_moves(&state, variant.legal_moves(&state))
+            {
+                Some(m0ve) => {
+                    state = m0ve.next.clone();
+                    moves.push(m0ve);
+                }
+                None => {
+                    return Err(ReplayError::IllegalMove {
+                        ply: i + 1,
+                        san: pgn_move.san.clone(),
+                    })
+                }
+            }
+        }
+        Ok(moves)
+    }
+}
+
+// parsers
+
+enum Element {
+    Move(String, MoveDescription),
+    Nag(u8),
+    Comment(String),
+    Variation(Vec<PgnMove>),
+}
+
+fn tag_value(input: &str) -> IResult<&str, String> {
+    let (mut input, _) = char('"')(input)?;
+    let mut value = String::new();
+    let mut chars = input.char_indices();
+    loop {
+        match chars.next() {
+            Some((i, '"')) => {
+                input = &input[i + 1..];
+                break;
+            }
+            Some((_, '\\')) => match chars.next() {
+                Some((_, c)) => value.push(c),
+                None => return Err(Err::Error(make_error(input, ErrorKind::Escaped))),
+            },
+            Some((_, c)) => value.push(c),
+            None => return Err(Err::Error(make_error(input, ErrorKind::Char))),
+        }
+    }
+    Ok((input, value))
+}
+
+fn tag_pair(input: &str) -> IResult<&str, (String, String)> {
+    let (input, _) = char('[')(input)?;
+    let (input, _) = space0(input)?;
+    let (input, name) = take_while1(|c: char| c.is_alphanumeric() || c == '_')(input)?;
+    let (input, _) = space0(input)?;
+    let (input, value) = tag_value(input)?;
+    let (input, _) = space0(input)?;
+    let (input, _) = char(']')(input)?;
+    Ok((input, (name.to_string(), value)))
+}
+
+fn result(input: &str) -> IResult<&str, String> {
+    map(
+        alt((tag("1-0"), tag("0-1"), tag("1/2-1/2"), tag("*"))),
+        |r: &str| r.to_string(),
+    )(input)
+}
+
+/// Move numbers such as `12.` or `12...`, which carry no information.
+fn move_number(input: &str) -> IResult<&str, ()> {
+    value((), terminated(digit1, many1(char('.'))))(input)
+}
+
+/// Parses a `{...}` comment, collapsing runs of whitespace.
+fn brace_comment(input: &str) -> IResult<&str, String> {
+    map(
+        delimited(char('{'), take_till(|c| c == '}'), char('}')),
+        |comment: &str| comment.split_whitespace().collect::<Vec<_>>().join(" "),
+    )(input)
+}
+
+fn line_comment(input: &str) -> IResult<&str, String> {
+    map(
+        preceded(char(';'), take_till(|c| c == '\n')),
+        |comment: &str| comment.trim().to_string(),
+    )(input)
+}
+
+fn nag(input: &str) -> IResult<&str, u8> {
+    map_res(preceded(char('$'), digit1), |n: &str| n.parse::<u8>())(input)
+}
+
+fn san(input: &str) -> IResult<&str, (String, MoveDescription)> {
+    map_res(is_not(" \t\r\n(){};$"), |san: &str| {
+        parse_algebraic_notation(san).map(|description| (san.to_string(), description))
+    })(input)
+}
+
+fn element(input: &str) -> IResult<&str, Option<Element>> {
+    alt((
+        map(move_number, |_| None),
+        map(brace_comment, |c| Some(Element::Comment(c))),
+        map(line_comment, |c| Some(Element::Comment(c))),
+        map(nag, |n| Some(Element::Nag(n))),
+        map(variation, |v| Some(Element::Variation(v))),
+        map(san, |(san, description)| {
+            Some(Element::Move(san, description))
+        }),
+    ))(input)
+}
+
+/// Fold movetext elements into moves, attaching commentary and variations
+/// to the move they follow.
+fn line(input: &str) -> IResult<&str, Vec<PgnMove>> {
+    let (input, elements) = many0(terminated(element, multispace0))(input)?;
+    let mut moves: Vec<PgnMove> = vec![];
+    let mut leading_comments = vec![];
+    for element in elements.into_iter().flatten() {
+        match (element, moves.last_mut()) {
+            (Element::Move(san, description), _) => moves.push(PgnMove {
+                san,
+                description,
+                nags: vec![],
+                leading_comments: std::mem::take(&mut leading_comments),
+                comments: vec![],
+                variations: vec![],
+            }),
+            (Element::Comment(comment), None) => leading_comments.push(comment),
+            (Element::Comment(comment), Some(last)) => last.comments.push(comment),
+            (Element::Nag(n), Some(last)) => last.nags.push(n),
+            (Element::Variation(variation), Some(last)) => last.variations.push(variation),
+            (_, None) => return Err(Err::Error(make_error(input, ErrorKind::Verify))),
+        }
+    }
+    Ok((input, moves))
+}
+
+fn variation(input: &str) -> IResult<&str, Vec<PgnMove>> {
+    delimited(terminated(char('('), multispace0), line, char(')'))(input)
+}
+
+fn pgn(input: &str) -> IResult<&str, Pgn> {
+    let (input, _) = multispace0(input)?;
+    let (input, tags) = many0(terminated(tag_pair, multispace0))(input)?;
+    let (input, moves) = line(input)?;
+    let (input, result) = opt(result)(input)?;
+    let (input, _) = multispace0(input)?;
+    Ok((
+        input,
+        Pgn {
+            tags,
+            moves,
+            result,
+        },
+    ))
+}
+
+fn non_empty_pgn(input: &str) -> IResult<&str, Pgn> {
+    let (rest, game) = pgn(input)?;
+    if game.tags.is_empty() && game.moves.is_empty() && game.result.is_none() {
+        return Err(Err::Error(make_error(input, ErrorKind::Eof)));
+    }
+    Ok((rest, game))
+}
+
+fn pgn_multiple(input: &str) -> IResult<&str, Vec<Pgn>> {
+    many0(non_empty_pgn)(input)
+}
+
+/// Parses a single game in Portable Game Notation:
+/// https://en.wikipedia.org/wiki/Portable_Game_Notation
+pub fn parse_pgn(input: &str) -> Result<Pgn, String> {
+    match pgn(input) {
+        Ok(("", game)) => Ok(game),
+        Ok((rem, _)) => Err(format!(
+            "parsing error: unexpected {:?}",
+            rem.lines().next().unwrap_or("")
+        )),
+        Err(e) => Err(format!("parsing error: {:?}", e)),
+    }
+}
+
+/// Parses a PGN database containing any number of games.
+pub fn parse_pgn_multiple(input: &str) -> Result<Vec<Pgn>, String> {
+    match pgn_multiple(input) {
+        Ok((rem, games)) if rem.trim().is_empty() => Ok(games),
+        Ok((rem, _)) => Err(format!(
+            "parsing error: unexpected {:?}",
+            rem.lines().next().unwrap_or("")
+        )),
+        Err(e) => Err(format!("parsing error: {:?}", e)),
+    }
+}
+
+// writer
+
+fn escape_tag_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Append the movetext tokens for `moves`, where the first move is made by
+/// `player` on move `fullmove_number`.
+fn movetext_tokens(
+    moves: &[PgnMove],
+    mut fullmove_number: u32,
+    mut player: Player,
+    tokens: &mut Vec<String>,
+) {
+    let mut needs_number = true;
+    for pgn_move in moves {
+        for comment in &pgn_move.leading_comments {
+            tokens.push(format!("{{{}}}", comment));
+        }
+        match player {
+            Player::White => tokens.push(format!("{}.", fullmove_number)),
+            Player::Black if needs_number => tokens.push(format!("{}...", fullmove_number)),
+            Player::Black => (),
+        }
+        tokens.push(pgn_move.san.clone());
+        needs_number = false;
+
+        for nag in &pgn_move.nags {
+            tokens.push(format!("${}", nag));
+        }
+        for comment in &pgn_move.comments {
+            tokens.push(format!("{{{}}}", comment));
+            needs_number = true;
+        }
+        for variation in &pgn_move.variations {
+            let mut variation_tokens = vec![];
+            movetext_tokens(variation, fullmove_number, player, &mut variation_tokens);
+            if let Some(first) = variation_tokens.first_mut() {
+                first.insert(0, '(');
+            }
+            if let Some(last) = variation_tokens.last_mut() {
+                last.push(')');
+            }
+            tokens.append(&mut variation_tokens);
+            needs_number = true;
+        }
+
+        if player == Player::Black {
+            fullmove_number += 1;
+        }
+        player = player.other();
+    }
+}
+
+impl fmt::Display for Pgn {
+    /// Export format PGN: roster tags first, then movetext wrapped at 80
+    /// columns.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let roster = SEVEN_TAG_ROSTER
+            .iter()
+            .filter_map(|&name| self.tags.iter().find(|(tag_name, _)| tag_name == name));
+        let others = self
+            .tags
+            .iter()
+            .filter(|(name, _)| !SEVEN_TAG_ROSTER.contains(&name.as_str()));
+        for (name, value) in roster.chain(others) {
+            writeln!(f, "[{} \"{}\"]", name, escape_tag_value(value))?;
+        }
+        writeln!(f)?;
+
+        let (fullmove_number, player) = match self.initial_state() {
+            Ok(state) => (state.fullmove_number, state.player),
+            Err(_) => (1, Player::White),
+        };
+        let mut tokens = vec![];
+        movetext_tokens(&self.moves, fullmove_number, player, &mut tokens);
+        tokens.push(self.result.clone().unwrap_or_else(|| "*".to_string()));
+
+        let mut line = String::new();
+        for token in tokens.iter().flat_map(|token| token.split(' ')) {
+            if !line.is_empty() && line.len() + 1 + token.len() > MAX_LINE_LENGTH {
+                writeln!(f, "{}", line)?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(token);
+        }
+        writeln!(f, "{}", line)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::piece::Piece;
+    use crate::game::pos::*;
+
+    const IMMORTAL: &str = r#"[Event "London"]
+[Site "London ENG"]
+[Date "1851.06.21"]
+[Round "?"]
+[White "Adolf Anderssen"]
+[Black "Lionel Kieseritzky"]
+[Result "1-0"]
+[ECO "C33"]
+
+1.e4 e5 2.f4 exf4 3.Bc4 Qh4+ 4.Kf1 b5 5.Bxb5 Nf6 6.Nf3 Qh6 7.d3 Nh5 8.Nh4 Qg5
+9.Nf5 c6 10.g4 Nf6 11.Rg1 cxb5 12.h4 Qg6 13.h5 Qg5 14.Qf3 Ng8 15.Bxf4 Qf6
+16.Nc3 Bc5 17.Nd5 Qxb2 18.Bd6 Bxg1 {It is from this move that Black's defeat
+stems.} 19.e5 Qxa1+ 20.Ke2 Na6 21.Nxg7+ Kd8 22.Qf6+ Nxf6 23.Be7# 1-0
+"#;
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(
+            tag_pair(r#"[White "Adolf \"the\" Anderssen"]"#),
+            Ok((
+                "",
+                ("White".to_string(), r#"Adolf "the" Anderssen"#.to_string())
+            ))
+        );
+
+        let game = parse_pgn(IMMORTAL).unwrap();
+        assert_eq!(game.tags.len(), 8);
+        for name in SEVEN_TAG_ROSTER.iter() {
+            assert!(game.tag(name).is_some(), "{} tag present", name);
+        }
+        assert_eq!(game.tag("Black"), Some("Lionel Kieseritzky"));
+        assert_eq!(game.tag("Annotator"), None);
+    }
+
+    #[test]
+    fn test_parse_movetext() {
+        let game = parse_pgn(IMMORTAL).unwrap();
+        assert_eq!(game.moves.len(), 45);
+        assert_eq!(game.moves[0].san, "e4");
+        assert_eq!(game.moves[44].san, "Be7#");
+        assert_eq!(
+            game.moves[35].comments,
+            vec!["It is from this move that Black's defeat stems.".to_string()]
+        );
+        assert_eq!(game.result, Some("1-0".to_string()));
+    }
+
+    #[test]
+    fn test_parse_variations_nags_and_comments() {
+        let input = "{Opening} 1. e4 $1 e5 (1... c5 ; the Sicilian\n 2. Nf3 (2. c3) d6) 2. Nf3 *";
+        let game = parse_pgn(input).unwrap();
+        assert_eq!(game.moves.len(), 3);
+        assert_eq!(game.moves[0].leading_comments, vec!["Opening".to_string()]);
+        assert!(game.moves[0].comments.is_empty());
+        assert_eq!(game.moves[0].nags, vec![1]);
+        let variation = &game.moves[1].variations[0];
+        assert_eq!(variation.len(), 3);
+        assert_eq!(variation[0].san, "c5");
+        assert_eq!(variation[0].comments, vec!["the Sicilian".to_string()]);
+        assert_eq!(variation[1].variations[0][0].san, "c3");
+        assert_eq!(game.result, Some("*".to_string()));
+    }
+
+    #[test]
+    fn test_parse_invalid_pgn() {
+        assert!(parse_pgn("1. e4 Zz9 1-0").is_err());
+        assert!(parse_pgn("1. e4 (e5 1-0").is_err());
+    }
+
+    #[test]
+    fn test_parse_pgn_multiple() {
+        let input = format!("{}\n{}", IMMORTAL, "[Event \"?\"]\n\n1. d4 d5 1/2-1/2\n");
+        let games = parse_pgn_multiple(&input).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[1].moves.len(), 2);
+        assert_eq!(games[1].result, Some("1/2-1/2".to_string()));
+    }
+
+    #[test]
+    fn test_replay() {
+        let game = parse_pgn(IMMORTAL).unwrap();
+        let moves = game.replay().unwrap();
+        let state = &moves.last().unwrap().next;
+        assert_eq!(
+            state.board.piece_at(e7),
+            Some((Player::White, Piece::Bishop))
+        );
+        assert!(state.gen_moves().is_empty());
+    }
+
+    #[test]
+    fn test_replay_from_fen() {
+        let input = r#"[SetUp "1"]
+[FEN "8/4P3/8/8/8/k7/8/K7 w - - 0 1"]
+
+1. e8=Q Kb3 *"#;
+        let moves = parse_pgn(input).unwrap().replay().unwrap();
+        assert_eq!(moves.len(), 2);
+        assert_eq!(
+            moves[0].next.board.piece_at(e8),
+            Some((Player::White, Piece::Queen))
+        );
+    }
+
+    #[test]
+    fn test_replay_reports_illegal_move() {
+        let game = parse_pgn("1. e4 e5 2. Ke3 *").unwrap();
+        assert_eq!(
+            game.replay(),
+            Err(ReplayError::IllegalMove {
+                ply: 3,
+                san: "Ke3".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_replay_variant() {
+        // Antichess: the captures are compulsory, and the king is taken.
+        let input = r#"[Variant "Antichess"]
+
+1. e3 d5 2. Ba6 bxa6 3. Ke2 Qd6 4. Kd3 Qxh2 5. Rxh2 *"#;
+        let pgn = parse_pgn(input).unwrap();
+        assert_eq!(pgn.variant().unwrap().name(), "Antichess");
+        assert_eq!(pgn.replay().unwrap().len(), 9);
+        let refused = parse_pgn("[Variant \"Antichess\"]\n\n1. e3 d5 2. Ba6 Nc6 *").unwrap();
+        assert_eq!(
+            refused.replay(),
+            Err(ReplayError::IllegalMove {
+                ply: 4,
+                san: "Nc6".to_string()
+            })
+        );
+
+        let three_check = parse_pgn("[Variant \"Three-check\"]\n\n1. e4 f6 2. Qh5+ *").unwrap();
+        let moves = three_check.replay().unwrap();
+        assert_eq!(moves[2].next.checks.map(|checks| checks.white), Some(1));
+
+        let unknown = parse_pgn("[Variant \"Horde\"]\n\n1. e4 *").unwrap();
+        assert_eq!(
+            unknown.replay(),
+            Err(ReplayError::UnsupportedVariant("Horde".to_string()))
+        );
+    }
+
+    fn played_game(initial: State, sans: &[&str]) -> Game {
+        let mut game = Game::with_state(initial);
+        for san in sans {
+            let description = parse_algebraic_notation(san).unwrap();
+            game.play(
+                description
+                    .match_moves(&game.state, game.state.gen_moves())
+                    .unwrap(),
+            );
+        }
+        game
+    }
+
+    #[test]
+    fn test_from_game() {
+        let game = played_game(Game::default().state, &["f3", "e5", "g4", "Qh4#"]);
+        let pgn = Pgn::from_game(&game, &[("White", "Fool"), ("Annotator", "me")]);
+        assert_eq!(
+            pgn.to_string(),
+            r#"[Event "?"]
+[Site "?"]
+[Date "????.??.??"]
+[Round "?"]
+[White "Fool"]
+[Black "?"]
+[Result "0-1"]
+[Annotator "me"]
+
+1. f3 e5 2. g4 Qh4# 0-1
+"#
+        );
+    }
+
+    #[test]
+    fn test_from_game_with_setup() {
+        let initial = parse_fen("8/4P3/8/8/8/k7/8/K7 b - - 0 40").unwrap();
+        let game = played_game(initial, &["Kb3", "e8=Q"]);
+        let pgn = Pgn::from_game(&game, &[]);
+        assert_eq!(pgn.tag("SetUp"), Some("1"));
+        assert_eq!(pgn.tag("FEN"), Some("8/4P3/8/8/8/k7/8/K7 b - - 0 40"));
+        assert_eq!(pgn.tag("Result"), Some("*"));
+        assert!(pgn.to_string().ends_with("\n40... Kb3 41. e8=Q *\n"));
+    }
+
+    #[test]
+    fn test_write_wraps_movetext() {
+        let game = parse_pgn(IMMORTAL).unwrap();
+        let written = game.to_string();
+        assert!(written.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+        assert_eq!(parse_pgn(&written), Ok(game));
+    }
+
+    #[test]
+    fn test_write_round_trips_variations() {
+        let input = "{Opening} 1. e4 $1 e5 (1... c5 {the Sicilian} 2. Nf3 (2. c3) d6) 2. Nf3 *";
+        let game = parse_pgn(input).unwrap();
+        let written = game.to_string();
+        let movetext = written.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert!(movetext.ends_with(
+            "{Opening} 1. e4 $1 e5 (1... c5 {the Sicilian} 2. Nf3 (2. c3) 2... d6) 2. Nf3 *"
+        ));
+        assert_eq!(parse_pgn(&written), Ok(game));
+    }
+}
@@ -0,0 +1,110 @@
+//! Text protocols for driving the engine from chess GUIs and tournament
+//! managers.
+
+pub mod uci;
+pub mod xboard;
+
+use crate::engine::{SearchResult, MATE};
+use crate::game::{m0ve::Move, piece::Piece, player::Player, state::State};
+use crate::parsing::{parse_coordinate_notation, parse_fen};
+use std::time::Duration;
+
+/// The position in `fen`, which the engine can only play from with one
+/// king of each color.
+pub(crate) fn parse_position(fen: &str) -> Result<State, String> {
+    let state = parse_fen(fen)?;
+    for &player in &[Player::White, Player::Black] {
+        if state.board.pieces(player, Piece::King).count_ones() != 1 {
+            return Err(format!("{} needs exactly one king", player));
+        }
+    }
+    Ok(state)
+}
+
+/// The legal move from `state` written as `text` in coordinate notation.
+pub(crate) fn find_move(state: &State, text: &str) -> Option<Move> {
+    parse_coordinate_notation(text)
+        .ok()
+        .and_then(|m0ve| m0ve.match_moves(state))
+}
+
+/// The moves of a principal variation starting from `state`, in coordinate
+/// notation.
+pub(crate) fn pv_coordinates(result: &SearchResult, state: &State) -> Vec<String> {
+    let mut current = state;
+    let mut moves = vec![];
+    for m0ve in &result.pv {
+        moves.push(m0ve.to_coordinate(current));
+        current = &m0ve.next;
+    }
+    moves
+}
+
+/// How long to think about a move with `time_left` on the clock, gaining
+/// `increment` per move, with `moves_to_go` until the next time control (or
+/// sudden death if `None`). `overhead` is kept back for communication lag.
+pub(crate) fn allocate_time(
+    time_left: Duration,
+    increment: Duration,
+    moves_to_go: Option<u32>,
+    overhead: Duration,
+) -> Duration {
+    let moves_to_go = moves_to_go.unwrap_or(30).max(1);
+    let share = (time_left / moves_to_go).saturating_add(increment.saturating_mul(3) / 4);
+    let available = time_left.saturating_sub(overhead);
+    share
+        .min(available)
+        .saturating_sub(overhead)
+        .max(Duration::from_millis(1))
+}
+
+/// Whether a score is a forced mate, see `SearchResult::mate_in`.
+pub(crate) fn is_mate_score(score: i32) -> bool {
+    score.abs() > MATE - crate::engine::MAX_DEPTH as i32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::{castles::Castleside, m0ve::Action};
+    use crate::parsing::parse_fen;
+
+    #[test]
+    fn test_find_move() {
+        let state = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+        let m0ve = find_move(&state, "e8c8").unwrap();
+        assert_eq!(
+            m0ve.action,
+            Action::Castle {
+                castleside: Castleside::Queenside
+            }
+        );
+        assert!(find_move(&state, "e8e6").is_none());
+        assert!(find_move(&state, "nonsense").is_none());
+    }
+
+    #[test]
+    fn test_parse_position() {
+        assert!(parse_position("4k3/8/8/8/8/8/8/4K3 w - - 0 1").is_ok());
+        assert_eq!(
+            parse_position("4k3/8/8/8/8/8/4P3/8 w - - 0 1").err(),
+            Some("White needs exactly one king".to_string())
+        );
+        assert!(parse_position("4k3/8/8/8/8/8/8/3KK3 w - - 0 1").is_err());
+        assert!(parse_position("8/8/8/8/8/8/8/4K3 w - - 0 1").is_err());
+        assert!(parse_position("nonsense").is_err());
+    }
+
+    #[test]
+    fn test_allocate_time() {
+        let ms = Duration::from_millis;
+        assert_eq!(allocate_time(ms(60_000), ms(0), None, ms(0)), ms(2_000));
+        assert_eq!(
+            allocate_time(ms(60_000), ms(1_000), Some(10), ms(50)),
+            ms(6_700)
+        );
+        assert_eq!(allocate_time(ms(10), ms(0), Some(1), ms(50)), ms(1));
+        let forever = Duration::MAX;
+        assert_eq!(allocate_time(ms(60_000), forever, None, ms(0)), ms(60_000));
+    }
+}
@@ -0,0 +1,468 @@
+//! The Universal Chess Interface, see
+//! http://wbec-ridderkerk.nl/html/UCIProtocol.html
+//!
+//! Commands are read a line at a time. Searches run on their own thread so
+//! that `stop`, `isready` and `quit` are answered while thinking.
+
+use crate::book::Book;
+use crate::engine::transposition::{self, TranspositionTable};
+use crate::engine::{self, Limits, SearchResult};
+use crate::game::{m0ve::Move, player::Player::*, state::State};
+use crate::protocol::{allocate_time, find_move, is_mate_score, parse_position, pv_coordinates};
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_MOVE_OVERHEAD: u64 = 10;
+const MAX_MOVE_OVERHEAD: u64 = 5_000;
+
+/// Speak UCI, reading commands from `input` and answering on `output` until
+/// `quit` or the end of the input. At the end of the input, a running search
+/// is allowed to finish if it has a depth, node or time limit, and is
+/// stopped otherwise.
+pub fn run<R, W>(input: R, output: W)
+where
+    R: BufRead,
+    W: Write + Send + 'static,
+{
+    let mut uci = Uci::new(output);
+    for line in input.lines() {
+        match line {
+            Ok(line) => {
+                if !uci.handle(&line) {
+                    return;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    uci.finish();
+}
+
+struct Search {
+    handle: JoinHandle<()>,
+    stop: Arc<AtomicBool>,
+    /// Will the search end by itself?
+    bounded: bool,
+}
+
+struct Uci<W> {
+    output: Arc<Mutex<W>>,
+    state: State,
+    move_overhead: Duration,
+    /// Shared with the search thread, which holds the lock while searching.
+    table: Arc<Mutex<TranspositionTable>>,
+    /// Moves are played from here while it has any, see the `BookFile`
+    /// option.
+    book: Option<Book>,
+    search: Option<Search>,
+}
+
+fn send<W: Write>(output: &Mutex<W>, line: &str) {
+    let mut output = output.lock().unwrap();
+    // There's no one to tell if the GUI has gone away.
+    let _ = writeln!(output, "{}", line).and_then(|_| output.flush());
+}
+
+impl<W: Write + Send + 'static> Uci<W> {
+    fn new(output: W) -> Self {
+        Uci {
+            output: Arc::new(Mutex::new(output)),
+            state: crate::new_game().state,
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD),
+            table: Arc::new(Mutex::new(TranspositionTable::default())),
+            book: None,
+            search: None,
+        }
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.output, line)
+    }
+
+    /// Handle one command. Returns false on `quit`.
+    fn handle(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("uci") => {
+                self.send(&format!("id name chess {}", env!("CARGO_PKG_VERSION")));
+                self.send(&format!("id author {}", env!("CARGO_PKG_AUTHORS")));
+                self.send(&format!(
+                    "option name Move Overhead type spin default {} min 0 max {}",
+                    DEFAULT_MOVE_OVERHEAD, MAX_MOVE_OVERHEAD
+                ));
+                self.send(&format!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    transposition::DEFAULT_MEGABYTES,
+                    transposition::MAX_MEGABYTES
+                ));
+                self.send("option name BookFile type string default <empty>");
+                self.send("uciok");
+            }
+            Some("isready") => self.send("readyok"),
+            Some("ucinewgame") => {
+                self.stop();
+                self.state = crate::new_game().state;
+                self.table.lock().unwrap().clear();
+            }
+            Some("position") => {
+                self.stop();
+                match position(tokens) {
+                    Ok(state) => self.state = state,
+                    Err(e) => self.send(&format!("info string {}", e)),
+                }
+            }
+            Some("setoption") => {
+                if let Err(e) = self.set_option(line) {
+                    self.send(&format!("info string {}", e));
+                }
+            }
+            Some("go") => self.go(tokens),
+            Some("stop") => self.stop(),
+            Some("quit") => {
+                self.stop();
+                return false;
+            }
+            Some("debug") | Some("ponderhit") | Some("register") | None => (),
+            Some(command) => self.send(&format!("info string unknown command {}", command)),
+        }
+        true
+    }
+
+    fn set_option(&mut self, line: &str) -> Result<(), String> {
+        let rest = line.trim_start_matches("setoption").trim();
+        let rest = rest.strip_prefix("name").ok_or("expected option name")?;
+        let (name, value) = match rest.find(" value ") {
+            Some(index) => (rest[..index].trim(), rest[index + 7..].trim()),
+            None => (rest.trim(), ""),
+        };
+        match name.to_lowercase().as_str() {
+            "move overhead" => {
+                let millis: u64 = value
+                    .parse()
+                    .map_err(|_| format!("invalid Move Overhead {}", value))?;
+                self.move_overhead = Duration::from_millis(millis.min(MAX_MOVE_OVERHEAD));
+                Ok(())
+            }
+            "hash" => {
+                let megabytes: usize = value
+                    .parse()
+                    .map_err(|_| format!("invalid Hash {}", value))?;
+                self.stop();
+                *self.table.lock().unwrap() =
+                    TranspositionTable::new(megabytes.clamp(1, transposition::MAX_MEGABYTES));
+                Ok(())
+            }
+            "bookfile" => {
+                self.book = match value {
+                    "" | "<empty>" => None,
+                    path => Some(Book::open(path)?),
+                };
+                Ok(())
+            }
+            _ => Err(format!("unknown option {}", name)),
+        }
+    }
+
+    fn go<'a>(&mut self, tokens: impl Iterator<Item = &'a str>) {
+        self.stop();
+
+        let params = GoParams::parse(tokens);
+        if !params.infinite {
+            if let Some(m0ve) = self.book_move() {
+                self.send(&format!("bestmove {}", m0ve.to_coordinate(&self.state)));
+                return;
+            }
+        }
+        let (time_left, increment) = match self.state.player {
+            White => (params.wtime, params.winc),
+            Black => (params.btime, params.binc),
+        };
+        let clock = time_left.map(|time_left| {
+            allocate_time(
+                time_left,
+                increment.unwrap_or_default(),
+                params.moves_to_go,
+                self.move_overhead,
+            )
+        });
+        let limits = Limits {
+            depth: params.depth,
+            nodes: params.nodes,
+            movetime: params.movetime.or(clock),
+        };
+
+        let bounded = !params.infinite
+            && (limits.depth.is_some() || limits.nodes.is_some() || limits.movetime.is_some());
+        let stop = Arc::new(AtomicBool::new(false));
+        let infinite = params.infinite;
+        let state = self.state.clone();
+        let output = Arc::clone(&self.output);
+        let thread_stop = Arc::clone(&stop);
+        let table = Arc::clone(&self.table);
+        let handle = thread::spawn(move || {
+            let mut table = table.lock().unwrap();
+            let result = engine::search(&state, limits, &mut table, &thread_stop, |result| {
+                send(&output, &info(result, &state))
+            });
+            // An infinite search only reports its move once told to stop.
+            while infinite && !thread_stop.load(Ordering::SeqCst) {
+                thread::park();
+            }
+            send(&output, &bestmove(&result, &state));
+        });
+        self.search = Some(Search {
+            handle,
+            stop,
+            bounded,
+        });
+    }
+
+    /// A move from the book for the current position, picked at random by
+    /// weight.
+    fn book_move(&self) -> Option<Move> {
+        let random = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        self.book.as_ref()?.weighted_move(&self.state, random)
+    }
+
+    /// Stop any running search, waiting for it to report its move.
+    fn stop(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.stop.store(true, Ordering::SeqCst);
+            search.handle.thread().unpark();
+            let _ = search.handle.join();
+        }
+    }
+
+    /// Let a running search finish, stopping it if it would never end.
+    fn finish(&mut self) {
+        match &self.search {
+            Some(search) if search.bounded => {
+                if let Some(search) = self.search.take() {
+                    let _ = search.handle.join();
+                }
+            }
+            _ => self.stop(),
+        }
+    }
+}
+
+/// Parse the arguments to `position`: `startpos` or `fen <fen>`, then
+/// optionally `moves` and moves in coordinate notation.
+fn position<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Result<State, String> {
+    let mut state = match tokens.next() {
+        Some("startpos") => match tokens.next() {
+            None | Some("moves") => crate::new_game().state,
+            Some(token) => return Err(format!("unexpected {}", token)),
+        },
+        Some("fen") => {
+            let fen: Vec<&str> = tokens
+                .by_ref()
+                .take_while(|&token| token != "moves")
+                .collect();
+            parse_position(&fen.join(" "))?
+        }
+        _ => return Err("expected startpos or fen".to_string()),
+    };
+    for text in tokens {
+        match find_move(&state, text) {
+            Some(m0ve) => state = m0ve.next,
+            None => return Err(format!("illegal move {}", text)),
+        }
+    }
+    Ok(state)
+}
+
+#[derive(Default)]
+struct GoParams {
+    depth: Option<u32>,
+    nodes: Option<u64>,
+    movetime: Option<Duration>,
+    wtime: Option<Duration>,
+    btime: Option<Duration>,
+    winc: Option<Duration>,
+    binc: Option<Duration>,
+    moves_to_go: Option<u32>,
+    infinite: bool,
+}
+
+impl GoParams {
+    fn parse<'a>(mut tokens: impl Iterator<Item = &'a str>) -> GoParams {
+        let mut params = GoParams::default();
+        while let Some(token) = tokens.next() {
+            let mut number = || tokens.next().and_then(|value| value.parse::<u64>().ok());
+            let mut millis = || number().map(Duration::from_millis);
+            match token {
+                "depth" => params.depth = number().map(|depth| depth as u32),
+                "nodes" => params.nodes = number(),
+                "movetime" => params.movetime = millis(),
+                "wtime" => params.wtime = millis(),
+                "btime" => params.btime = millis(),
+                "winc" => params.winc = millis(),
+                "binc" => params.binc = millis(),
+                "movestogo" => params.moves_to_go = number().map(|moves| moves as u32),
+                "infinite" => params.infinite = true,
+                _ => (),
+            }
+        }
+        params
+    }
+}
+
+fn info(result: &SearchResult, state: &State) -> String {
+    let score = match result.mate_in() {
+        Some(moves) if is_mate_score(result.score) => format!("mate {}", moves),
+        _ => format!("cp {}", result.score),
+    };
+    let millis = result.elapsed.as_millis() as u64;
+    format!(
+        "info depth {} score {} nodes {} nps {} time {} pv {}",
+        result.depth,
+        score,
+        result.nodes,
+        result.nodes * 1000 / millis.max(1),
+        millis,
+        pv_coordinates(result, state).join(" ")
+    )
+}
+
+fn bestmove(result: &SearchResult, state: &State) -> String {
+    let pv = pv_coordinates(result, state);
+    match pv.as_slice() {
+        [] => "bestmove 0000".to_string(),
+        [best] => format!("bestmove {}", best),
+        [best, ponder, ..] => format!("bestmove {} ponder {}", best, ponder),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::book::BookBuilder;
+    use std::io::Cursor;
+
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn script(input: &str) -> Vec<String> {
+        let output = Output::default();
+        run(Cursor::new(input.to_string()), output.clone());
+        let bytes = output.0.lock().unwrap().clone();
+        String::from_utf8(bytes)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_handshake() {
+        let lines = script("uci\nisready\nquit\n");
+        assert!(lines[0].starts_with("id name chess"));
+        assert!(lines.contains(&"option name Hash type spin default 16 min 1 max 4096".to_string()));
+        assert!(lines.contains(&"uciok".to_string()));
+        assert_eq!(lines.last().unwrap(), "readyok");
+    }
+
+    #[test]
+    fn test_go_depth() {
+        let lines = script("position startpos moves e2e4 e7e5\ngo depth 2\n");
+        assert!(lines[0].starts_with("info depth 1 score cp "));
+        assert!(lines[1].starts_with("info depth 2 "));
+        assert!(lines[2].starts_with("bestmove "));
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn test_mate() {
+        let lines = script("position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\ngo depth 4\n");
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("info depth 1 score mate 1 nodes "));
+        assert!(lines[0].ends_with(" pv a1a8"));
+        assert_eq!(lines[1], "bestmove a1a8");
+    }
+
+    #[test]
+    fn test_position_with_moves() {
+        let state = position(
+            "fen 4k3/P7/8/8/8/8/8/4K2R w K - 0 1 moves e1g1 e8d7 a7a8q".split_whitespace(),
+        )
+        .unwrap();
+        assert_eq!(state.to_fen(), "Q7/3k4/8/8/8/8/8/5RK1 b - - 0 2");
+
+        assert_eq!(
+            position("startpos moves e2e5".split_whitespace()),
+            Err("illegal move e2e5".to_string())
+        );
+        assert!(position("fen 8/8 w".split_whitespace()).is_err());
+    }
+
+    #[test]
+    fn test_book() {
+        let start = crate::new_game().state;
+        let mut builder = BookBuilder::new(1);
+        let e4 = find_move(&start, "e2e4").unwrap();
+        builder.add_game(&start, &[e4], "1-0");
+        let path = std::env::temp_dir().join(format!("uci-book-{}.bin", std::process::id()));
+        builder.build().save(path.to_str().unwrap()).unwrap();
+
+        let lines = script(&format!(
+            "setoption name BookFile value nowhere\nsetoption name BookFile value {}\n\
+             position startpos\ngo depth 1\nposition startpos moves e2e4\ngo depth 1\n",
+            path.display()
+        ));
+        std::fs::remove_file(&path).unwrap();
+        assert!(lines[0].starts_with("info string couldn't read nowhere"));
+        assert_eq!(lines[1], "bestmove e2e4");
+        // Out of book, so it searches.
+        assert!(lines[2].starts_with("info depth 1 "));
+        assert!(lines[3].starts_with("bestmove "));
+    }
+
+    #[test]
+    fn test_infinite_and_stop() {
+        let lines = script("position startpos\ngo infinite\nisready\nstop\n");
+        assert!(lines.contains(&"readyok".to_string()));
+        assert!(lines.last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn test_unlimited_go_stops_at_end_of_input() {
+        let lines = script("position startpos\ngo\n");
+        assert!(lines.last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn test_errors_and_options() {
+        let lines = script(
+            "position startpos moves e2e5\nposition fen 8/8/8/8/8/8/4P3/8 w - - 0 1 moves e2e4\n\
+             foo\nsetoption name Move Overhead value 100\n\
+             setoption name Hash value 1\nsetoption name Hash value lots\n\
+             setoption name Threads value 2\n",
+        );
+        assert_eq!(
+            lines,
+            vec![
+                "info string illegal move e2e5",
+                "info string White needs exactly one king",
+                "info string unknown command foo",
+                "info string invalid Hash lots",
+                "info string unknown option Threads",
+            ]
+        );
+    }
+}
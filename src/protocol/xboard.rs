@@ -0,0 +1,386 @@
+//! The Chess Engine Communication Protocol spoken by XBoard and WinBoard,
+//! see https://www.gnu.org/software/xboard/engine-intf.html
+//!
+//! Unlike UCI the engine keeps track of the game itself. Searches run to
+//! completion before the next command is read.
+
+use crate::engine::transposition::{self, TranspositionTable};
+use crate::engine::{self, Limits, SearchResult};
+use crate::game::{player::Player, player::Player::*, Game};
+use crate::protocol::{allocate_time, find_move, is_mate_score, parse_position, pv_coordinates};
+use std::io::{BufRead, Write};
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+/// Kept back from the clock for communication lag.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+
+/// Speak CECP, reading commands from `input` and answering on `output` until
+/// `quit` or the end of the input.
+pub fn run<R: BufRead, W: Write>(input: R, output: W) {
+    let mut xboard = XBoard::new(output);
+    for line in input.lines() {
+        match line {
+            Ok(line) => {
+                if !xboard.handle(&line) {
+                    return;
+                }
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+/// A conventional time control from the `level` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Level {
+    /// Moves per time control, or 0 for the whole game.
+    moves: u32,
+    base: Duration,
+    increment: Duration,
+}
+
+struct XBoard<W> {
+    output: W,
+    game: Game,
+    /// The side the engine plays, or `None` in force mode.
+    engine: Option<Player>,
+    post: bool,
+    depth: Option<u32>,
+    movetime: Option<Duration>,
+    level: Option<Level>,
+    /// The engine's clock, from the `time` command.
+    time_left: Option<Duration>,
+    table: TranspositionTable,
+}
+
+impl<W: Write> XBoard<W> {
+    fn new(output: W) -> Self {
+        XBoard {
+            output,
+            game: crate::new_game(),
+            engine: Some(Black),
+            post: false,
+            depth: None,
+            movetime: None,
+            level: None,
+            time_left: None,
+            table: TranspositionTable::default(),
+        }
+    }
+
+    fn send(&mut self, line: &str) {
+        // There's no one to tell if the GUI has gone away.
+        let _ = writeln!(self.output, "{}", line).and_then(|_| self.output.flush());
+    }
+
+    /// Handle one command. Returns false on `quit`.
+    fn handle(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_whitespace();
+        let command = tokens.next();
+        let args: Vec<&str> = tokens.collect();
+        match command {
+            Some("protover") => {
+                self.send(&format!(
+                    "feature myname=\"chess {}\" ping=1 setboard=1 usermove=1 time=1 memory=1 \
+                     colors=0 sigint=0 sigterm=0 reuse=1 analyze=0 done=1",
+                    env!("CARGO_PKG_VERSION")
+                ));
+            }
+            Some("new") => {
+                self.game = crate::new_game();
+                self.engine = Some(Black);
+                self.depth = None;
+                self.time_left = None;
+                self.table.clear();
+            }
+            Some("setboard") => match parse_position(&args.join(" ")) {
+                Ok(state) => self.game = Game::with_state(state),
+                Err(e) => self.send(&format!("tellusererror Illegal position: {}", e)),
+            },
+            Some("usermove") => match args.first() {
+                Some(&text) => self.user_move(text),
+                None => self.send("Error (no move): usermove"),
+            },
+            Some("go") => {
+                self.engine = Some(self.game.state.player);
+                self.think();
+            }
+            Some("force") | Some("result") => self.engine = None,
+            Some("undo") => {
+                self.game.undo();
+            }
+            Some("remove") => {
+                self.game.undo();
+                self.game.undo();
+            }
+            Some("level") => match level(&args) {
+                Some(level) => {
+                    self.level = Some(level);
+                    self.movetime = None;
+                }
+                None => self.send(&format!("Error (bad time control): {}", line)),
+            },
+            Some("st") => match args.first().and_then(|secs| secs.parse().ok()) {
+                Some(secs) => {
+                    self.movetime = Some(Duration::from_secs(secs));
+                    self.level = None;
+                }
+                None => self.send(&format!("Error (bad time control): {}", line)),
+            },
+            Some("sd") => match args.first().and_then(|depth| depth.parse().ok()) {
+                Some(depth) => self.depth = Some(depth),
+                None => self.send(&format!("Error (bad depth): {}", line)),
+            },
+            Some("time") => {
+                let centis: Option<u64> = args.first().and_then(|centis| centis.parse().ok());
+                self.time_left =
+                    centis.map(|centis| Duration::from_millis(centis.saturating_mul(10)));
+            }
+            Some("memory") => match args
+                .first()
+                .and_then(|megabytes| megabytes.parse::<usize>().ok())
+            {
+                Some(megabytes) => {
+                    self.table =
+                        TranspositionTable::new(megabytes.clamp(1, transposition::MAX_MEGABYTES))
+                }
+                None => self.send(&format!("Error (bad memory size): {}", line)),
+            },
+            Some("ping") => {
+                let reply = format!("pong {}", args.join(" "));
+                self.send(&reply);
+            }
+            Some("post") => self.post = true,
+            Some("nopost") => self.post = false,
+            Some("quit") => return false,
+            Some("xboard") | Some("accepted") | Some("rejected") | Some("otim")
+            | Some("random") | Some("hard") | Some("easy") | Some("computer") | Some("name")
+            | Some("rating") | Some("draw") | Some("?") | None => (),
+            Some(command) => self.send(&format!("Error (unknown command): {}", command)),
+        }
+        true
+    }
+
+    fn user_move(&mut self, text: &str) {
+        match find_move(&self.game.state, text) {
+            Some(m0ve) => {
+                self.game.play(m0ve);
+                if !self.report_outcome() && self.engine == Some(self.game.state.player) {
+                    self.think();
+                }
+            }
+            None => self.send(&format!("Illegal move: {}", text)),
+        }
+    }
+
+    /// If the game is over, send its result. Returns whether it was.
+    fn report_outcome(&mut self) -> bool {
+        match self.game.outcome() {
+            Some(outcome) => {
+                self.send(&format!("{} {{{}}}", outcome.result_token(), outcome));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn limits(&self) -> Limits {
+        let clock = match (self.level, self.time_left) {
+            (Some(level), time_left) => {
+                let moves_to_go = match level.moves {
+                    0 => None,
+                    moves => Some(moves - (self.game.ply() / 2) as u32 % moves),
+                };
+                Some(allocate_time(
+                    time_left.unwrap_or(level.base),
+                    level.increment,
+                    moves_to_go,
+                    MOVE_OVERHEAD,
+                ))
+            }
+            (None, Some(time_left)) => Some(allocate_time(
+                time_left,
+                Duration::from_secs(0),
+                None,
+                MOVE_OVERHEAD,
+            )),
+            (None, None) => None,
+        };
+        Limits {
+            depth: self.depth,
+            nodes: None,
+            movetime: self.movetime.or(clock),
+        }
+    }
+
+    /// Search for a move for the side to move and play it.
+    fn think(&mut self) {
+        if self.report_outcome() {
+            return;
+        }
+        let state = self.game.state.clone();
+        let limits = self.limits();
+        let post = self.post;
+        let mut thinking = vec![];
+        let stop = AtomicBool::new(false);
+        let result = engine::search(&state, limits, &mut self.table, &stop, |result| {
+            if post {
+                thinking.push(thinking_line(result, &state));
+            }
+        });
+        for line in thinking {
+            self.send(&line);
+        }
+        if let Some(m0ve) = result.best_move {
+            self.send(&format!("move {}", m0ve.to_coordinate(&state)));
+            self.game.play(m0ve);
+            self.report_outcome();
+        }
+    }
+}
+
+/// Parse the arguments to `level`: moves per session, base time in minutes
+/// or `minutes:seconds`, and increment in seconds.
+fn level(args: &[&str]) -> Option<Level> {
+    match args {
+        [moves, base, increment] => {
+            let (mins, secs) = base.split_once(':').unwrap_or((base, "0"));
+            let base = mins
+                .parse::<u64>()
+                .ok()?
+                .checked_mul(60)?
+                .checked_add(secs.parse().ok()?)?;
+            Some(Level {
+                moves: moves.parse().ok()?,
+                base: Duration::from_secs(base),
+                // Rejects negative, infinite and NaN increments.
+                increment: Duration::try_from_secs_f64(increment.parse().ok()?).ok()?,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// A line of thinking output: ply, score, time in centiseconds, nodes and
+/// principal variation. Mates are scored as 100000 plus the moves to mate.
+fn thinking_line(result: &SearchResult, state: &crate::game::state::State) -> String {
+    let score = match result.mate_in() {
+        Some(moves) if is_mate_score(result.score) && moves > 0 => 100_000 + moves,
+        Some(moves) if is_mate_score(result.score) => -100_000 + moves,
+        _ => result.score,
+    };
+    format!(
+        "{} {} {} {} {}",
+        result.depth,
+        score,
+        result.elapsed.as_millis() / 10,
+        result.nodes,
+        pv_coordinates(result, state).join(" ")
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn script(input: &str) -> Vec<String> {
+        let mut output = vec![];
+        run(Cursor::new(input.to_string()), &mut output);
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_handshake() {
+        let lines =
+            script("xboard\nprotover 2\nmemory 32\nmemory lots\nmemory 99999999999999\nping 7\n");
+        assert!(lines[0].starts_with("feature "));
+        assert!(lines[0].ends_with(" done=1"));
+        assert_eq!(lines[1], "Error (bad memory size): memory lots");
+        assert_eq!(lines[2], "pong 7");
+    }
+
+    #[test]
+    fn test_engine_replies_to_usermove() {
+        let lines = script("new\nsd 2\nusermove e2e4\n");
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("move "));
+    }
+
+    #[test]
+    fn test_force_and_undo() {
+        let output = script("new\nforce\nusermove e2e4\nusermove e7e5\nusermove e5e4\n");
+        assert_eq!(output, vec!["Illegal move: e5e4"]);
+
+        let mut xboard = XBoard::new(vec![]);
+        for line in &["force", "usermove e2e4", "usermove e7e5", "undo"] {
+            xboard.handle(line);
+        }
+        assert_eq!(xboard.game.ply(), 1);
+        xboard.handle("remove");
+        assert_eq!(xboard.game.ply(), 0);
+        assert!(xboard.output.is_empty());
+    }
+
+    #[test]
+    fn test_go_mates() {
+        let lines = script("setboard 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\nsd 3\npost\ngo\n");
+        assert!(lines[0].starts_with("1 100001 "));
+        assert!(lines[0].ends_with(" a1a8"));
+        assert_eq!(lines[1], "move a1a8");
+        assert_eq!(lines[2], "1-0 {White wins by checkmate}");
+    }
+
+    #[test]
+    fn test_user_mates() {
+        let lines = script("setboard 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\nusermove a1a8\n");
+        assert_eq!(lines, vec!["1-0 {White wins by checkmate}"]);
+    }
+
+    #[test]
+    fn test_setboard_needs_kings() {
+        let lines = script("force\nsetboard 8/8/8/8/8/8/4P3/8 w - - 0 1\nusermove e2e4\n");
+        assert_eq!(
+            lines,
+            vec!["tellusererror Illegal position: White needs exactly one king"]
+        );
+    }
+
+    #[test]
+    fn test_level() {
+        assert_eq!(
+            level(&["40", "5", "0"]),
+            Some(Level {
+                moves: 40,
+                base: Duration::from_secs(300),
+                increment: Duration::from_secs(0),
+            })
+        );
+        assert_eq!(
+            level(&["0", "2:30", "1.5"]),
+            Some(Level {
+                moves: 0,
+                base: Duration::from_secs(150),
+                increment: Duration::from_millis(1_500),
+            })
+        );
+        assert_eq!(level(&["0", "2:xx", "1"]), None);
+        assert_eq!(level(&["40", "5", "-1"]), None);
+        assert_eq!(level(&["40", "5", "NaN"]), None);
+        assert_eq!(level(&["40", "5", "inf"]), None);
+        assert_eq!(level(&["40", "99999999999999999999", "0"]), None);
+        assert_eq!(level(&["40", "999999999999999999", "0"]), None);
+
+        let mut xboard = XBoard::new(vec![]);
+        xboard.handle("level 40 5 0");
+        xboard.handle("time 6000");
+        assert_eq!(xboard.limits().movetime, Some(Duration::from_millis(1_450)));
+        xboard.handle("st 2");
+        assert_eq!(xboard.limits().movetime, Some(Duration::from_secs(2)));
+    }
+}
@@ -0,0 +1,1426 @@
+//! Probing Syzygy endgame tablebases: win/draw/loss (`.rtbw`) and distance
+//! to zeroing (`.rtbz`) tables for positions with few pieces.
+//!
+//! The format is only documented by the generator and the probing code in
+//! Stockfish and Fathom, which this follows. Tables are read into memory the
+//! first time a position needs them.
+
+use crate::game::{
+    bitboard, board::Board, castles::Castleside, m0ve::Move, piece::Piece, player::Player,
+    pos::Pos, state::State,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Neg;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+/// The most pieces, kings included, in any table.
+const MAX_PIECES: usize = 7;
+
+// Flags of each subtable.
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+/// Which of the four DTZ maps to use for each `Wdl`, offset by 2.
+const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+
+/// The result of a position with perfect play, from the side to move. The
+/// cursed and blessed results are wins and losses that take too long to
+/// bring about under the fifty-move rule, so are draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Wdl {
+        match value {
+            -2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    fn signum(self) -> i32 {
+        (self as i32).signum()
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Wdl {
+        Wdl::from_value(-(self as i32))
+    }
+}
+
+impl fmt::Display for Wdl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Wdl::Loss => write!(f, "loss"),
+            Wdl::BlessedLoss => write!(f, "blessed loss"),
+            Wdl::Draw => write!(f, "draw"),
+            Wdl::CursedWin => write!(f, "cursed win"),
+            Wdl::Win => write!(f, "win"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Wdl,
+    Dtz,
+}
+
+impl Kind {
+    fn extension(self) -> &'static str {
+        match self {
+            Kind::Wdl => "rtbw",
+            Kind::Dtz => "rtbz",
+        }
+    }
+}
+
+/// A directory of tables.
+pub struct Tablebase {
+    paths: HashMap<String, PathBuf>,
+    max_pieces: usize,
+    tables: Mutex<HashMap<String, Arc<Table>>>,
+}
+
+impl Tablebase {
+    /// Find the tables in `dir`. They're only read when first probed.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Tablebase, String> {
+        let dir = dir.as_ref();
+        let entries =
+            fs::read_dir(dir).map_err(|e| format!("couldn't read {}: {}", dir.display(), e))?;
+        let mut paths = HashMap::new();
+        let mut max_pieces = 0;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let (stem, extension) = match (path.file_stem(), path.extension()) {
+                (Some(stem), Some(extension)) => (stem.to_string_lossy(), extension),
+                _ => continue,
+            };
+            if extension != "rtbw" && extension != "rtbz" {
+                continue;
+            }
+            if let Ok(material) = Material::parse(&stem) {
+                if extension == "rtbw" {
+                    max_pieces = max_pieces.max(material.piece_count);
+                }
+                paths.insert(
+                    path.file_name().unwrap().to_string_lossy().into_owned(),
+                    path,
+                );
+            }
+        }
+        Ok(Tablebase {
+            paths,
+            max_pieces,
+            tables: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The most pieces of any win/draw/loss table found.
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// The result of `state` with perfect play.
+    pub fn probe_wdl(&self, state: &State) -> Result<Wdl, String> {
+        self.check(state)?;
+        Ok(self.search(state, false)?.0)
+    }
+
+    /// The number of plies until the next capture or pawn move (which reset
+    /// the fifty-move counter) with best play, positive when the side to
+    /// move wins and negative when it loses. Draws are 0. Cursed wins and
+    /// blessed losses are counted past 100.
+    ///
+    /// As in Stockfish and Fathom, a count of `n` can mean `n + 1`: tables
+    /// store moves rather than plies except where that would blur the
+    /// fifty-move limit, so a win is certain if the count plus the halfmove
+    /// clock is at most 99.
+    pub fn probe_dtz(&self, state: &State) -> Result<i32, String> {
+        self.check(state)?;
+        self.dtz(state)
+    }
+
+    /// Every legal move from `state`, with its DTZ counted from `state`
+    /// from the point of view of the side to move there.
+    pub fn probe_moves(&self, state: &State) -> Result<Vec<(Move, i32)>, String> {
+        self.check(state)?;
+        state
+            .gen_moves()
+            .into_iter()
+            .map(|m0ve| {
+                let next = &m0ve.next;
+                let mut dtz = if next.halfmove_clock == 0 {
+                    dtz_before_zeroing(-self.search(next, false)?.0)
+                } else {
+                    let dtz = -self.dtz(next)?;
+                    dtz + dtz.signum()
+                };
+                // Mate is always one ply.
+                if dtz == 2 && next.in_check() && next.gen_moves().is_empty() {
+                    dtz = 1;
+                }
+                Ok((m0ve, dtz))
+            })
+            .collect()
+    }
+
+    /// The moves from `state` that keep the best result: the quickest win,
+    /// any draw, or the slowest loss.
+    pub fn best_moves(&self, state: &State) -> Result<Vec<(Move, i32)>, String> {
+        let rank = |dtz: i32| match dtz {
+            _ if dtz > 0 => (2, -dtz),
+            0 => (1, 0),
+            _ => (0, -dtz),
+        };
+        let moves = self.probe_moves(state)?;
+        let best = moves.iter().map(|(_, dtz)| rank(*dtz)).max();
+        Ok(moves
+            .into_iter()
+            .filter(|(_, dtz)| Some(rank(*dtz)) == best)
+            .collect())
+    }
+
+    fn check(&self, state: &State) -> Result<(), String> {
+        let castling = [Player::White, Player::Black].iter().any(|&player| {
+            state.castling.able(player, Castleside::Kingside)
+                || state.castling.able(player, Castleside::Queenside)
+        });
+        if castling {
+            return Err("tablebases don't cover positions with castling rights".to_string());
+        }
+        let pieces = state.board.occupied().count_ones() as usize;
+        if pieces > self.max_pieces.max(2) {
+            return Err(format!(
+                "{} pieces is more than the {} the tables cover",
+                pieces, self.max_pieces
+            ));
+        }
+        Ok(())
+    }
+
+    /// The table `name` with the extension for `kind`, if there is one.
+    fn table(&self, name: &str, kind: Kind) -> Result<Option<Arc<Table>>, String> {
+        let file_name = format!("{}.{}", name, kind.extension());
+        let path = match self.paths.get(&file_name) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(table) = tables.get(&file_name) {
+            return Ok(Some(Arc::clone(table)));
+        }
+        let bytes = fs::read(path).map_err(|e| format!("couldn't read {}: {}", file_name, e))?;
+        let table = Arc::new(
+            Table::parse(kind, Material::parse(name)?, bytes)
+                .map_err(|e| format!("{}: {}", file_name, e))?,
+        );
+        tables.insert(file_name, Arc::clone(&table));
+        Ok(Some(table))
+    }
+
+    /// The result of `state`, looking at captures (and pawn moves if
+    /// `zeroing`) as well as the table, which may store any value for
+    /// positions where such a move is best. Also whether the best move
+    /// resets the fifty-move counter, in which case DTZ tables can't be
+    /// trusted either.
+    fn search(&self, state: &State, zeroing: bool) -> Result<(Wdl, bool), String> {
+        let moves = state.gen_moves();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for m0ve in &moves {
+            let capture = m0ve.captured.is_some();
+            if !(capture || zeroing && m0ve.next.halfmove_clock == 0) {
+                continue;
+            }
+            searched += 1;
+            let value = -self.search(&m0ve.next, false)?.0;
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Ok((value, true));
+                }
+            }
+        }
+
+        // The table can't be trusted if every move was searched, e.g. when
+        // the only legal move is en passant, which the tables ignore.
+        let all_searched = searched > 0 && searched == moves.len();
+        let value = if all_searched {
+            best
+        } else {
+            Wdl::from_value(self.probe_table(state, Kind::Wdl, Wdl::Draw)?.unwrap_or(0))
+        };
+        if best >= value {
+            Ok((best, best > Wdl::Draw || all_searched))
+        } else {
+            Ok((value, false))
+        }
+    }
+
+    fn dtz(&self, state: &State) -> Result<i32, String> {
+        let (wdl, zeroing) = self.search(state, true)?;
+        if wdl == Wdl::Draw {
+            return Ok(0);
+        }
+        if zeroing {
+            return Ok(dtz_before_zeroing(wdl));
+        }
+        if let Some(dtz) = self.probe_table(state, Kind::Dtz, wdl)? {
+            let cursed = wdl == Wdl::CursedWin || wdl == Wdl::BlessedLoss;
+            return Ok((dtz + if cursed { 100 } else { 0 }) * wdl.signum());
+        }
+
+        // The table only has the other side to move, so look one ply ahead.
+        let mut best = None;
+        for m0ve in state.gen_moves() {
+            let next = &m0ve.next;
+            let zeroing = next.halfmove_clock == 0;
+            let mut dtz = if zeroing {
+                -dtz_before_zeroing(self.search(next, false)?.0)
+            } else {
+                -self.dtz(next)?
+            };
+            if dtz == 1 && next.in_check() && next.gen_moves().is_empty() {
+                best = Some(1);
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz.signum() == wdl.signum() && best.map_or(true, |best| dtz < best) {
+                best = Some(dtz);
+            }
+        }
+        Ok(best.unwrap_or(-1))
+    }
+
+    /// The value stored for `state` in the table of `kind`: a `Wdl` as a
+    /// number, or a DTZ given the position's `wdl`. `None` if a DTZ table
+    /// only has the other side to move.
+    fn probe_table(&self, state: &State, kind: Kind, wdl: Wdl) -> Result<Option<i32>, String> {
+        let board = &state.board;
+        let occupied = board.occupied();
+        if occupied.count_ones() == 2 {
+            return Ok(Some(0));
+        }
+
+        let white = side_name(board, Player::White);
+        let black = side_name(board, Player::Black);
+        let (table, black_stronger) = match self.table(&format!("{}v{}", white, black), kind)? {
+            Some(table) => (table, false),
+            None => match self.table(&format!("{}v{}", black, white), kind)? {
+                Some(table) => (table, true),
+                None => return Err(format!("no table for {}v{}", white, black)),
+            },
+        };
+
+        // Tables have white as the stronger side, so otherwise swap the
+        // colors and flip the board. Symmetric tables only store white to
+        // move.
+        let flip = black_stronger || (table.material.symmetric && state.player == Player::Black);
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = flip as usize ^ (state.player == Player::Black) as usize;
+
+        let indices = indices();
+        let mut squares = [0usize; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = bitboard::EMPTY;
+        let mut file = 0;
+        if table.material.has_pawns {
+            let color = match table.items[0][0].pieces[0] ^ flip_color {
+                code if code & 8 == 0 => Player::White,
+                _ => Player::Black,
+            };
+            lead_pawns = board.pieces(color, Piece::Pawn);
+            for pos in bitboard::squares(lead_pawns) {
+                squares[size] = square(pos) ^ flip_squares;
+                size += 1;
+            }
+            let lead = (0..size)
+                .max_by_key(|&i| indices.map_pawns[squares[i]])
+                .unwrap();
+            squares.swap(0, lead);
+            file = (squares[0] % 8).min(7 - squares[0] % 8);
+        }
+        let lead_count = size;
+
+        if kind == Kind::Dtz {
+            let flags = table.items[0][file].flags;
+            let symmetric = table.material.symmetric && !table.material.has_pawns;
+            if (flags & FLAG_STM) as usize != stm && !symmetric {
+                return Ok(None);
+            }
+        }
+
+        for pos in bitboard::squares(occupied & !lead_pawns) {
+            let (player, piece) = board.piece_at(pos).unwrap();
+            squares[size] = square(pos) ^ flip_squares;
+            pieces[size] = piece_code(player, piece) ^ flip_color;
+            size += 1;
+        }
+        if size != table.material.piece_count {
+            return Err(format!("position doesn't match {}", table.material.name));
+        }
+
+        // Put the pieces in the order the table has them.
+        let d = table.get(stm, file);
+        for i in lead_count..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|&j| pieces[j] == d.pieces[i]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        let index = table.index(d, &mut squares[..size], lead_count);
+        let value = d.decompress(&table.bytes, index) as i32;
+        Ok(Some(match kind {
+            Kind::Wdl => value - 2,
+            Kind::Dtz => table.map_score(file, value, wdl),
+        }))
+    }
+}
+
+/// The DTZ of a position whose best move resets the fifty-move counter.
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0,
+    }
+}
+
+fn square(pos: Pos) -> usize {
+    pos.rank as usize * 8 + pos.file as usize
+}
+
+/// A piece as the tables number them.
+fn piece_code(player: Player, piece: Piece) -> u8 {
+    let code = match piece {
+        Piece::Pawn => 1,
+        Piece::Knight => 2,
+        Piece::Bishop => 3,
+        Piece::Rook => 4,
+        Piece::Queen => 5,
+        Piece::King => 6,
+    };
+    match player {
+        Player::White => code,
+        Player::Black => code + 8,
+    }
+}
+
+/// One side's pieces as they're written in table names, e.g. `KRP`.
+fn side_name(board: &Board, player: Player) -> String {
+    let pieces = [
+        (Piece::King, 'K'),
+        (Piece::Queen, 'Q'),
+        (Piece::Rook, 'R'),
+        (Piece::Bishop, 'B'),
+        (Piece::Knight, 'N'),
+        (Piece::Pawn, 'P'),
+    ];
+    let mut name = String::new();
+    for (piece, letter) in pieces.iter() {
+        let count = board.pieces(player, *piece).count_ones();
+        name.extend(std::iter::repeat(*letter).take(count as usize));
+    }
+    name
+}
+
+/// Whether `square` is above (positive), on or below (negative) the a1-h8
+/// diagonal.
+fn off_diagonal(square: usize) -> i32 {
+    (square / 8) as i32 - (square % 8) as i32
+}
+
+/// The pieces of a table, from its name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Material {
+    name: String,
+    piece_count: usize,
+    has_pawns: bool,
+    /// Whether some piece other than a king is the only one of its kind.
+    has_unique_pieces: bool,
+    /// The pawns of the side the leading pawns belong to, then the other's.
+    pawn_count: [usize; 2],
+    symmetric: bool,
+}
+
+impl Material {
+    fn parse(name: &str) -> Result<Material, String> {
+        let error = || format!("invalid table name {}", name);
+        let mut sides = name.split('v');
+        let (white, black) = match (sides.next(), sides.next(), sides.next()) {
+            (Some(white), Some(black), None) => (white, black),
+            _ => return Err(error()),
+        };
+        for side in &[white, black] {
+            if !side.starts_with('K') || !side.chars().all(|c| "KQRBNP".contains(c)) {
+                return Err(error());
+            }
+        }
+        let piece_count = white.len() + black.len();
+        if piece_count > MAX_PIECES {
+            return Err(error());
+        }
+        let count = |side: &str, letter: char| side.chars().filter(|&c| c == letter).count();
+        let has_unique_pieces = [white, black]
+            .iter()
+            .any(|side| "QRBNP".chars().any(|letter| count(side, letter) == 1));
+        let (white_pawns, black_pawns) = (count(white, 'P'), count(black, 'P'));
+        // The side with fewer pawns leads, as that compresses better.
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let pawn_count = if white_leads {
+            [white_pawns, black_pawns]
+        } else {
+            [black_pawns, white_pawns]
+        };
+        Ok(Material {
+            name: name.to_string(),
+            piece_count,
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_count,
+            symmetric: white == black,
+        })
+    }
+}
+
+/// Lookup tables for turning positions into indices.
+struct Indices {
+    /// `binomial[k][n]` is n choose k.
+    binomial: [[u64; 64]; MAX_PIECES],
+    /// Squares a2-h7 numbered 0-47, so that the leading pawn, the one
+    /// nearest the a file and then the first rank, has the highest.
+    map_pawns: [usize; 64],
+    lead_pawn_index: [[u64; 64]; MAX_PIECES],
+    lead_pawns_size: [[u64; 4]; MAX_PIECES],
+    /// Squares below the a1-h8 diagonal numbered 0-27.
+    map_b1h1h7: [usize; 64],
+    /// Squares in the a1-d1-d4 triangle numbered 0-9, diagonal last.
+    map_a1d1d4: [usize; 64],
+    /// The 462 placements of two kings, the first in the triangle.
+    map_kk: [[u64; 64]; 10],
+}
+
+// The tables are easiest to follow as loops over square numbers.
+#[allow(clippy::needless_range_loop)]
+fn indices() -> &'static Indices {
+    static INDICES: OnceLock<Indices> = OnceLock::new();
+    INDICES.get_or_init(|| {
+        let mut binomial = [[0; 64]; MAX_PIECES];
+        binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                binomial[k][n] = if k > 0 { binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        let mut map_pawns = [0; 64];
+        let mut lead_pawn_index = [[0; 64]; MAX_PIECES];
+        let mut lead_pawns_size = [[0; 4]; MAX_PIECES];
+        let mut available: usize = 47;
+        for lead_count in 1..=5 {
+            for file in 0..4 {
+                let mut index = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    if lead_count == 1 {
+                        map_pawns[square] = available;
+                        map_pawns[square ^ 7] = available - 1;
+                        available = available.saturating_sub(2);
+                    }
+                    lead_pawn_index[lead_count][square] = index;
+                    index += binomial[lead_count - 1][map_pawns[square]];
+                }
+                lead_pawns_size[lead_count][file] = index;
+            }
+        }
+
+        let mut map_b1h1h7 = [0; 64];
+        let mut code = 0;
+        for square in 0..64 {
+            if off_diagonal(square) < 0 {
+                map_b1h1h7[square] = code;
+                code += 1;
+            }
+        }
+
+        let mut map_a1d1d4 = [0; 64];
+        let mut diagonal = vec![];
+        let mut code = 0;
+        for square in 0..28 {
+            if off_diagonal(square) < 0 && square % 8 <= 3 {
+                map_a1d1d4[square] = code;
+                code += 1;
+            } else if off_diagonal(square) == 0 && square % 8 <= 3 {
+                diagonal.push(square);
+            }
+        }
+        for square in diagonal {
+            map_a1d1d4[square] = code;
+            code += 1;
+        }
+
+        let mut map_kk = [[0; 64]; 10];
+        let mut both_on_diagonal = vec![];
+        let mut code = 0;
+        for index in 0..10 {
+            for first in 0..28 {
+                // b1 is 0, and so is everything outside the triangle.
+                if map_a1d1d4[first] != index || (index == 0 && first != 1) {
+                    continue;
+                }
+                let king = bitboard::king_attacks(bitboard::pos_at(first as u32))
+                    | bitboard::bit(bitboard::pos_at(first as u32));
+                for second in 0..64 {
+                    if king & (1 << second) != 0 {
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 {
+                        match off_diagonal(second) {
+                            0 => both_on_diagonal.push((index, second)),
+                            off if off > 0 => (),
+                            _ => {
+                                map_kk[index][second] = code;
+                                code += 1;
+                            }
+                        }
+                    } else {
+                        map_kk[index][second] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (index, second) in both_on_diagonal {
+            map_kk[index][second] = code;
+            code += 1;
+        }
+
+        Indices {
+            binomial,
+            map_pawns,
+            lead_pawn_index,
+            lead_pawns_size,
+            map_b1h1h7,
+            map_a1d1d4,
+            map_kk,
+        }
+    })
+}
+
+fn byte(bytes: &[u8], at: usize) -> u8 {
+    bytes.get(at).copied().unwrap_or(0)
+}
+
+fn u16_le(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([byte(bytes, at), byte(bytes, at + 1)])
+}
+
+fn u32_le(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([
+        byte(bytes, at),
+        byte(bytes, at + 1),
+        byte(bytes, at + 2),
+        byte(bytes, at + 3),
+    ])
+}
+
+fn u32_be(bytes: &[u8], at: usize) -> u32 {
+    u32_le(bytes, at).swap_bytes()
+}
+
+fn u64_be(bytes: &[u8], at: usize) -> u64 {
+    (u32_be(bytes, at) as u64) << 32 | u32_be(bytes, at + 4) as u64
+}
+
+/// One compressed subtable: a side to move, and for pawn tables, a file of
+/// the leading pawn. Offsets are into the table's bytes.
+#[derive(Debug, Clone, Default)]
+struct PairsData {
+    flags: u8,
+    pieces: [u8; MAX_PIECES],
+    /// The sizes of the groups pieces are encoded in, ending with 0.
+    group_len: [usize; MAX_PIECES + 1],
+    /// What each group's index is multiplied by. The entry after the last
+    /// group is the number of positions.
+    group_idx: [u64; MAX_PIECES + 1],
+    block_size: u64,
+    span: u64,
+    sparse_index_size: usize,
+    blocks: usize,
+    block_lengths: usize,
+    /// The shortest code length, or the value of a single value table.
+    min_sym_len: u8,
+    lowest_sym: usize,
+    base64: Vec<u64>,
+    /// How many symbols each symbol expands to, less one.
+    symlen: Vec<u8>,
+    btree: usize,
+    sparse_index: usize,
+    block_length: usize,
+    data: usize,
+    map_idx: [usize; 4],
+}
+
+impl PairsData {
+    fn set_groups(&mut self, material: &Material, order: [u8; 2], file: usize) {
+        let mut first_len: i32 = if material.has_pawns {
+            0
+        } else if material.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        let mut n = 0;
+        self.group_len[0] = 1;
+        for i in 1..material.piece_count {
+            first_len -= 1;
+            if first_len > 0 || self.pieces[i] == self.pieces[i - 1] {
+                self.group_len[n] += 1;
+            } else {
+                n += 1;
+                self.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        self.group_len[n] = 0;
+
+        let indices = indices();
+        let both_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free = 64 - self.group_len[0] - if both_pawns { self.group_len[1] } else { 0 };
+        let mut index: u64 = 1;
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                self.group_idx[0] = index;
+                index = index.wrapping_mul(if material.has_pawns {
+                    indices.lead_pawns_size[self.group_len[0]][file]
+                } else if material.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                });
+            } else if k == order[1] as usize {
+                self.group_idx[1] = index;
+                index =
+                    index.wrapping_mul(indices.binomial[self.group_len[1]][48 - self.group_len[0]]);
+            } else {
+                self.group_idx[next] = index;
+                index = index.wrapping_mul(indices.binomial[self.group_len[next]][free]);
+                free -= self.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        self.group_idx[n] = index;
+    }
+
+    /// Read the compression parameters at `at`, returning where they end.
+    fn set_sizes(&mut self, bytes: &[u8], mut at: usize) -> Result<usize, String> {
+        self.flags = byte(bytes, at);
+        at += 1;
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            self.min_sym_len = byte(bytes, at);
+            return Ok(at + 1);
+        }
+
+        let end = self.group_len.iter().position(|&len| len == 0).unwrap();
+        let positions = self.group_idx[end];
+        let (block_bits, span_bits) = (byte(bytes, at), byte(bytes, at + 1));
+        if block_bits >= 32 || span_bits >= 32 {
+            return Err("corrupt block sizes".to_string());
+        }
+        self.block_size = 1 << block_bits;
+        self.span = 1 << span_bits;
+        self.sparse_index_size = positions.div_ceil(self.span) as usize;
+        let padding = byte(bytes, at + 2) as usize;
+        self.blocks = u32_le(bytes, at + 3) as usize;
+        self.block_lengths = self.blocks + padding;
+        let max_sym_len = byte(bytes, at + 7);
+        self.min_sym_len = byte(bytes, at + 8);
+        at += 9;
+        if self.min_sym_len == 0 || max_sym_len < self.min_sym_len || max_sym_len > 64 {
+            return Err("corrupt symbol lengths".to_string());
+        }
+
+        // Canonical Huffman codes: longer codes have lower values, so for
+        // a code of length l padded to 64 bits, base64[l - 1] >= code >=
+        // base64[l], counting from the shortest length.
+        self.lowest_sym = at;
+        let lengths = (max_sym_len - self.min_sym_len) as usize + 1;
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = u16_le(bytes, at + 2 * i) as u64;
+            let next_lowest = u16_le(bytes, at + 2 * i + 2) as u64;
+            self.base64[i] = (self.base64[i + 1] + lowest).wrapping_sub(next_lowest) / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base = base
+                .checked_shl(64 - i as u32 - self.min_sym_len as u32)
+                .unwrap_or(0);
+        }
+        at += 2 * lengths;
+
+        // Symbols are either values or pairs of other symbols.
+        let symbols = u16_le(bytes, at) as usize;
+        at += 2;
+        self.btree = at;
+        if at + 3 * symbols > bytes.len() {
+            return Err("truncated".to_string());
+        }
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                self.symlen[symbol] = self.set_symlen(bytes, symbol, &mut visited)?;
+            }
+        }
+        Ok(at + 3 * symbols + (symbols & 1))
+    }
+
+    fn set_symlen(
+        &mut self,
+        bytes: &[u8],
+        symbol: usize,
+        visited: &mut [bool],
+    ) -> Result<u8, String> {
+        visited[symbol] = true;
+        let (left, right) = self.pair(bytes, symbol);
+        if right == 0xfff {
+            return Ok(0);
+        }
+        for &child in &[left, right] {
+            if child >= visited.len() {
+                return Err("corrupt symbol".to_string());
+            }
+            if !visited[child] {
+                self.symlen[child] = self.set_symlen(bytes, child, visited)?;
+            }
+        }
+        Ok(self.symlen[left]
+            .wrapping_add(self.symlen[right])
+            .wrapping_add(1))
+    }
+
+    /// The two symbols a symbol stands for. For a value, the value and
+    /// 0xfff.
+    fn pair(&self, bytes: &[u8], symbol: usize) -> (usize, usize) {
+        let at = self.btree + 3 * symbol;
+        let (b0, b1, b2) = (
+            byte(bytes, at) as usize,
+            byte(bytes, at + 1) as usize,
+            byte(bytes, at + 2) as usize,
+        );
+        ((b1 & 0xf) << 8 | b0, b2 << 4 | b1 >> 4)
+    }
+
+    fn symlen(&self, symbol: usize) -> i64 {
+        self.symlen.get(symbol).copied().unwrap_or(0) as i64
+    }
+
+    fn block_len(&self, bytes: &[u8], block: usize) -> i64 {
+        u16_le(bytes, self.block_length + 2 * block) as i64
+    }
+
+    /// The value stored for position `index`.
+    fn decompress(&self, bytes: &[u8], index: u64) -> usize {
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            return self.min_sym_len as usize;
+        }
+
+        // The sparse index points into the middle of every span of
+        // positions. Walk from there to the block holding `index`.
+        let k = (index / self.span) as usize;
+        let entry = self.sparse_index + 6 * k;
+        let mut block = u32_le(bytes, entry) as usize;
+        let mut offset = u16_le(bytes, entry + 4) as i64;
+        offset += (index % self.span) as i64 - (self.span / 2) as i64;
+        while offset < 0 && block > 0 {
+            block -= 1;
+            offset += self.block_len(bytes, block) + 1;
+        }
+        while offset > self.block_len(bytes, block) && block + 1 < self.block_lengths {
+            offset -= self.block_len(bytes, block) + 1;
+            block += 1;
+        }
+
+        // Decode symbols until the one covering `offset`.
+        let mut at = self.data + block * self.block_size as usize;
+        let mut buffer = u64_be(bytes, at);
+        at += 8;
+        let mut buffer_size = 64;
+        let min_sym_len = self.min_sym_len as u32;
+        let mut symbol;
+        loop {
+            let mut len = 0;
+            while len + 1 < self.base64.len() && buffer < self.base64[len] {
+                len += 1;
+            }
+            symbol = ((buffer - self.base64[len]) >> (64 - len as u32 - min_sym_len)) as usize;
+            symbol += u16_le(bytes, self.lowest_sym + 2 * len) as usize;
+            if offset < self.symlen(symbol) + 1 {
+                break;
+            }
+            offset -= self.symlen(symbol) + 1;
+            let bits = len as u32 + min_sym_len;
+            buffer = buffer.checked_shl(bits).unwrap_or(0);
+            buffer_size -= bits as i32;
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                buffer |= (u32_be(bytes, at) as u64) << (64 - buffer_size);
+                at += 4;
+            }
+        }
+
+        // Expand the pairs the symbol stands for down to a single value.
+        while self.symlen(symbol) != 0 {
+            let (left, right) = self.pair(bytes, symbol);
+            if offset < self.symlen(left) + 1 {
+                symbol = left;
+            } else {
+                offset -= self.symlen(left) + 1;
+                symbol = right;
+            }
+        }
+        self.pair(bytes, symbol).0
+    }
+}
+
+/// A table file, read into memory.
+struct Table {
+    bytes: Vec<u8>,
+    material: Material,
+    /// Subtables by side to move, then file of the leading pawn.
+    items: Vec<Vec<PairsData>>,
+    /// Where a DTZ table's maps from stored values to distances start.
+    map: usize,
+}
+
+impl Table {
+    fn parse(kind: Kind, material: Material, bytes: Vec<u8>) -> Result<Table, String> {
+        let magic = match kind {
+            Kind::Wdl => WDL_MAGIC,
+            Kind::Dtz => DTZ_MAGIC,
+        };
+        if bytes.len() < 5 || bytes[..4] != magic {
+            return Err("not a Syzygy table".to_string());
+        }
+        if bytes.len() % 64 != 16 {
+            return Err("unexpected size, the file may be damaged".to_string());
+        }
+        let mut at = 4;
+        if (bytes[at] & 2 != 0) != material.has_pawns {
+            return Err("the pieces don't match the name".to_string());
+        }
+        at += 1;
+
+        let sides = if kind == Kind::Wdl && !material.symmetric {
+            2
+        } else {
+            1
+        };
+        let files = if material.has_pawns { 4 } else { 1 };
+        let both_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut items = vec![vec![PairsData::default(); files]; sides];
+        for file in 0..files {
+            let first = byte(&bytes, at);
+            let second = if both_pawns {
+                byte(&bytes, at + 1)
+            } else {
+                0xff
+            };
+            let orders = [[first & 0xf, second & 0xf], [first >> 4, second >> 4]];
+            at += 1 + both_pawns as usize;
+            for k in 0..material.piece_count {
+                let codes = byte(&bytes, at);
+                for (side, side_items) in items.iter_mut().enumerate() {
+                    side_items[file].pieces[k] = if side == 0 { codes & 0xf } else { codes >> 4 };
+                }
+                at += 1;
+            }
+            for (side, side_items) in items.iter_mut().enumerate() {
+                side_items[file].set_groups(&material, orders[side], file);
+            }
+        }
+        at += at & 1;
+
+        for file in 0..files {
+            for side_items in items.iter_mut() {
+                at = side_items[file].set_sizes(&bytes, at)?;
+            }
+        }
+
+        let map = at;
+        if kind == Kind::Dtz {
+            for d in items[0].iter_mut() {
+                if d.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & FLAG_WIDE != 0 {
+                    at += at & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = (at - map) / 2 + 1;
+                        at += 2 * u16_le(&bytes, at) as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = at - map + 1;
+                        at += byte(&bytes, at) as usize + 1;
+                    }
+                }
+            }
+            at += at & 1;
+        }
+
+        for file in 0..files {
+            for side_items in items.iter_mut() {
+                side_items[file].sparse_index = at;
+                at += 6 * side_items[file].sparse_index_size;
+            }
+        }
+        for file in 0..files {
+            for side_items in items.iter_mut() {
+                side_items[file].block_length = at;
+                at += 2 * side_items[file].block_lengths;
+            }
+        }
+        for file in 0..files {
+            for side_items in items.iter_mut() {
+                at = (at + 0x3f) & !0x3f;
+                side_items[file].data = at;
+                at += side_items[file].blocks * side_items[file].block_size as usize;
+            }
+        }
+        if at > bytes.len() {
+            return Err("truncated".to_string());
+        }
+
+        Ok(Table {
+            bytes,
+            material,
+            items,
+            map,
+        })
+    }
+
+    fn get(&self, stm: usize, file: usize) -> &PairsData {
+        &self.items[stm % self.items.len()][file]
+    }
+
+    /// The position's index in subtable `d`. `squares` are in the order of
+    /// the table's pieces, starting with `lead_count` leading pawns.
+    fn index(&self, d: &PairsData, squares: &mut [usize], lead_count: usize) -> u64 {
+        let indices = indices();
+        let material = &self.material;
+
+        // Mirror so that the leading piece is on files a-d...
+        if squares[0] % 8 > 3 {
+            for square in squares.iter_mut() {
+                *square ^= 7;
+            }
+        }
+
+        let mut index;
+        if material.has_pawns {
+            index = indices.lead_pawn_index[lead_count][squares[0]];
+            squares[1..lead_count].sort_by_key(|&square| indices.map_pawns[square]);
+            for (i, &square) in squares.iter().enumerate().take(lead_count).skip(1) {
+                index += indices.binomial[i][indices.map_pawns[square]];
+            }
+        } else {
+            // ...and without pawns, in the a1-d1-d4 triangle, with the first
+            // piece of the leading group that's off the diagonal below it.
+            if squares[0] / 8 > 3 {
+                for square in squares.iter_mut() {
+                    *square ^= 56;
+                }
+            }
+            for i in 0..d.group_len[0] {
+                match off_diagonal(squares[i]) {
+                    0 => continue,
+                    off if off > 0 => {
+                        for square in squares[i..].iter_mut() {
+                            *square = ((*square >> 3) | (*square << 3)) & 63;
+                        }
+                    }
+                    _ => (),
+                }
+                break;
+            }
+
+            if material.has_unique_pieces {
+                let rank = |square: usize| (square / 8) as u64;
+                let adjust1 = (squares[1] > squares[0]) as usize;
+                let adjust2 =
+                    (squares[2] > squares[0]) as usize + (squares[2] > squares[1]) as usize;
+                index = if off_diagonal(squares[0]) != 0 {
+                    ((indices.map_a1d1d4[squares[0]] * 63 + squares[1] - adjust1) * 62 + squares[2]
+                        - adjust2) as u64
+                } else if off_diagonal(squares[1]) != 0 {
+                    (6 * 63 + rank(squares[0]) * 28 + indices.map_b1h1h7[squares[1]] as u64) * 62
+                        + (squares[2] - adjust2) as u64
+                } else if off_diagonal(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(squares[0]) * 7 * 28
+                        + (rank(squares[1]) - adjust1 as u64) * 28
+                        + indices.map_b1h1h7[squares[2]] as u64
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(squares[0]) * 7 * 6
+                        + (rank(squares[1]) - adjust1 as u64) * 6
+                        + (rank(squares[2]) - adjust2 as u64)
+                };
+            } else {
+                index = indices.map_kk[indices.map_a1d1d4[squares[0]]][squares[1]];
+            }
+        }
+        index *= d.group_idx[0];
+
+        // Each remaining group is a combination of the squares left over.
+        let mut remaining_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut start = d.group_len[0];
+        let mut group = 1;
+        while d.group_len[group] != 0 {
+            let len = d.group_len[group];
+            squares[start..start + len].sort_unstable();
+            let mut combination = 0;
+            for i in 0..len {
+                let square = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| square > s).count();
+                let pawn_adjust = if remaining_pawns { 8 } else { 0 };
+                combination += indices.binomial[i + 1][square - adjust - pawn_adjust];
+            }
+            remaining_pawns = false;
+            index += combination * d.group_idx[group];
+            start += len;
+            group += 1;
+        }
+        index
+    }
+
+    /// Turn a value from a DTZ table into plies, rounding moves down to an
+    /// odd number of plies, see `Tablebase::probe_dtz`.
+    fn map_score(&self, file: usize, value: i32, wdl: Wdl) -> i32 {
+        let d = &self.items[0][file];
+        let mut value = value;
+        if d.flags & FLAG_MAPPED != 0 {
+            let i = d.map_idx[WDL_MAP[(wdl as i32 + 2) as usize]] + value as usize;
+            value = if d.flags & FLAG_WIDE != 0 {
+                u16_le(&self.bytes, self.map + 2 * i) as i32
+            } else {
+                byte(&self.bytes, self.map + i) as i32
+            };
+        }
+        let in_moves = match wdl {
+            Wdl::Win => d.flags & FLAG_WIN_PLIES == 0,
+            Wdl::Loss => d.flags & FLAG_LOSS_PLIES == 0,
+            _ => true,
+        };
+        if in_moves {
+            value *= 2;
+        }
+        value + 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parsing::parse_fen;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A subtable to write: every position has the same value, or the
+    /// values are stored with 3 bit codes.
+    enum Side {
+        Single { flags: u8, value: u8 },
+        Coded(Vec<u8>),
+    }
+
+    const BLOCK_BITS: u8 = 6;
+    const SPAN_BITS: u8 = 7;
+    const VALUES_PER_BLOCK: usize = (8 << BLOCK_BITS) / 3;
+
+    /// Write a table for a pawnless endgame with three different pieces,
+    /// like KQvK, in the Syzygy format.
+    fn write_table(magic: [u8; 4], pieces: [u8; 3], sides: &[Side]) -> Vec<u8> {
+        let mut bytes = magic.to_vec();
+        bytes.push(if sides.len() == 2 { 1 } else { 0 });
+        // The leading group is encoded first on both sides.
+        bytes.push(0);
+        for piece in &pieces {
+            bytes.push(piece | piece << 4);
+        }
+        if bytes.len() % 2 == 1 {
+            bytes.push(0);
+        }
+
+        let blocks = |values: &[u8]| values.len().div_ceil(VALUES_PER_BLOCK);
+        for side in sides {
+            match side {
+                Side::Single { flags, value } => bytes.extend(&[FLAG_SINGLE_VALUE | flags, *value]),
+                Side::Coded(values) => {
+                    bytes.extend(&[0, BLOCK_BITS, SPAN_BITS, 2]);
+                    bytes.extend(&(blocks(values) as u32).to_le_bytes());
+                    // All codes 3 bits long, for symbols 0-7, each a value.
+                    bytes.extend(&[3, 3, 0, 0, 8, 0]);
+                    for symbol in 0..8u8 {
+                        bytes.extend(&[symbol, 0xf0, 0xff]);
+                    }
+                }
+            }
+        }
+        for side in sides {
+            if let Side::Coded(values) = side {
+                let span = 1 << SPAN_BITS;
+                for k in 0..values.len().div_ceil(span) {
+                    let middle = k * span + span / 2;
+                    bytes.extend(&((middle / VALUES_PER_BLOCK) as u32).to_le_bytes());
+                    bytes.extend(&((middle % VALUES_PER_BLOCK) as u16).to_le_bytes());
+                }
+            }
+        }
+        for side in sides {
+            if let Side::Coded(values) = side {
+                for _ in 0..blocks(values) + 2 {
+                    bytes.extend(&(VALUES_PER_BLOCK as u16 - 1).to_le_bytes());
+                }
+            }
+        }
+        for side in sides {
+            if let Side::Coded(values) = side {
+                for block in values.chunks(VALUES_PER_BLOCK) {
+                    bytes.resize(bytes.len().div_ceil(64) * 64, 0);
+                    let start = bytes.len();
+                    bytes.resize(start + (1 << BLOCK_BITS), 0);
+                    for (i, value) in block.iter().enumerate() {
+                        for bit in 0..3 {
+                            if value & (4 >> bit) != 0 {
+                                let at = 3 * i + bit;
+                                bytes[start + at / 8] |= 0x80 >> (at % 8);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        // Real tables end with a checksum.
+        bytes.resize(bytes.len().div_ceil(64) * 64 + 16, 0);
+        bytes
+    }
+
+    const KQVK: [u8; 3] = [6, 5, 14];
+
+    #[test]
+    fn test_indices() {
+        let indices = indices();
+        assert_eq!(indices.binomial[2][5], 10);
+        assert_eq!(indices.binomial[5][48], 1_712_304);
+        let kings = indices.map_kk.iter().flatten().max().unwrap();
+        assert_eq!(kings + 1, 462);
+        assert_eq!(indices.lead_pawns_size[1], [6, 6, 6, 6]);
+        assert_eq!(indices.map_pawns[8], 47);
+        assert_eq!(indices.map_pawns[15], 46);
+        assert_eq!(indices.map_a1d1d4[0], 6);
+        assert_eq!(indices.map_a1d1d4[27], 9);
+    }
+
+    #[test]
+    fn test_symmetric_positions_share_an_index() {
+        let material = Material::parse("KQvK").unwrap();
+        let table = Table {
+            bytes: vec![],
+            material: material.clone(),
+            items: vec![],
+            map: 0,
+        };
+        let mut d = PairsData {
+            pieces: [6, 5, 14, 0, 0, 0, 0],
+            ..PairsData::default()
+        };
+        d.set_groups(&material, [0, 0xf], 0);
+        assert_eq!(d.group_len[..2], [3, 0]);
+        assert_eq!(d.group_idx[1], 31332);
+
+        let symmetries: [fn(usize) -> usize; 4] =
+            [|s| s, |s| s ^ 7, |s| s ^ 56, |s| ((s >> 3) | (s << 3)) & 63];
+        for king in (0..64).step_by(5) {
+            for queen in 0..64 {
+                for other in 0..64 {
+                    let kings_apart =
+                        bitboard::king_attacks(bitboard::pos_at(king as u32)) & (1 << other) == 0;
+                    if queen == king || queen == other || other == king || !kings_apart {
+                        continue;
+                    }
+                    let indices: Vec<u64> = symmetries
+                        .iter()
+                        .map(|symmetry| {
+                            let mut squares = [symmetry(king), symmetry(queen), symmetry(other)];
+                            table.index(&d, &mut squares, 0)
+                        })
+                        .collect();
+                    assert!(indices[0] < 31332);
+                    assert!(indices.iter().all(|&index| index == indices[0]));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_decompress() {
+        let values: Vec<u8> = (0..31332u32).map(|i| (i * 7 % 5) as u8).collect();
+        let bytes = write_table(
+            WDL_MAGIC,
+            KQVK,
+            &[
+                Side::Coded(values.clone()),
+                Side::Single { flags: 0, value: 2 },
+            ],
+        );
+        let table = Table::parse(Kind::Wdl, Material::parse("KQvK").unwrap(), bytes).unwrap();
+        let d = table.get(0, 0);
+        for (index, value) in values.iter().enumerate() {
+            assert_eq!(d.decompress(&table.bytes, index as u64), *value as usize);
+        }
+        assert_eq!(table.get(1, 0).decompress(&table.bytes, 1234), 2);
+
+        let bytes = write_table(DTZ_MAGIC, KQVK, &[Side::Single { flags: 0, value: 2 }]);
+        assert!(Table::parse(Kind::Wdl, Material::parse("KQvK").unwrap(), bytes).is_err());
+    }
+
+    #[test]
+    fn test_dtz_in_moves_or_plies() {
+        let dtz = |flags: u8, value: i32, wdl: Wdl| {
+            let bytes = write_table(DTZ_MAGIC, KQVK, &[Side::Single { flags, value: 0 }]);
+            let table = Table::parse(Kind::Dtz, Material::parse("KQvK").unwrap(), bytes).unwrap();
+            table.map_score(0, value, wdl)
+        };
+        // 5 moves is 11 or 12 plies.
+        assert_eq!(dtz(0, 5, Wdl::Win), 11);
+        assert_eq!(dtz(0, 5, Wdl::Loss), 11);
+        // Tables with wins or losses near the fifty-move limit count plies.
+        assert_eq!(dtz(FLAG_WIN_PLIES, 11, Wdl::Win), 12);
+        assert_eq!(dtz(FLAG_WIN_PLIES, 5, Wdl::Loss), 11);
+        assert_eq!(dtz(FLAG_LOSS_PLIES, 11, Wdl::Loss), 12);
+        // Cursed wins and blessed losses are always in moves.
+        assert_eq!(dtz(FLAG_WIN_PLIES | FLAG_LOSS_PLIES, 5, Wdl::CursedWin), 11);
+        assert_eq!(
+            dtz(FLAG_WIN_PLIES | FLAG_LOSS_PLIES, 5, Wdl::BlessedLoss),
+            11
+        );
+    }
+
+    /// A directory with a made up KQvK table: every position with white to
+    /// move is won in 11 plies, and with black to move, lost.
+    fn tablebase() -> Tablebase {
+        static DIRS: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "syzygy-{}-{}",
+            std::process::id(),
+            DIRS.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let wdl = write_table(
+            WDL_MAGIC,
+            KQVK,
+            &[
+                Side::Single { flags: 0, value: 4 },
+                Side::Single { flags: 0, value: 0 },
+            ],
+        );
+        fs::write(dir.join("KQvK.rtbw"), wdl).unwrap();
+        let dtz = write_table(DTZ_MAGIC, KQVK, &[Side::Single { flags: 0, value: 5 }]);
+        fs::write(dir.join("KQvK.rtbz"), dtz).unwrap();
+        let tablebase = Tablebase::open(&dir).unwrap();
+        tablebase.table("KQvK", Kind::Wdl).unwrap();
+        tablebase.table("KQvK", Kind::Dtz).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        tablebase
+    }
+
+    #[test]
+    fn test_probe() {
+        let tablebase = tablebase();
+        assert_eq!(tablebase.max_pieces(), 3);
+        let probe = |fen: &str| {
+            let state = parse_fen(fen).unwrap();
+            (
+                tablebase.probe_wdl(&state).unwrap(),
+                tablebase.probe_dtz(&state).unwrap(),
+            )
+        };
+        assert_eq!(probe("8/8/8/8/8/2k5/8/KQ6 w - - 0 1"), (Wdl::Win, 11));
+        // Black to move isn't in the DTZ table, so it's found a ply later.
+        assert_eq!(probe("8/8/8/8/8/2k5/8/KQ6 b - - 0 1"), (Wdl::Loss, -12));
+        // Black can take the queen.
+        assert_eq!(probe("8/8/8/8/8/2kQ4/8/K7 b - - 0 1"), (Wdl::Draw, 0));
+        // With colors reversed.
+        assert_eq!(probe("kq6/8/8/8/8/8/8/7K b - - 0 1"), (Wdl::Win, 11));
+        assert_eq!(probe("kq6/8/8/8/8/8/8/7K w - - 0 1"), (Wdl::Loss, -12));
+        // Bare kings need no table.
+        assert_eq!(probe("k7/8/8/8/8/8/8/7K w - - 0 1"), (Wdl::Draw, 0));
+    }
+
+    #[test]
+    fn test_best_moves() {
+        let tablebase = tablebase();
+        let state = parse_fen("8/8/8/8/8/2kQ4/8/K7 w - - 0 1").unwrap();
+        let moves = tablebase.probe_moves(&state).unwrap();
+        assert_eq!(moves.len(), state.gen_moves().len());
+        // Leaving the queen en prise draws.
+        let hanging = moves
+            .iter()
+            .find(|(m0ve, _)| m0ve.to_coordinate(&state) == "d3d4")
+            .unwrap();
+        assert_eq!(hanging.1, 0);
+        let best = tablebase.best_moves(&state).unwrap();
+        assert!(!best.is_empty());
+        assert!(best.iter().all(|(_, dtz)| *dtz == 13));
+    }
+
+    #[test]
+    fn test_errors() {
+        let tablebase = tablebase();
+        let missing = parse_fen("8/8/8/8/8/2k5/8/KR6 w - - 0 1").unwrap();
+        assert_eq!(
+            tablebase.probe_wdl(&missing),
+            Err("no table for KRvK".to_string())
+        );
+        let castling = parse_fen("4k3/8/8/8/8/8/8/3QK2R w K - 0 1").unwrap();
+        assert!(tablebase.probe_wdl(&castling).is_err());
+        assert!(Tablebase::open("/nonexistent/syzygy").is_err());
+        assert!(Material::parse("KQvKvK").is_err());
+        assert!(Material::parse("QKvK").is_err());
+    }
+}
@@ -25,7 +25,7 @@ impl Iterator for FromToStep {
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let steps_remaining = ((self.to - self.from) / self.step).abs() as usize - 1;
+        let steps_remaining = ((self.to - self.from) / self.step).unsigned_abs() as usize - 1;
         (steps_remaining, Some(steps_remaining))
     }
 }
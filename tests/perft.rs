@@ -0,0 +1,219 @@
+//! Perft node counts for the standard test positions, see
+//! https://www.chessprogramming.org/Perft_Results, and some Chess960 ones
+//! from https://www.chessprogramming.org/Chess960_Perft_Results, and
+//! crazyhouse, atomic and antichess ones as counted by Fairy-Stockfish.
+//!
+//! Depths are kept low enough to run quickly in debug builds; use
+//! `chess perft --depth N --fen ...` to go deeper.
+
+use chess::game::antichess::Antichess;
+use chess::game::atomic::Atomic;
+use chess::game::king_of_the_hill::KingOfTheHill;
+use chess::game::three_check::ThreeCheck;
+use chess::game::variant::Variant;
+use chess::parsing::parse_fen;
+
+fn perft(fen: &str, expected: &[u64]) {
+    let state = parse_fen(fen).unwrap();
+    for (depth, &nodes) in expected.iter().enumerate() {
+        let depth = depth as u32 + 1;
+        assert_eq!(state.perft(depth), nodes, "{} at depth {}", fen, depth);
+    }
+}
+
+#[test]
+fn perft_initial() {
+    perft(
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        &[20, 400, 8_902, 197_281],
+    );
+}
+
+#[test]
+fn perft_kiwipete() {
+    perft(
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        &[48, 2_039, 97_862],
+    );
+}
+
+#[test]
+fn perft_position_3() {
+    perft(
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        &[14, 191, 2_812, 43_238],
+    );
+}
+
+#[test]
+fn perft_position_4() {
+    perft(
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        &[6, 264, 9_467],
+    );
+}
+
+#[test]
+fn perft_position_4_mirrored() {
+    perft(
+        "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+        &[6, 264, 9_467],
+    );
+}
+
+#[test]
+fn perft_position_5() {
+    perft(
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        &[44, 1_486, 62_379],
+    );
+}
+
+#[test]
+fn perft_position_6() {
+    perft(
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        &[46, 2_079, 89_890],
+    );
+}
+
+#[test]
+fn perft_chess960_1() {
+    perft(
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        &[21, 528, 12_189],
+    );
+}
+
+#[test]
+fn perft_chess960_2() {
+    perft(
+        "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+        &[21, 807, 18_002],
+    );
+}
+
+#[test]
+fn perft_chess960_3() {
+    perft(
+        "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+        &[20, 479, 10_471],
+    );
+}
+
+#[test]
+fn perft_chess960_4() {
+    perft(
+        "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9",
+        &[29, 502, 14_569],
+    );
+}
+
+#[test]
+fn perft_crazyhouse_initial() {
+    perft(
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+        &[20, 400, 8_902, 197_281],
+    );
+}
+
+#[test]
+fn perft_crazyhouse_full_pockets() {
+    perft("2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1", &[301, 75_353]);
+}
+
+/// Like `perft`, under the rules of `variant`.
+fn variant_perft<V: Variant>(variant: V, fen: &str, expected: &[u64]) {
+    let state = parse_fen(fen).unwrap();
+    for (depth, &nodes) in expected.iter().enumerate() {
+        let depth = depth as u32 + 1;
+        assert_eq!(
+            variant.perft(&state, depth),
+            nodes,
+            "{} at depth {}",
+            fen,
+            depth
+        );
+    }
+}
+
+#[test]
+fn perft_atomic_initial() {
+    variant_perft(
+        Atomic,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        &[20, 400, 8_902],
+    );
+}
+
+#[test]
+fn perft_atomic_1() {
+    variant_perft(
+        Atomic,
+        "rn2kb1r/1pp1p2p/p2q1pp1/3P4/2P3b1/4PN2/PP3PPP/R2QKB1R b KQkq - 0 1",
+        &[40, 1_238, 45_237],
+    );
+}
+
+#[test]
+fn perft_atomic_2() {
+    variant_perft(
+        Atomic,
+        "rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1",
+        &[28, 833, 23_353],
+    );
+}
+
+#[test]
+fn perft_atomic_3() {
+    variant_perft(
+        Atomic,
+        "r4b1r/2kb1N2/p2Bpnp1/8/2Pp3p/1P1PPP2/P5PP/R3K2R b KQ - 0 1",
+        &[4, 148],
+    );
+}
+
+#[test]
+fn perft_atomic_castling() {
+    variant_perft(
+        Atomic,
+        "8/8/8/8/8/8/2k5/rR4KR w KQ - 0 1",
+        &[18, 180, 4_364, 61_401],
+    );
+}
+
+#[test]
+fn perft_atomic_rook_endgame() {
+    variant_perft(
+        Atomic,
+        "1R4kr/4K3/8/8/8/8/8/8 b k - 0 1",
+        &[4, 77, 1_021, 17_915],
+    );
+}
+
+#[test]
+fn perft_antichess_initial() {
+    variant_perft(
+        Antichess,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+        &[20, 400, 8_067, 153_299],
+    );
+}
+
+#[test]
+fn perft_three_check_initial() {
+    variant_perft(
+        ThreeCheck,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 +0+0",
+        &[20, 400, 8_902, 197_281],
+    );
+}
+
+#[test]
+fn perft_king_of_the_hill_initial() {
+    variant_perft(
+        KingOfTheHill,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        &[20, 400, 8_902, 197_281],
+    );
+}
@@ -0,0 +1,51 @@
+//! Probes of real Syzygy tables, from e.g.
+//! https://tablebase.lichess.ovh/tables/standard/3-4-5/, for positions whose
+//! result is certain: mates, immediate promotions, a hanging queen and a rook
+//! pawn the defending king blocks.
+//!
+//! The tables belong in tests/syzygy: KQvK, KRvK and KPvK, both `.rtbw` and
+//! `.rtbz`. Until they're checked in, run this with
+//! `cargo test --test syzygy -- --ignored`.
+
+use chess::parsing::parse_fen;
+use chess::tablebase::{Tablebase, Wdl};
+use std::path::Path;
+
+#[test]
+#[ignore = "needs the KQvK, KRvK and KPvK tables in tests/syzygy"]
+fn syzygy_three_pieces() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/syzygy");
+    for table in &["KQvK", "KRvK", "KPvK"] {
+        for extension in &["rtbw", "rtbz"] {
+            let path = dir.join(format!("{}.{}", table, extension));
+            assert!(path.is_file(), "missing {}", path.display());
+        }
+    }
+    let tablebase = Tablebase::open(&dir).unwrap();
+    // The DTZ is only checked where it's exact.
+    let positions = [
+        // KQvK
+        ("8/8/8/8/8/2k5/8/KQ6 w - - 0 1", Wdl::Win, None),
+        ("8/8/8/8/8/2k5/8/KQ6 b - - 0 1", Wdl::Loss, None),
+        ("k7/8/1K6/8/8/8/7Q/8 w - - 0 1", Wdl::Win, Some(1)),
+        ("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1", Wdl::Loss, Some(-1)),
+        ("8/8/8/8/8/2kQ4/8/K7 b - - 0 1", Wdl::Draw, Some(0)),
+        ("kq6/8/8/8/8/8/8/7K b - - 0 1", Wdl::Win, None),
+        // KRvK
+        ("8/8/8/8/8/2k5/8/KR6 w - - 0 1", Wdl::Win, None),
+        ("k7/8/1K6/8/8/8/8/7R w - - 0 1", Wdl::Win, Some(1)),
+        ("R1k5/8/2K5/8/8/8/8/8 b - - 0 1", Wdl::Loss, Some(-1)),
+        ("8/8/8/8/8/2kR4/8/K7 b - - 0 1", Wdl::Draw, Some(0)),
+        // KPvK
+        ("8/4P3/8/8/8/8/k7/4K3 w - - 0 1", Wdl::Win, Some(1)),
+        ("k7/8/K7/P7/8/8/8/8 b - - 0 1", Wdl::Draw, Some(0)),
+        ("k7/8/K7/P7/8/8/8/8 w - - 0 1", Wdl::Draw, Some(0)),
+    ];
+    for (fen, wdl, dtz) in &positions {
+        let state = parse_fen(fen).unwrap();
+        assert_eq!(tablebase.probe_wdl(&state), Ok(*wdl), "{}", fen);
+        if let Some(dtz) = dtz {
+            assert_eq!(tablebase.probe_dtz(&state), Ok(*dtz), "{}", fen);
+        }
+    }
+}